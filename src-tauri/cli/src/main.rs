@@ -0,0 +1,503 @@
+//! A command-line interface to the same encoding and decoding that the app does.
+//!
+//! Any input or output path may be `-` to use stdin or stdout instead, which are staged in a
+//! temporary directory of their own. A secret file read from stdin is hidden under the name
+//! given with `--name`, or `secret` without one. Results are printed as
+//! text, or with `--json` in the same shape the app receives them:
+//! `{"Ok": ...}` on success and `{"Err": ...}` on failure. When the output file is written to
+//! stdout, the JSON result is printed to stderr instead.
+
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use stegosaurusography_core::{
+    get_properties, inspect, list_entries, output_context, secret_context, AlphaMode, Compression,
+    DecodeOptions, Decoder, EncodeOptions, Encoder, ErrorType, Redundancy, Result,
+    DEFAULT_BITS_PER_CHANNEL,
+};
+use tempfile::TempDir;
+
+/// Hide files inside of images and audio, and find them again.
+#[derive(Parser)]
+#[command(name = "stego", version, after_help = EXIT_CODES)]
+struct Cli {
+    /// Print results as JSON instead of text.
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Describes the exit codes in the help text, which match `exit_code`.
+const EXIT_CODES: &str = "Exit codes:
+  0  Success
+  2  Invalid arguments
+  3  Couldn't read or write a file
+  4  Unsupported file type
+  5  File contents don't match the extension
+  6  Base file not big enough
+  7  Unsupported bits per channel
+  8  The same file was given more than once
+  9  Uninterpretable image
+  10 Uninterpretable audio
+  11 No payload, or a corrupted one
+  12 Password required
+  13 Wrong password
+  14 Cancelled";
+
+#[derive(Subcommand)]
+enum Command {
+    /// Hide a secret file, or several files and directories, inside of a base file.
+    Encode {
+        /// The file to hide the secret file in.
+        base_file: PathBuf,
+        /// The files and directories to hide. Anything but a single file is hidden as an archive.
+        #[arg(required = true, num_args = 1..)]
+        secret_files: Vec<PathBuf>,
+        /// Where to write the base file with the secret file hidden in it.
+        output_file: PathBuf,
+        /// The name to hide a secret file read from stdin under.
+        #[arg(long, default_value = "secret", value_parser = parse_file_name)]
+        name: String,
+        #[command(flatten)]
+        secrets: Secrets,
+        /// How many of the lowest bits of each channel to store the secret file in.
+        #[arg(short, long, default_value_t = DEFAULT_BITS_PER_CHANNEL)]
+        bits_per_channel: u8,
+        /// Leave out metadata that could identify the base file, such as text and timestamps.
+        #[arg(long)]
+        strip_metadata: bool,
+        /// Which channels and pixels of an image with an alpha channel to use.
+        #[arg(long, value_enum, default_value_t = AlphaArg::ColourOnly)]
+        alpha: AlphaArg,
+        /// Compress the secret file before hiding it, if that makes it smaller.
+        #[arg(long, value_enum, default_value_t = CompressionArg::None)]
+        compression: CompressionArg,
+        /// Store error correction with the secret file, so it survives some damage to the
+        /// output.
+        #[arg(long, value_enum, default_value_t = RedundancyArg::None)]
+        redundancy: RedundancyArg,
+    },
+    /// Recover a secret file from an encoded file.
+    Decode {
+        /// The file with a secret file hidden in it.
+        encoded_file: PathBuf,
+        /// Where to write the secret file. If this is a directory, the secret file's original
+        /// name is used. Several secret files are unpacked into this as a directory.
+        output_file: PathBuf,
+        #[command(flatten)]
+        secrets: Secrets,
+        /// Write out whatever can be recovered of a damaged secret file, while still failing.
+        #[arg(long)]
+        best_effort: bool,
+    },
+    /// Describe the secret file hidden in an encoded file, without recovering it.
+    Info {
+        /// The file with a secret file hidden in it.
+        encoded_file: PathBuf,
+        #[command(flatten)]
+        secrets: Secrets,
+    },
+    /// List the files and directories hidden in an encoded file, without recovering them.
+    List {
+        /// The file with secret files hidden in it.
+        encoded_file: PathBuf,
+        #[command(flatten)]
+        secrets: Secrets,
+    },
+    /// Show how large a secret file can be hidden in a base file.
+    Capacity {
+        /// The file that would hide the secret file.
+        base_file: PathBuf,
+        /// How many of the lowest bits of each channel would be used.
+        #[arg(short, long, default_value_t = DEFAULT_BITS_PER_CHANNEL)]
+        bits_per_channel: u8,
+        /// Which channels and pixels of an image with an alpha channel would be used.
+        #[arg(long, value_enum, default_value_t = AlphaArg::ColourOnly)]
+        alpha: AlphaArg,
+        /// How much error correction would be stored with the secret file.
+        #[arg(long, value_enum, default_value_t = RedundancyArg::None)]
+        redundancy: RedundancyArg,
+    },
+}
+
+impl Command {
+    /// Whether the output file is written to stdout, which leaves no room for results.
+    fn writes_to_stdout(&self) -> bool {
+        match self {
+            Command::Encode { output_file, .. } | Command::Decode { output_file, .. } => {
+                output_file == Path::new("-")
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The alpha modes, as they are written on the command line.
+#[derive(Clone, Copy, ValueEnum)]
+enum AlphaArg {
+    /// Only the colour channels, in every pixel.
+    ColourOnly,
+    /// The alpha channel as well, if every pixel is fully opaque.
+    IncludeAlpha,
+    /// Every pixel that isn't fully transparent.
+    SkipTransparent,
+    /// Only the fully transparent pixels.
+    OnlyTransparent,
+}
+
+/// The compression algorithms, as they are written on the command line.
+#[derive(Clone, Copy, ValueEnum)]
+enum CompressionArg {
+    /// Store the secret file as it is.
+    None,
+    /// Deflate, which is quick.
+    Deflate,
+    /// Zstandard, which usually compresses further.
+    Zstd,
+}
+
+impl From<CompressionArg> for Compression {
+    fn from(compression: CompressionArg) -> Compression {
+        match compression {
+            CompressionArg::None => Compression::None,
+            CompressionArg::Deflate => Compression::Deflate,
+            CompressionArg::Zstd => Compression::Zstd,
+        }
+    }
+}
+
+/// The levels of error correction, as they are written on the command line.
+#[derive(Clone, Copy, ValueEnum)]
+enum RedundancyArg {
+    /// No error correction.
+    None,
+    /// Corrects up to 8 damaged bytes in every 255.
+    Low,
+    /// Corrects up to 16 damaged bytes in every 255.
+    Medium,
+    /// Corrects up to 32 damaged bytes in every 255.
+    High,
+}
+
+impl From<RedundancyArg> for Redundancy {
+    fn from(redundancy: RedundancyArg) -> Redundancy {
+        match redundancy {
+            RedundancyArg::None => Redundancy::None,
+            RedundancyArg::Low => Redundancy::Low,
+            RedundancyArg::Medium => Redundancy::Medium,
+            RedundancyArg::High => Redundancy::High,
+        }
+    }
+}
+
+impl From<AlphaArg> for AlphaMode {
+    fn from(alpha: AlphaArg) -> AlphaMode {
+        match alpha {
+            AlphaArg::ColourOnly => AlphaMode::ColourOnly,
+            AlphaArg::IncludeAlpha => AlphaMode::IncludeAlpha,
+            AlphaArg::SkipTransparent => AlphaMode::SkipTransparent,
+            AlphaArg::OnlyTransparent => AlphaMode::OnlyTransparent,
+        }
+    }
+}
+
+/// The password and key a secret file is protected with.
+#[derive(Args)]
+struct Secrets {
+    /// Encrypt or decrypt the secret file with this password.
+    #[arg(short, long, env = "STEGO_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+    /// Scatter or gather the secret file across the carrier using this key.
+    #[arg(short, long, env = "STEGO_KEY", hide_env_values = true)]
+    key: Option<String>,
+}
+
+/// A directory holding stdin and stdout while they are used as files, removed when dropped.
+///
+/// The directory is given a unique random name, and is only created once stdin or stdout is
+/// used.
+struct Staging {
+    directory: RefCell<Option<TempDir>>,
+}
+
+impl Staging {
+    fn new() -> Staging {
+        Staging {
+            directory: RefCell::new(None),
+        }
+    }
+
+    /// Returns the path to a file in the staging directory, creating the directory if needed.
+    fn staged(&self, name: &str) -> io::Result<PathBuf> {
+        let mut directory = self.directory.borrow_mut();
+        let directory = match &mut *directory {
+            Some(directory) => directory,
+            None => directory.insert(tempfile::Builder::new().prefix("stego-").tempdir()?),
+        };
+
+        Ok(directory.path().join(name))
+    }
+
+    /// Returns the path to use for an input, copying stdin to a file if the path is `-`.
+    ///
+    /// The file is named after the role the input plays, as some carriers record the name.
+    fn input(&self, path: &Path, name: &str) -> io::Result<PathBuf> {
+        if path != Path::new("-") {
+            return Ok(path.to_path_buf());
+        }
+
+        let staged = self.staged(name)?;
+        io::copy(&mut io::stdin().lock(), &mut File::create(&staged)?)?;
+        Ok(staged)
+    }
+
+    /// Returns the path to write an output to, which is a staged file if the path is `-`.
+    fn output(&self, path: &Path) -> io::Result<PathBuf> {
+        if path == Path::new("-") {
+            self.staged("output")
+        } else {
+            Ok(path.to_path_buf())
+        }
+    }
+
+    /// Copies a staged output to stdout if the output path was `-`.
+    fn finish_output(&self, path: &Path, written_to: &Path) -> io::Result<()> {
+        if path != Path::new("-") {
+            return Ok(());
+        }
+
+        let mut stdout = io::stdout().lock();
+        io::copy(&mut File::open(written_to)?, &mut stdout)?;
+        stdout.flush()
+    }
+}
+
+/// The result of a command, in a form that can be printed as text or JSON.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Output {
+    Nothing,
+    Path(PathBuf),
+    Json(serde_json::Value),
+}
+
+fn run(command: Command) -> Result<Output> {
+    let staging = Staging::new();
+
+    match command {
+        Command::Encode {
+            base_file,
+            secret_files,
+            output_file,
+            name,
+            secrets,
+            bits_per_channel,
+            strip_metadata,
+            alpha,
+            compression,
+            redundancy,
+        } => {
+            let inputs: Vec<&Path> = [&base_file]
+                .into_iter()
+                .chain(&secret_files)
+                .map(PathBuf::as_path)
+                .collect();
+            check_single_stdin(&inputs)?;
+            let base_path = secret_context!(staging.input(&base_file, "base"))?;
+            let secret_paths = secret_files
+                .iter()
+                .map(|secret_file| secret_context!(staging.input(secret_file, &name)))
+                .collect::<Result<Vec<_>>>()?;
+            let output_path = output_context!(staging.output(&output_file))?;
+
+            let options = EncodeOptions {
+                password: secrets.password,
+                key: secrets.key,
+                bits_per_channel,
+                strip_metadata,
+                alpha: alpha.into(),
+                compression: compression.into(),
+                redundancy: redundancy.into(),
+            };
+            Encoder::with_secrets(base_path, secret_paths, &output_path, options)?.encode()?;
+
+            output_context!(staging.finish_output(&output_file, &output_path))?;
+            Ok(Output::Nothing)
+        }
+        Command::Decode {
+            encoded_file,
+            output_file,
+            secrets,
+            best_effort,
+        } => {
+            let encoded_path = secret_context!(staging.input(&encoded_file, "encoded"))?;
+            let output_path = output_context!(staging.output(&output_file))?;
+
+            let options = DecodeOptions {
+                password: secrets.password,
+                key: secrets.key,
+                best_effort,
+            };
+            let mut decoder = Decoder::with_options(encoded_path, &output_path, options)?;
+            let decoded = decoder.decode();
+            if output_file == Path::new("-") && decoder.output_file_path().is_dir() {
+                return output_context!(Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Several secret files can't be written to stdout",
+                )));
+            }
+
+            // A best effort recovery writes out a damaged secret file before failing
+            if decoded.is_ok() || decoder.output_file_path().exists() {
+                output_context!(staging.finish_output(&output_file, decoder.output_file_path()))?;
+            }
+            decoded?;
+            if output_file == Path::new("-") {
+                Ok(Output::Nothing)
+            } else {
+                Ok(Output::Path(decoder.output_file_path().to_path_buf()))
+            }
+        }
+        Command::Info {
+            encoded_file,
+            secrets,
+        } => {
+            let encoded_path = secret_context!(staging.input(&encoded_file, "encoded"))?;
+            let options = DecodeOptions {
+                password: secrets.password,
+                key: secrets.key,
+                ..Default::default()
+            };
+
+            Ok(Output::Json(to_json(&inspect(encoded_path, &options)?)))
+        }
+        Command::List {
+            encoded_file,
+            secrets,
+        } => {
+            let encoded_path = secret_context!(staging.input(&encoded_file, "encoded"))?;
+            let options = DecodeOptions {
+                password: secrets.password,
+                key: secrets.key,
+                ..Default::default()
+            };
+
+            Ok(Output::Json(to_json(&list_entries(
+                encoded_path,
+                &options,
+            )?)))
+        }
+        Command::Capacity {
+            base_file,
+            bits_per_channel,
+            alpha,
+            redundancy,
+        } => {
+            let base_path = secret_context!(staging.input(&base_file, "base"))?;
+            let options = EncodeOptions {
+                bits_per_channel,
+                alpha: alpha.into(),
+                redundancy: redundancy.into(),
+                ..Default::default()
+            };
+
+            Ok(Output::Json(to_json(&get_properties(base_path, &options)?)))
+        }
+    }
+}
+
+/// Only accepts a plain file name, which can't lead outside of the staging directory.
+fn parse_file_name(name: &str) -> std::result::Result<String, String> {
+    if Path::new(name).file_name() == Some(name.as_ref()) {
+        Ok(name.to_owned())
+    } else {
+        Err(format!("{name:?} isn't a file name"))
+    }
+}
+
+/// Stdin can only be read once, so only one input may use it.
+fn check_single_stdin(paths: &[&Path]) -> Result<()> {
+    let stdin_count = paths.iter().filter(|path| **path == Path::new("-")).count();
+    if stdin_count > 1 {
+        return secret_context!(Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Only one input can be read from stdin",
+        )));
+    }
+
+    Ok(())
+}
+
+fn to_json(value: &impl Serialize) -> serde_json::Value {
+    serde_json::to_value(value).expect("Our results serialize to JSON")
+}
+
+/// The exit code for each type of error, so scripts can tell failures apart.
+fn exit_code(error_type: &ErrorType) -> u8 {
+    match error_type {
+        ErrorType::IOError(_) => 3,
+        ErrorType::UnsupportedFileType(_) | ErrorType::UnregisteredFileType(_) => 4,
+        ErrorType::FileTypeMismatch { .. } => 5,
+        ErrorType::BaseFileNotBigEnough { .. } => 6,
+        ErrorType::UnsupportedBitsPerChannel(_) => 7,
+        ErrorType::DuplicateFiles(_) => 8,
+        ErrorType::ImageError(_) => 9,
+        ErrorType::AudioError(_) => 10,
+        ErrorType::CorruptedFile(_) => 11,
+        ErrorType::PasswordRequired => 12,
+        ErrorType::WrongPassword => 13,
+        ErrorType::Cancelled => 14,
+    }
+}
+
+/// Prints a JSON result, to stderr if stdout holds the output file instead.
+fn print_json(value: serde_json::Value, to_stderr: bool) {
+    if to_stderr {
+        eprintln!("{value}");
+    } else {
+        println!("{value}");
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let to_stderr = cli.command.writes_to_stdout();
+
+    match run(cli.command) {
+        Ok(output) => {
+            if cli.json {
+                print_json(serde_json::json!({ "Ok": output }), to_stderr);
+            } else {
+                match output {
+                    Output::Nothing => {}
+                    Output::Path(path) => println!("{}", path.display()),
+                    Output::Json(value) => {
+                        let pretty = serde_json::to_string_pretty(&value)
+                            .expect("Our results serialize to JSON");
+                        println!("{pretty}");
+                    }
+                }
+            }
+
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            if cli.json {
+                print_json(serde_json::json!({ "Err": error }), to_stderr);
+            } else {
+                eprintln!("Error with {:?}: {:?}", error.contexts, error.error_type);
+            }
+
+            ExitCode::from(exit_code(&error.error_type))
+        }
+    }
+}
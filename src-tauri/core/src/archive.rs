@@ -0,0 +1,446 @@
+use std::{
+    fs::{self, Metadata, OpenOptions},
+    io::{self, ErrorKind, Write},
+    path::{Component, Path},
+};
+
+use crate::CorruptionType;
+
+/// Whether an entry of an archive is a file or a directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum EntryKind {
+    File = 0,
+    Directory = 1,
+}
+
+/// A file or directory hidden in an archive, described without its contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ArchiveEntry {
+    /// The entry's path inside the archive, with its components separated by `/`. The first
+    /// component is the name of the file or directory that was given to be hidden.
+    pub path: String,
+    pub kind: EntryKind,
+    /// The number of bytes in the file, which is 0 for directories.
+    pub size: u64,
+    /// The entry's Unix permission bits, if they were known when it was packed.
+    pub mode: Option<u32>,
+}
+
+/// Several files and directories packed into the contents of a single payload.
+///
+/// Serialized as the number of entries, followed by each entry's length-prefixed path, kind,
+/// optional permissions, size and contents.
+pub struct Archive<'a> {
+    entries: Vec<(ArchiveEntry, &'a [u8])>,
+}
+
+impl<'a> Archive<'a> {
+    /// Reads an archive, checking that every path in it stays inside of the directory it's
+    /// unpacked into.
+    pub fn read(bytes: &'a [u8]) -> Result<Archive<'a>, CorruptionType> {
+        let mut remaining = bytes;
+        let count = u32::from_be_bytes(take_array(&mut remaining)?);
+
+        let mut entries = vec![];
+        for _ in 0..count {
+            let path_length = u16::from_be_bytes(take_array(&mut remaining)?) as usize;
+            let path = std::str::from_utf8(take(&mut remaining, path_length)?)
+                .map_err(|_| CorruptionType::IncorrectArchive)?
+                .to_owned();
+            if relative_path(&path).is_none() {
+                log::warn!("The archive contains the unsafe path {path:?}");
+                return Err(CorruptionType::UnsafeArchivePath(path));
+            }
+
+            let [kind, has_mode] = take_array(&mut remaining)?;
+            let kind = match kind {
+                0 => EntryKind::File,
+                1 => EntryKind::Directory,
+                _ => return Err(CorruptionType::IncorrectArchive),
+            };
+            let mode = u32::from_be_bytes(take_array(&mut remaining)?);
+            let size = u64::from_be_bytes(take_array(&mut remaining)?);
+            if kind == EntryKind::Directory && size != 0 {
+                return Err(CorruptionType::IncorrectArchive);
+            }
+            let size_in_memory =
+                usize::try_from(size).map_err(|_| CorruptionType::IncorrectArchive)?;
+            let contents = take(&mut remaining, size_in_memory)?;
+
+            let entry = ArchiveEntry {
+                path,
+                kind,
+                size,
+                mode: (has_mode != 0).then_some(mode),
+            };
+            entries.push((entry, contents));
+        }
+
+        if !remaining.is_empty() {
+            log::debug!(
+                "The archive had {} bytes after its last entry",
+                remaining.len()
+            );
+            return Err(CorruptionType::IncorrectArchive);
+        }
+
+        Ok(Archive { entries })
+    }
+
+    /// The entries of the archive, in the order they were packed.
+    pub fn entries(&self) -> Vec<ArchiveEntry> {
+        self.entries
+            .iter()
+            .map(|(entry, _)| entry.clone())
+            .collect()
+    }
+
+    /// Unpacks the archive into the directory, creating it if it doesn't exist.
+    ///
+    /// Existing files are never overwritten, and existing symbolic links are never followed, so
+    /// nothing outside of the directory can be written to.
+    pub fn unpack(&self, directory: &Path) -> io::Result<()> {
+        fs::create_dir_all(directory)?;
+
+        let mut directories = vec![];
+        for (entry, contents) in &self.entries {
+            let components = relative_path(&entry.path).expect("Paths are checked when read");
+
+            // Creating the parents one at a time, as create_dir_all would follow symbolic links
+            let mut path = directory.to_path_buf();
+            for component in &components[..components.len() - 1] {
+                path.push(component);
+                create_directory(&path)?;
+            }
+            path.push(components[components.len() - 1]);
+
+            match entry.kind {
+                EntryKind::Directory => {
+                    create_directory(&path)?;
+                    directories.push((path, entry.mode));
+                }
+                EntryKind::File => {
+                    OpenOptions::new()
+                        .write(true)
+                        .create_new(true)
+                        .open(&path)?
+                        .write_all(contents)?;
+                    set_mode(&path, entry.mode)?;
+                }
+            }
+        }
+
+        // Directories get their permissions last, as a read-only directory couldn't be filled
+        for (path, mode) in directories.iter().rev() {
+            set_mode(path, *mode)?;
+        }
+        log::trace!("Unpacked {} entries into {directory:?}", self.entries.len());
+
+        Ok(())
+    }
+}
+
+/// Packs the files and directories into an archive, along with everything inside of the
+/// directories.
+///
+/// Each path is stored under its own name, so two with the same name can't be packed together.
+/// Symbolic links inside of the directories are skipped, as they could point anywhere once
+/// unpacked.
+pub fn pack(paths: &[impl AsRef<Path>]) -> io::Result<Vec<u8>> {
+    let mut entries = vec![];
+    for path in paths {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| invalid_input(format!("{path:?} has no name that can be stored")))?;
+        if entries
+            .iter()
+            .any(|(entry, _): &(ArchiveEntry, Vec<u8>)| entry.path == name)
+        {
+            return Err(invalid_input(format!(
+                "More than one of the secret files is named {name:?}"
+            )));
+        }
+
+        collect(path, name.to_owned(), &fs::metadata(path)?, &mut entries)?;
+    }
+    log::trace!("Packed {} entries into an archive", entries.len());
+
+    let mut bytes = (entries.len() as u32).to_be_bytes().to_vec();
+    for (entry, contents) in &entries {
+        let path = entry.path.as_bytes();
+        let path_length = u16::try_from(path.len())
+            .map_err(|_| invalid_input(format!("The path {:?} is too long", entry.path)))?;
+        bytes.extend(path_length.to_be_bytes());
+        bytes.extend(path);
+        bytes.push(entry.kind as u8);
+        bytes.push(entry.mode.is_some() as u8);
+        bytes.extend(entry.mode.unwrap_or_default().to_be_bytes());
+        bytes.extend(entry.size.to_be_bytes());
+        bytes.extend(contents);
+    }
+
+    Ok(bytes)
+}
+
+/// Adds the file or directory at the path to the entries, under the given path in the archive.
+fn collect(
+    path: &Path,
+    archive_path: String,
+    metadata: &Metadata,
+    entries: &mut Vec<(ArchiveEntry, Vec<u8>)>,
+) -> io::Result<()> {
+    if metadata.is_file() {
+        let contents = fs::read(path)?;
+        let entry = ArchiveEntry {
+            path: archive_path,
+            kind: EntryKind::File,
+            size: contents.len() as u64,
+            mode: mode_of(metadata),
+        };
+        entries.push((entry, contents));
+        return Ok(());
+    }
+    if !metadata.is_dir() {
+        log::warn!("Skipping {path:?}, which is neither a file nor a directory");
+        return Ok(());
+    }
+
+    let entry = ArchiveEntry {
+        path: archive_path.clone(),
+        kind: EntryKind::Directory,
+        size: 0,
+        mode: mode_of(metadata),
+    };
+    entries.push((entry, vec![]));
+
+    // Sorting the children, so the same directory always packs the same way
+    let mut children = fs::read_dir(path)?.collect::<io::Result<Vec<_>>>()?;
+    children.sort_by_key(|child| child.file_name());
+    for child in children {
+        let name = child.file_name().into_string().map_err(|name| {
+            invalid_input(format!(
+                "{name:?} in {path:?} has no name that can be stored"
+            ))
+        })?;
+
+        // The metadata of a directory's children isn't read through symbolic links
+        let metadata = child.metadata()?;
+        if metadata.is_symlink() {
+            log::warn!("Skipping the symbolic link {:?}", child.path());
+            continue;
+        }
+        collect(
+            &child.path(),
+            format!("{archive_path}/{name}"),
+            &metadata,
+            entries,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Splits a path from an archive into its components, or returns None if any of them could
+/// lead outside of the directory it's unpacked into, such as `..` or a drive prefix.
+fn relative_path(path: &str) -> Option<Vec<&str>> {
+    let components: Vec<&str> = path.split('/').collect();
+    let safe = components.iter().all(|component| {
+        let mut parsed = Path::new(component).components();
+        matches!(
+            (parsed.next(), parsed.next()),
+            (Some(Component::Normal(name)), None) if name == *component
+        )
+    });
+
+    safe.then_some(components)
+}
+
+/// Creates a directory for an entry, or uses the one that's already there. Anything else
+/// already at the path, including a symbolic link to a directory, is an error.
+fn create_directory(path: &Path) -> io::Result<()> {
+    match fs::create_dir(path) {
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+            if fs::symlink_metadata(path)?.is_dir() {
+                Ok(())
+            } else {
+                Err(io::Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{path:?} already exists and isn't a directory"),
+                ))
+            }
+        }
+        result => result,
+    }
+}
+
+/// The permission bits of a file or directory.
+#[cfg(unix)]
+fn mode_of(metadata: &Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+/// The permission bits of a file or directory, of which only whether it can be written is known.
+#[cfg(not(unix))]
+fn mode_of(metadata: &Metadata) -> Option<u32> {
+    let mode = if metadata.is_dir() { 0o755 } else { 0o644 };
+
+    match metadata.permissions().readonly() {
+        true => Some(mode & !0o222),
+        false => Some(mode),
+    }
+}
+
+/// Gives an unpacked file or directory the permissions it was packed with.
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: Option<u32>) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    match mode {
+        // Leaving out the setuid, setgid and sticky bits, which a hidden file shouldn't carry
+        Some(mode) => fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777)),
+        None => Ok(()),
+    }
+}
+
+/// Gives an unpacked file or directory the permissions it was packed with, of which only whether
+/// it can be written is kept.
+#[cfg(not(unix))]
+fn set_mode(path: &Path, mode: Option<u32>) -> io::Result<()> {
+    match mode {
+        Some(mode) if mode & 0o200 == 0 => {
+            let mut permissions = fs::metadata(path)?.permissions();
+            permissions.set_readonly(true);
+            fs::set_permissions(path, permissions)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidInput, message)
+}
+
+/// Splits the next bytes off of the archive.
+fn take<'a>(bytes: &mut &'a [u8], count: usize) -> Result<&'a [u8], CorruptionType> {
+    let (taken, rest) = bytes
+        .split_at_checked(count)
+        .ok_or(CorruptionType::IncorrectArchive)?;
+    *bytes = rest;
+
+    Ok(taken)
+}
+
+/// Splits a fixed number of bytes off of the archive.
+fn take_array<const N: usize>(bytes: &mut &[u8]) -> Result<[u8; N], CorruptionType> {
+    Ok(take(bytes, N)?.try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use crate::{
+        archive::{pack, Archive, EntryKind},
+        CorruptionType,
+    };
+
+    fn test_data_dir() -> PathBuf {
+        "./test_data".into()
+    }
+
+    /// An archive with a single empty file at the path.
+    fn archive_with_path(path: &str) -> Vec<u8> {
+        let mut bytes = 1u32.to_be_bytes().to_vec();
+        bytes.extend((path.len() as u16).to_be_bytes());
+        bytes.extend(path.as_bytes());
+        bytes.extend([0, 0, 0, 0, 0, 0]);
+        bytes.extend(0u64.to_be_bytes());
+
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let packed_dir = test_data_dir().join("archive.result.packed");
+        let unpacked_dir = test_data_dir().join("archive.result.unpacked");
+        let _ = fs::remove_dir_all(&packed_dir);
+        let _ = fs::remove_dir_all(&unpacked_dir);
+        fs::create_dir_all(packed_dir.join("notes/empty")).unwrap();
+        fs::write(packed_dir.join("notes/todo.txt"), "Feed the stegosaurus").unwrap();
+
+        let bytes = pack(&[packed_dir.join("notes"), test_data_dir().join("story.txt")]).unwrap();
+        let archive = Archive::read(&bytes).unwrap();
+        let entries: Vec<_> = archive
+            .entries()
+            .into_iter()
+            .map(|entry| (entry.path, entry.kind, entry.size))
+            .collect();
+        let story_size = fs::metadata(test_data_dir().join("story.txt"))
+            .unwrap()
+            .len();
+        assert_eq!(
+            entries,
+            [
+                ("notes".to_owned(), EntryKind::Directory, 0),
+                ("notes/empty".to_owned(), EntryKind::Directory, 0),
+                ("notes/todo.txt".to_owned(), EntryKind::File, 20),
+                ("story.txt".to_owned(), EntryKind::File, story_size),
+            ]
+        );
+
+        archive.unpack(&unpacked_dir).unwrap();
+        assert!(unpacked_dir.join("notes/empty").is_dir());
+        assert_eq!(
+            fs::read(unpacked_dir.join("notes/todo.txt")).unwrap(),
+            b"Feed the stegosaurus"
+        );
+        assert_eq!(
+            fs::read(unpacked_dir.join("story.txt")).unwrap(),
+            fs::read(test_data_dir().join("story.txt")).unwrap()
+        );
+
+        // Unpacking again would overwrite the files, which is refused
+        assert!(archive.unpack(&unpacked_dir).is_err());
+    }
+
+    #[test]
+    fn rejects_paths_outside_of_the_directory() {
+        for path in [
+            "../escaped",
+            "notes/../../escaped",
+            "/etc/passwd",
+            "",
+            "notes//todo",
+        ] {
+            assert_eq!(
+                Archive::read(&archive_with_path(path)).err(),
+                Some(CorruptionType::UnsafeArchivePath(path.to_owned()))
+            );
+        }
+
+        assert!(Archive::read(&archive_with_path("notes/todo.txt")).is_ok());
+    }
+
+    #[test]
+    fn rejects_truncated_archive() {
+        let bytes = archive_with_path("notes");
+
+        assert_eq!(
+            Archive::read(&bytes[..bytes.len() - 1]).err(),
+            Some(CorruptionType::IncorrectArchive)
+        );
+    }
+
+    #[test]
+    fn rejects_secrets_with_the_same_name() {
+        let story = test_data_dir().join("story.txt");
+
+        assert!(pack(&[&story, &story]).is_err());
+    }
+}
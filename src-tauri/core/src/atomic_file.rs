@@ -0,0 +1,323 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+/// The number of differently named temporary files tried before giving up.
+const MAX_ATTEMPTS: u32 = 100;
+
+/// A file that is written under a temporary name, then renamed over its destination once it is
+/// complete.
+///
+/// The destination is never left half written. If the AtomicFile is dropped without being
+/// persisted, the temporary file is removed and the destination is untouched.
+pub struct AtomicFile {
+    file: File,
+    temporary_path: PathBuf,
+    destination: PathBuf,
+    persisted: bool,
+}
+
+impl AtomicFile {
+    /// Creates a temporary file next to the destination, so that it can be renamed over it
+    /// without crossing file systems.
+    pub fn create(destination: impl AsRef<Path>) -> io::Result<AtomicFile> {
+        let destination = destination.as_ref().to_path_buf();
+        let directory = match destination.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+
+        let (file, temporary_path) = create_temporary(directory, &destination, |path| {
+            OpenOptions::new().write(true).create_new(true).open(path)
+        })?;
+        log::trace!("Created temporary file {temporary_path:?}");
+
+        Ok(AtomicFile {
+            file,
+            temporary_path,
+            destination,
+            persisted: false,
+        })
+    }
+
+    /// The temporary file that should be written to.
+    pub fn file_mut(&mut self) -> &mut File {
+        &mut self.file
+    }
+
+    /// Renames the temporary file over the destination, replacing anything already there.
+    ///
+    /// A destination that already exists keeps its permissions, rather than taking on the
+    /// temporary file's defaults.
+    pub fn persist(mut self) -> io::Result<()> {
+        if let Ok(metadata) = fs::metadata(&self.destination) {
+            self.file.set_permissions(metadata.permissions())?;
+        }
+        self.file.sync_all()?;
+        fs::rename(&self.temporary_path, &self.destination)?;
+        self.persisted = true;
+        log::trace!("Moved temporary file to {:?}", self.destination);
+
+        Ok(())
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            if let Err(err) = fs::remove_file(&self.temporary_path) {
+                log::warn!(
+                    "Couldn't remove temporary file {:?}: {err}",
+                    self.temporary_path
+                );
+            }
+        }
+    }
+}
+
+/// A directory that is filled under a temporary name, then moved into its destination once it
+/// is complete.
+///
+/// If the destination doesn't exist, the whole directory is renamed into place. If it's an
+/// existing directory, the temporary directory is made inside of it, and each of its entries is
+/// moved out into the destination, none of which may already exist there. If the
+/// AtomicDirectory is dropped without being persisted, the temporary directory and everything
+/// in it is removed and the destination is untouched.
+pub struct AtomicDirectory {
+    temporary_path: PathBuf,
+    destination: PathBuf,
+    persisted: bool,
+}
+
+impl AtomicDirectory {
+    /// Creates a temporary directory next to or inside of the destination, so that its contents
+    /// can be moved into it without crossing file systems.
+    pub fn create(destination: impl AsRef<Path>) -> io::Result<AtomicDirectory> {
+        let destination = destination.as_ref().to_path_buf();
+        let directory = if destination.is_dir() {
+            destination.as_path()
+        } else {
+            match destination.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            }
+        };
+
+        let ((), temporary_path) =
+            create_temporary(directory, &destination, |path| fs::create_dir(path))?;
+        log::trace!("Created temporary directory {temporary_path:?}");
+
+        Ok(AtomicDirectory {
+            temporary_path,
+            destination,
+            persisted: false,
+        })
+    }
+
+    /// The temporary directory that should be filled.
+    pub fn path(&self) -> &Path {
+        &self.temporary_path
+    }
+
+    /// Moves the temporary directory, or each of its entries, into the destination.
+    ///
+    /// Fails without moving anything if an entry already exists in the destination. If moving
+    /// an entry fails partway, the entries already moved are moved back, so the destination is
+    /// left as it was.
+    pub fn persist(mut self) -> io::Result<()> {
+        if !self.destination.is_dir() {
+            fs::rename(&self.temporary_path, &self.destination)?;
+            self.persisted = true;
+            log::trace!("Moved temporary directory to {:?}", self.destination);
+            return Ok(());
+        }
+
+        let entries = fs::read_dir(&self.temporary_path)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<io::Result<Vec<_>>>()?;
+        if let Some(existing) = entries
+            .iter()
+            .find(|name| fs::symlink_metadata(self.destination.join(name)).is_ok())
+        {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{existing:?} already exists in {:?}", self.destination),
+            ));
+        }
+
+        move_entries(&self.temporary_path, &self.destination, &entries)?;
+        fs::remove_dir(&self.temporary_path)?;
+        self.persisted = true;
+        log::trace!(
+            "Moved temporary directory's entries into {:?}",
+            self.destination
+        );
+
+        Ok(())
+    }
+}
+
+impl Drop for AtomicDirectory {
+    fn drop(&mut self) {
+        if !self.persisted {
+            if let Err(err) = fs::remove_dir_all(&self.temporary_path) {
+                log::warn!(
+                    "Couldn't remove temporary directory {:?}: {err}",
+                    self.temporary_path
+                );
+            }
+        }
+    }
+}
+
+/// Moves each of the named entries from one directory to another, moving the ones already moved
+/// back again if any of them fails.
+fn move_entries(from: &Path, to: &Path, names: &[OsString]) -> io::Result<()> {
+    for (moved, name) in names.iter().enumerate() {
+        if let Err(err) = fs::rename(from.join(name), to.join(name)) {
+            for name in &names[..moved] {
+                if let Err(err) = fs::rename(to.join(name), from.join(name)) {
+                    log::warn!("Couldn't move {name:?} back out of {to:?}: {err}");
+                }
+            }
+            return Err(err);
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates something with an unused temporary name in the directory, named after the
+/// destination.
+fn create_temporary<T>(
+    directory: &Path,
+    destination: &Path,
+    create: impl Fn(&Path) -> io::Result<T>,
+) -> io::Result<(T, PathBuf)> {
+    let file_name = destination
+        .file_name()
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "The output has no file name"))?
+        .to_string_lossy();
+
+    for attempt in 0..MAX_ATTEMPTS {
+        let temporary_path =
+            directory.join(format!(".{file_name}.{}.{attempt}.tmp", std::process::id()));
+
+        match create(&temporary_path) {
+            Ok(created) => return Ok((created, temporary_path)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Err(io::Error::new(
+        ErrorKind::AlreadyExists,
+        "Couldn't find an unused name for a temporary file",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write, path::PathBuf};
+
+    use crate::atomic_file::{move_entries, AtomicDirectory, AtomicFile};
+
+    fn test_data_dir() -> PathBuf {
+        "./test_data".into()
+    }
+
+    #[test]
+    fn only_replaces_destination_when_persisted() {
+        let destination = test_data_dir().join("atomic.result.txt");
+        fs::write(&destination, b"original").unwrap();
+
+        let mut file = AtomicFile::create(&destination).unwrap();
+        let temporary_path = file.temporary_path.clone();
+        file.file_mut().write_all(b"abandoned").unwrap();
+        drop(file);
+
+        assert_eq!(fs::read(&destination).unwrap(), b"original");
+        assert!(!temporary_path.exists());
+
+        let mut file = AtomicFile::create(&destination).unwrap();
+        file.file_mut().write_all(b"replaced").unwrap();
+        file.persist().unwrap();
+
+        assert_eq!(fs::read(&destination).unwrap(), b"replaced");
+        assert!(!temporary_path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_destination_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let destination = test_data_dir().join("atomic_permissions.result.txt");
+        fs::write(&destination, b"original").unwrap();
+        fs::set_permissions(&destination, fs::Permissions::from_mode(0o600)).unwrap();
+
+        let mut file = AtomicFile::create(&destination).unwrap();
+        file.file_mut().write_all(b"replaced").unwrap();
+        file.persist().unwrap();
+
+        let mode = fs::metadata(&destination).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn only_fills_directory_when_persisted() {
+        let destination = test_data_dir().join("atomic.result.dir");
+        let _ = fs::remove_dir_all(&destination);
+
+        let directory = AtomicDirectory::create(&destination).unwrap();
+        let temporary_path = directory.path().to_path_buf();
+        fs::write(temporary_path.join("abandoned.txt"), b"abandoned").unwrap();
+        drop(directory);
+
+        assert!(!destination.exists());
+        assert!(!temporary_path.exists());
+
+        let directory = AtomicDirectory::create(&destination).unwrap();
+        fs::write(directory.path().join("first.txt"), b"first").unwrap();
+        directory.persist().unwrap();
+        assert_eq!(fs::read(destination.join("first.txt")).unwrap(), b"first");
+
+        // An existing directory is added to, without replacing anything already in it
+        let directory = AtomicDirectory::create(&destination).unwrap();
+        fs::write(directory.path().join("second.txt"), b"second").unwrap();
+        fs::write(directory.path().join("first.txt"), b"replaced").unwrap();
+        assert!(directory.persist().is_err());
+        assert_eq!(fs::read(destination.join("first.txt")).unwrap(), b"first");
+        assert!(!destination.join("second.txt").exists());
+
+        let directory = AtomicDirectory::create(&destination).unwrap();
+        fs::write(directory.path().join("second.txt"), b"second").unwrap();
+        directory.persist().unwrap();
+        assert_eq!(
+            fs::read_dir(&destination).unwrap().count(),
+            2,
+            "The temporary directory should be gone"
+        );
+    }
+
+    #[test]
+    fn moves_entries_back_when_one_fails() {
+        let from = test_data_dir().join("atomic_moves.result.from");
+        let to = test_data_dir().join("atomic_moves.result.to");
+        let _ = fs::remove_dir_all(&from);
+        let _ = fs::remove_dir_all(&to);
+        fs::create_dir(&from).unwrap();
+        fs::create_dir(&to).unwrap();
+        fs::write(from.join("first.txt"), b"first").unwrap();
+
+        // The second entry is missing, so moving it fails after the first has been moved
+        let names = ["first.txt".into(), "missing.txt".into()];
+        assert!(move_entries(&from, &to, &names).is_err());
+
+        assert_eq!(fs::read(from.join("first.txt")).unwrap(), b"first");
+        assert_eq!(fs::read_dir(&to).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,191 @@
+use std::io::{Read, Write};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder};
+
+use crate::{header::Header, Compression, CorruptionType};
+
+/// The zstd level payloads are compressed at. Payloads are usually small, and the space to hide
+/// them in is tight, so a slow but thorough level is used.
+const ZSTD_LEVEL: i32 = 19;
+
+/// The most bytes a payload may decompress to. A tiny payload can claim to decompress to far
+/// more than fits in memory, so anything larger is treated as corrupted.
+const MAX_DECOMPRESSED_BYTES: u64 = 1 << 30;
+
+impl Compression {
+    /// The header flag recording that a payload was compressed this way, if it was compressed.
+    pub fn flag(self) -> u16 {
+        match self {
+            Compression::None => 0,
+            Compression::Deflate => Header::DEFLATE_COMPRESSED,
+            Compression::Zstd => Header::ZSTD_COMPRESSED,
+        }
+    }
+
+    /// Finds how a payload was compressed from its header's flags.
+    pub fn from_flags(flags: u16) -> Result<Compression, CorruptionType> {
+        match (
+            flags & Header::DEFLATE_COMPRESSED != 0,
+            flags & Header::ZSTD_COMPRESSED != 0,
+        ) {
+            (false, false) => Ok(Compression::None),
+            (true, false) => Ok(Compression::Deflate),
+            (false, true) => Ok(Compression::Zstd),
+            (true, true) => Err(CorruptionType::IncorrectHeader),
+        }
+    }
+
+    /// The most bytes each compressed byte can decompress to. Deflate repeats at most 258 bytes
+    /// for every 2 bits, and zstd at most a 128 KiB block for every 4 bytes.
+    fn max_ratio(self) -> u64 {
+        match self {
+            Compression::None => 1,
+            Compression::Deflate => 1032,
+            Compression::Zstd => 32768,
+        }
+    }
+}
+
+/// Compresses the bytes with the algorithm, returning None if that doesn't make them smaller.
+pub fn compress(compression: Compression, bytes: &[u8]) -> Option<Vec<u8>> {
+    let compressed = match compression {
+        Compression::None => return None,
+        Compression::Deflate => {
+            let mut encoder = DeflateEncoder::new(vec![], flate2::Compression::best());
+            encoder
+                .write_all(bytes)
+                .and_then(|_| encoder.finish())
+                .expect("Compressing into a Vec can't fail")
+        }
+        Compression::Zstd => {
+            zstd::bulk::compress(bytes, ZSTD_LEVEL).expect("Compressing into a Vec can't fail")
+        }
+    };
+    log::trace!(
+        "Compressed the payload from {} to {} bytes with {compression:?}",
+        bytes.len(),
+        compressed.len()
+    );
+
+    Some(compressed).filter(|compressed| compressed.len() < bytes.len())
+}
+
+/// Decompresses bytes produced by `compress` with the same algorithm, along with whether they
+/// were decompressed completely.
+///
+/// Damaged bytes are decompressed up to the damage, so that whatever came before it can be
+/// recovered. Returns `CorruptionType::IncorrectCompression` if nothing at all could be
+/// decompressed, or if they decompress to more than the algorithm could have compressed into
+/// that many bytes, or more than `MAX_DECOMPRESSED_BYTES`. A small payload crafted to
+/// decompress to a huge size is stopped without decompressing it all.
+pub fn decompress(
+    compression: Compression,
+    bytes: &[u8],
+) -> Result<(Vec<u8>, bool), CorruptionType> {
+    let limit = (bytes.len() as u64)
+        .saturating_mul(compression.max_ratio())
+        .min(MAX_DECOMPRESSED_BYTES);
+    decompress_up_to(compression, bytes, limit)
+}
+
+/// Decompresses the bytes like `decompress`, failing if they decompress to more than the limit.
+fn decompress_up_to(
+    compression: Compression,
+    bytes: &[u8],
+    limit: u64,
+) -> Result<(Vec<u8>, bool), CorruptionType> {
+    // Reading one byte past the limit shows whether it was reached
+    let mut decompressed = vec![];
+    let result = match compression {
+        Compression::None => return Ok((bytes.to_vec(), true)),
+        Compression::Deflate => DeflateDecoder::new(bytes)
+            .take(limit + 1)
+            .read_to_end(&mut decompressed),
+        Compression::Zstd => zstd::Decoder::new(bytes)
+            .and_then(|decoder| decoder.take(limit + 1).read_to_end(&mut decompressed)),
+    };
+    if decompressed.len() as u64 > limit {
+        log::debug!("The payload decompressed to more than {limit} bytes");
+        return Err(CorruptionType::IncorrectCompression);
+    }
+    // Reading stops at the damage, keeping everything decompressed before it
+    if let Err(err) = result {
+        log::debug!("Couldn't decompress the payload with {compression:?}: {err}");
+        if decompressed.is_empty() {
+            return Err(CorruptionType::IncorrectCompression);
+        }
+        return Ok((decompressed, false));
+    }
+    log::trace!("Decompressed the payload");
+
+    Ok((decompressed, true))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compression::{compress, decompress, decompress_up_to},
+        Compression, CorruptionType,
+    };
+
+    #[test]
+    fn round_trip() {
+        let text = "The quick brown fox jumps over the lazy dog. ".repeat(100);
+
+        for compression in [Compression::Deflate, Compression::Zstd] {
+            let compressed = compress(compression, text.as_bytes()).unwrap();
+
+            assert!(compressed.len() < text.len() / 10);
+            assert_eq!(
+                decompress(compression, &compressed).unwrap(),
+                (text.as_bytes().to_vec(), true)
+            );
+        }
+    }
+
+    #[test]
+    fn skips_compression_that_doesnt_help() {
+        assert_eq!(compress(Compression::Deflate, b"abc"), None);
+        assert_eq!(compress(Compression::Zstd, b"abc"), None);
+        assert_eq!(compress(Compression::None, &[0; 100]), None);
+    }
+
+    #[test]
+    fn rejects_corrupted_data() {
+        let mut compressed = compress(Compression::Zstd, &[0; 100]).unwrap();
+        compressed.truncate(compressed.len() / 2);
+
+        assert_eq!(
+            decompress(Compression::Zstd, &compressed),
+            Err(CorruptionType::IncorrectCompression)
+        );
+    }
+
+    #[test]
+    fn decompresses_highly_compressed_data() {
+        let zeros = vec![0; 16 << 20];
+
+        for compression in [Compression::Deflate, Compression::Zstd] {
+            let compressed = compress(compression, &zeros).unwrap();
+
+            assert!(decompress(compression, &compressed).unwrap() == (zeros.clone(), true));
+        }
+    }
+
+    #[test]
+    fn rejects_data_that_decompresses_past_the_limit() {
+        for compression in [Compression::Deflate, Compression::Zstd] {
+            let compressed = compress(compression, &[0; 1000]).unwrap();
+
+            assert_eq!(
+                decompress_up_to(compression, &compressed, 1000)
+                    .map(|(bytes, complete)| (bytes.len(), complete)),
+                Ok((1000, true))
+            );
+            assert_eq!(
+                decompress_up_to(compression, &compressed, 999),
+                Err(CorruptionType::IncorrectCompression)
+            );
+        }
+    }
+}
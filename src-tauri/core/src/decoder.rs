@@ -0,0 +1,179 @@
+use std::{
+    io::{Cursor, ErrorKind, Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use same_file::Handle;
+
+use crate::{
+    archive::Archive,
+    atomic_file::{AtomicDirectory, AtomicFile},
+    encoded_context,
+    file_types::encoded_file::EncodedFile,
+    output_context,
+    progress::Progress,
+    with_contexts, CorruptionType, DecodeOptions, ErrorContext, ErrorType, Result,
+    SecretProperties, SupportedFileType, WhichDuplicates,
+};
+
+/// Handles the steganographic process of decoding an encoded file.
+pub struct Decoder {
+    encoded_file: EncodedFile,
+    encoded_file_path: PathBuf,
+    /// Nothing is written here until decoding has succeeded. If this is a directory, the secret
+    /// file's name is added to it once the payload has been read.
+    output_file_path: PathBuf,
+    options: DecodeOptions,
+    progress: Progress,
+}
+
+impl Decoder {
+    /// Constructs a new Decoder using the default options.
+    ///
+    /// If the output path is a directory, the secret file will be written into it using the
+    /// name it was encoded with. Several secret files hidden together are always unpacked into
+    /// the output path as a directory, which is created if it doesn't exist.
+    pub fn new(
+        encoded_file_path: impl AsRef<Path>,
+        output_file_path: impl AsRef<Path>,
+    ) -> Result<Decoder> {
+        Decoder::with_options(
+            encoded_file_path,
+            output_file_path,
+            DecodeOptions::default(),
+        )
+    }
+
+    /// Constructs a new Decoder that will decode using the given options.
+    pub fn with_options(
+        encoded_file_path: impl AsRef<Path>,
+        output_file_path: impl AsRef<Path>,
+        options: DecodeOptions,
+    ) -> Result<Decoder> {
+        let encoded_file = EncodedFile::open(&encoded_file_path)?;
+        let output_file_path = output_file_path.as_ref().to_path_buf();
+
+        // A directory can't be the encoded file, but the file named within it is checked once
+        // the payload has been read
+        Decoder::check_for_duplicate_files(&encoded_file_path, &output_file_path)?;
+        log::trace!("Ensured no duplicate files");
+
+        Ok(Decoder {
+            encoded_file,
+            encoded_file_path: encoded_file_path.as_ref().to_path_buf(),
+            output_file_path,
+            options,
+            progress: Progress::default(),
+        })
+    }
+
+    /// Reports the progress of `decode` to the given progress, and lets it be cancelled.
+    pub fn set_progress(&mut self, progress: Progress) {
+        self.progress = progress;
+    }
+
+    /// The path the secret file will be written to, or the directory several secret files will
+    /// be unpacked into. If the output path is a directory, this is only the path the secret file
+    /// was written to once `decode` has read its name.
+    pub fn output_file_path(&self) -> &Path {
+        &self.output_file_path
+    }
+
+    /// Checks to see if any of the given files are the same.
+    ///
+    /// Files are compared by identity rather than by path, so hard links and symbolic links to
+    /// the same file are caught.
+    fn check_for_duplicate_files(
+        encoded_file_path: impl AsRef<Path>,
+        output_file_path: impl AsRef<Path>,
+    ) -> Result<()> {
+        let encoded = encoded_context!(Handle::from_path(encoded_file_path))?;
+        let output = match Handle::from_path(output_file_path) {
+            // An output file that doesn't exist yet can't be the same as the encoded file
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            result => output_context!(result)?,
+        };
+        log::trace!("Found the identities of the files");
+
+        if encoded == output {
+            with_contexts!(
+                Err(ErrorType::DuplicateFiles(WhichDuplicates::All)),
+                ErrorContext::EncodedFile,
+                ErrorContext::OutputFile,
+            )
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Decodes the encoded file, and writes the results to the output file.
+    ///
+    /// The secret file is read in full before anything is written, and is written to a
+    /// temporary file that only replaces the output file once it's complete, so a cancelled
+    /// decode leaves the output file untouched. A damaged secret file is only written for a best
+    /// effort recovery, which still returns the `ChecksumMismatch` error.
+    ///
+    /// An archive of several secret files is checked before any of them are unpacked, so one
+    /// with a path leading outside of the output directory writes nothing. It's unpacked into a
+    /// temporary directory that's only moved into the output directory once it's complete.
+    pub fn decode(&mut self) -> Result<()> {
+        // Payloads that don't match their checksum are only returned for a best effort recovery
+        let (payload, intact) = self.encoded_file.payload(&self.options, &self.progress)?;
+
+        if self.output_file_path.is_dir() && !payload.properties.archive {
+            self.output_file_path
+                .push(payload.properties.suggested_file_name());
+            log::debug!(
+                "Output is a directory, so decoding to {:?}",
+                self.output_file_path
+            );
+            Decoder::check_for_duplicate_files(&self.encoded_file_path, &self.output_file_path)?;
+        }
+
+        if payload.properties.archive {
+            let archive = encoded_context!(Archive::read(&payload.contents))?;
+            let directory = output_context!(AtomicDirectory::create(&self.output_file_path))?;
+            output_context!(archive.unpack(directory.path()))?;
+            output_context!(directory.persist())?;
+        } else {
+            let mut output_file = output_context!(AtomicFile::create(&self.output_file_path))?;
+            output_context!(output_file.file_mut().write_all(&payload.contents))?;
+            output_context!(output_file.persist())?;
+        }
+        log::trace!("Wrote the secret file's contents to the output");
+
+        if !intact {
+            return encoded_context!(Err(CorruptionType::ChecksumMismatch));
+        }
+        Ok(())
+    }
+}
+
+/// Decodes the secret hidden in an encoded file of the given type, writing it to the output.
+///
+/// The encoded file is read in full from its start. Returns the properties of the secret, which
+/// include the name it was encoded with.
+pub fn decode_to_writer(
+    encoded: impl Read + Seek,
+    encoded_type: SupportedFileType,
+    mut output: impl Write,
+    options: &DecodeOptions,
+) -> Result<SecretProperties> {
+    EncodedFile::from_reader(encoded, encoded_type)?.decode_to(
+        &mut output,
+        options,
+        &Progress::default(),
+    )
+}
+
+/// Decodes the secret hidden in an encoded file of the given type, returning its contents.
+pub fn decode_bytes(
+    encoded: &[u8],
+    encoded_type: SupportedFileType,
+    options: &DecodeOptions,
+) -> Result<Vec<u8>> {
+    let mut output = vec![];
+    decode_to_writer(Cursor::new(encoded), encoded_type, &mut output, options)?;
+
+    Ok(output)
+}
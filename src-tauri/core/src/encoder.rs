@@ -0,0 +1,512 @@
+use std::{
+    fs,
+    io::{self, Cursor, ErrorKind, Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use same_file::Handle;
+
+use crate::{
+    archive, atomic_file::AtomicFile, base_context, file_types::base_file::BaseFile,
+    output_context, payload::Payload, progress::Progress, secret_context, with_contexts,
+    EncodeOptions, ErrorType, Result, SupportedFileType, WhichDuplicates,
+};
+
+/// Handles the steganographic process of encoding a hidden file inside a base file.
+pub struct Encoder {
+    base_file: BaseFile,
+    /// The secret files and directories, which are only read when encoding. Kept as paths so
+    /// that their names can be stored alongside their contents.
+    secret_paths: Vec<PathBuf>,
+    /// Nothing is written here until encoding has succeeded.
+    output_file_path: PathBuf,
+    options: EncodeOptions,
+    progress: Progress,
+}
+
+impl Encoder {
+    /// Constructs a new Encoder using the default options.
+    pub fn new(
+        base_file_path: impl AsRef<Path>,
+        secret_file_path: impl AsRef<Path>,
+        output_file_path: impl AsRef<Path>,
+    ) -> Result<Encoder> {
+        Encoder::with_options(
+            base_file_path,
+            secret_file_path,
+            output_file_path,
+            EncodeOptions::default(),
+        )
+    }
+
+    /// Constructs a new Encoder that will encode using the given options.
+    ///
+    /// The paths are checked before any file is opened, so an output path that is the same file
+    /// as one of the inputs is reported without touching it.
+    pub fn with_options(
+        base_file_path: impl AsRef<Path>,
+        secret_file_path: impl AsRef<Path>,
+        output_file_path: impl AsRef<Path>,
+        options: EncodeOptions,
+    ) -> Result<Encoder> {
+        Encoder::with_secrets(
+            base_file_path,
+            [secret_file_path],
+            output_file_path,
+            options,
+        )
+    }
+
+    /// Constructs a new Encoder that will hide several secret files and directories together,
+    /// using the given options.
+    ///
+    /// Unless there's a single secret file, they're packed into an archive along with everything
+    /// inside of the directories, which is unpacked into a directory when decoded.
+    pub fn with_secrets(
+        base_file_path: impl AsRef<Path>,
+        secret_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        output_file_path: impl AsRef<Path>,
+        options: EncodeOptions,
+    ) -> Result<Encoder> {
+        let secret_paths: Vec<PathBuf> = secret_paths
+            .into_iter()
+            .map(|secret_path| secret_path.as_ref().to_path_buf())
+            .collect();
+        if secret_paths.is_empty() {
+            return secret_context!(Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "No secret files were given",
+            )));
+        }
+
+        for secret_path in &secret_paths {
+            Encoder::check_for_duplicate_files(&base_file_path, secret_path, &output_file_path)?;
+        }
+        log::trace!("Ensured no duplicate files");
+
+        let base_file = BaseFile::open(&base_file_path)?;
+        log::trace!("Opened the base file");
+
+        Ok(Encoder {
+            base_file,
+            secret_paths,
+            output_file_path: output_file_path.as_ref().into(),
+            options,
+            progress: Progress::default(),
+        })
+    }
+
+    /// Reports the progress of `encode` to the given progress, and lets it be cancelled.
+    pub fn set_progress(&mut self, progress: Progress) {
+        self.progress = progress;
+    }
+
+    /// Checks to see if any of the given files are the same.
+    ///
+    /// Files are compared by identity rather than by path, so hard links and symbolic links to
+    /// the same file are caught.
+    fn check_for_duplicate_files(
+        base_file_path: impl AsRef<Path>,
+        secret_file_path: impl AsRef<Path>,
+        output_file_path: impl AsRef<Path>,
+    ) -> Result<()> {
+        let base = base_context!(Handle::from_path(base_file_path))?;
+        let secret = secret_context!(Handle::from_path(secret_file_path))?;
+        let output = match Handle::from_path(output_file_path) {
+            // An output file that doesn't exist yet can't be the same as either input
+            Err(err) if err.kind() == ErrorKind::NotFound => None,
+            result => Some(output_context!(result)?),
+        };
+        log::trace!("Found the identities of the files");
+
+        use crate::error::ErrorContext::*;
+        if base == secret {
+            if output.as_ref() == Some(&secret) {
+                with_contexts!(
+                    Err(ErrorType::DuplicateFiles(WhichDuplicates::All)),
+                    BaseFile,
+                    SecretFile,
+                    OutputFile,
+                )
+            } else {
+                with_contexts!(
+                    Err(ErrorType::DuplicateFiles(WhichDuplicates::BaseAndSecret)),
+                    BaseFile,
+                    SecretFile,
+                )
+            }
+        } else if output.as_ref() == Some(&base) {
+            with_contexts!(
+                Err(ErrorType::DuplicateFiles(WhichDuplicates::BaseAndOutput)),
+                BaseFile,
+                OutputFile,
+            )
+        } else if output.as_ref() == Some(&secret) {
+            with_contexts!(
+                Err(ErrorType::DuplicateFiles(WhichDuplicates::SecretAndOutput)),
+                SecretFile,
+                OutputFile,
+            )
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Encodes the hidden file into the base file, and writes the results to the output file.
+    ///
+    /// The results are written to a temporary file that only replaces the output file once
+    /// encoding has succeeded, so a cancelled encode leaves the output file untouched.
+    pub fn encode(&mut self) -> Result<()> {
+        let payload = read_secrets(&self.secret_paths)?.seal(&self.options);
+        let mut output_file = output_context!(AtomicFile::create(&self.output_file_path))?;
+        self.base_file.encode_to(
+            &payload,
+            &self.options,
+            &self.progress,
+            output_file.file_mut(),
+        )?;
+
+        output_context!(output_file.persist())
+    }
+}
+
+/// Reads the secret files into a payload, packing them into an archive if there are several or
+/// any of them is a directory.
+fn read_secrets(secret_paths: &[impl AsRef<Path>]) -> Result<Payload> {
+    match secret_paths {
+        [secret_path] if !secret_path.as_ref().is_dir() => {
+            let contents = secret_context!(fs::read(secret_path))?;
+            log::trace!("Read in the secret file");
+
+            Ok(Payload::new(secret_path, contents))
+        }
+        _ => {
+            let archive = secret_context!(archive::pack(secret_paths))?;
+            log::trace!("Packed the secret files into an archive");
+
+            Ok(Payload::archive(secret_paths, archive))
+        }
+    }
+}
+
+/// Gets the number of bytes of a base file's available space that the secret files will take up
+/// when encoded with the given options, which is less than their size if they compress well.
+///
+/// Several files, or a directory, take up the space of the archive they would be hidden as.
+pub fn secret_size(secret_paths: &[impl AsRef<Path>], options: &EncodeOptions) -> Result<u64> {
+    Ok(read_secrets(secret_paths)?.sealed_size(options))
+}
+
+/// Encodes the secret into a base file of the given type, writing the results to the output.
+///
+/// The base is read in full from its start. The secret has no path, so no file name is recorded
+/// for it, and decoding it suggests a name from its contents instead.
+pub fn encode_to_writer(
+    base: impl Read + Seek,
+    base_type: SupportedFileType,
+    mut secret: impl Read,
+    mut output: impl Write,
+    options: &EncodeOptions,
+) -> Result<()> {
+    let mut base_file = BaseFile::from_reader(base, base_type)?;
+
+    let mut contents = vec![];
+    secret_context!(secret.read_to_end(&mut contents))?;
+    log::trace!("Read in the secret");
+
+    let payload = Payload::new("", contents).seal(options);
+    base_file.encode_to(&payload, options, &Progress::default(), &mut output)
+}
+
+/// Encodes the secret into a base file of the given type, returning the encoded file's contents.
+pub fn encode_bytes(
+    base: &[u8],
+    base_type: SupportedFileType,
+    secret: &[u8],
+    options: &EncodeOptions,
+) -> Result<Vec<u8>> {
+    let mut output = vec![];
+    encode_to_writer(Cursor::new(base), base_type, secret, &mut output, options)?;
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    };
+
+    use crate::{
+        decode_bytes, encode_bytes, list_entries, secret_size, CancellationToken, Compression,
+        DecodeOptions, Decoder, EncodeOptions, Encoder, EntryKind, ErrorType, Progress,
+        SupportedFileType, WhichDuplicates,
+    };
+
+    fn test_data_dir() -> PathBuf {
+        "./test_data".into()
+    }
+
+    #[test]
+    fn hard_linked_output_is_left_untouched() {
+        let base_path = test_data_dir().join("stick_linked.result.png");
+        let output_path = test_data_dir().join("stick_link.result.png");
+        fs::copy(test_data_dir().join("stick.png"), &base_path).unwrap();
+        let _ = fs::remove_file(&output_path);
+        fs::hard_link(&base_path, &output_path).unwrap();
+
+        let error = Encoder::new(&base_path, test_data_dir().join("story.txt"), &output_path)
+            .err()
+            .unwrap();
+
+        assert!(matches!(
+            error.error_type,
+            ErrorType::DuplicateFiles(WhichDuplicates::BaseAndOutput)
+        ));
+        assert_eq!(
+            fs::read(&base_path).unwrap(),
+            fs::read(test_data_dir().join("stick.png")).unwrap()
+        );
+    }
+
+    #[test]
+    fn in_memory_round_trip() {
+        let base = fs::read(test_data_dir().join("stick.png")).unwrap();
+        let secret = fs::read(test_data_dir().join("story.txt")).unwrap();
+        let options = EncodeOptions {
+            password: Some("password".to_owned()),
+            ..Default::default()
+        };
+
+        let encoded = encode_bytes(&base, SupportedFileType::Png, &secret, &options).unwrap();
+        let decoded = decode_bytes(
+            &encoded,
+            SupportedFileType::Png,
+            &DecodeOptions {
+                password: Some("password".to_owned()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(decoded, secret);
+    }
+
+    #[test]
+    fn compression_fits_a_larger_secret() {
+        let base = fs::read(test_data_dir().join("stick.png")).unwrap();
+        let story = fs::read(test_data_dir().join("story.txt")).unwrap();
+        let secret = story.repeat(200_000 / story.len() + 1);
+
+        let error = encode_bytes(
+            &base,
+            SupportedFileType::Png,
+            &secret,
+            &EncodeOptions::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error.error_type,
+            ErrorType::BaseFileNotBigEnough { .. }
+        ));
+
+        let options = EncodeOptions {
+            compression: Compression::Zstd,
+            ..Default::default()
+        };
+        let encoded = encode_bytes(&base, SupportedFileType::Png, &secret, &options).unwrap();
+        let decoded =
+            decode_bytes(&encoded, SupportedFileType::Png, &DecodeOptions::default()).unwrap();
+        assert_eq!(decoded, secret);
+    }
+
+    #[test]
+    fn secret_size_counts_compression() {
+        let story_path = test_data_dir().join("story.txt");
+        let repeated_path = test_data_dir().join("story_repeated.result.txt");
+        let story = fs::read(&story_path).unwrap();
+        fs::write(&repeated_path, story.repeat(100)).unwrap();
+        let options = EncodeOptions {
+            compression: Compression::Deflate,
+            ..Default::default()
+        };
+
+        // The file's name and content type are stored with it, so an uncompressed secret takes
+        // up more space than its size
+        assert!(
+            secret_size(&[&story_path], &EncodeOptions::default()).unwrap() > story.len() as u64
+        );
+        assert!(secret_size(&[&repeated_path], &options).unwrap() < story.len() as u64 * 10);
+    }
+
+    #[test]
+    fn secret_size_measures_the_archive() {
+        let story_path = test_data_dir().join("story.txt");
+        let stick_path = test_data_dir().join("stick.png");
+        let stick = fs::read(&stick_path).unwrap();
+        let options = EncodeOptions::default();
+
+        let story_size = secret_size(&[&story_path], &options).unwrap();
+        let both_size = secret_size(&[&story_path, &stick_path], &options).unwrap();
+        assert!(both_size > story_size + stick.len() as u64);
+    }
+
+    #[test]
+    fn directory_round_trip() {
+        let secret_dir = test_data_dir().join("documents.result.secret");
+        let encoded_path = test_data_dir().join("stick_documents.result.png");
+        let output_dir = test_data_dir().join("documents.result.decoded");
+        let _ = fs::remove_dir_all(&secret_dir);
+        let _ = fs::remove_dir_all(&output_dir);
+        fs::create_dir_all(secret_dir.join("letters")).unwrap();
+        fs::write(secret_dir.join("letters/dear.txt"), "Dear diary").unwrap();
+
+        let secrets = [&secret_dir, &test_data_dir().join("story.txt")];
+        Encoder::with_secrets(
+            test_data_dir().join("stick.png"),
+            secrets,
+            &encoded_path,
+            EncodeOptions::default(),
+        )
+        .unwrap()
+        .encode()
+        .unwrap();
+
+        let listed: Vec<_> = list_entries(&encoded_path, &DecodeOptions::default())
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.path, entry.kind))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("documents.result.secret".to_owned(), EntryKind::Directory),
+                (
+                    "documents.result.secret/letters".to_owned(),
+                    EntryKind::Directory
+                ),
+                (
+                    "documents.result.secret/letters/dear.txt".to_owned(),
+                    EntryKind::File
+                ),
+                ("story.txt".to_owned(), EntryKind::File),
+            ]
+        );
+        assert!(!output_dir.exists());
+
+        let mut decoder = Decoder::new(&encoded_path, &output_dir).unwrap();
+        decoder.decode().unwrap();
+        assert_eq!(decoder.output_file_path(), output_dir);
+        assert_eq!(
+            fs::read(output_dir.join("documents.result.secret/letters/dear.txt")).unwrap(),
+            b"Dear diary"
+        );
+        assert_eq!(
+            fs::read(output_dir.join("story.txt")).unwrap(),
+            fs::read(test_data_dir().join("story.txt")).unwrap()
+        );
+    }
+
+    #[test]
+    fn in_memory_type_must_match_contents() {
+        let base = fs::read(test_data_dir().join("stick.png")).unwrap();
+
+        let error = encode_bytes(
+            &base,
+            SupportedFileType::Jpeg,
+            b"secret",
+            &EncodeOptions::default(),
+        )
+        .unwrap_err();
+
+        assert!(matches!(
+            error.error_type,
+            ErrorType::FileTypeMismatch {
+                from_extension: SupportedFileType::Jpeg,
+                from_contents: SupportedFileType::Png,
+            }
+        ));
+    }
+
+    #[test]
+    fn reports_progress_up_to_the_payload_size() {
+        let output_path = test_data_dir().join("stick_progress.result.png");
+        let reports = Arc::new(Mutex::new(vec![]));
+        let observed = reports.clone();
+
+        let mut encoder = Encoder::new(
+            test_data_dir().join("stick.png"),
+            test_data_dir().join("story.txt"),
+            &output_path,
+        )
+        .unwrap();
+        encoder.set_progress(Progress::new().with_observer(move |processed, total| {
+            observed.lock().unwrap().push((processed, total));
+        }));
+        encoder.encode().unwrap();
+
+        let reports = reports.lock().unwrap();
+        let (processed, total) = *reports.last().unwrap();
+        assert_eq!(processed, total);
+        assert!(reports.len() > 2);
+    }
+
+    #[test]
+    fn cancelled_encode_leaves_no_output() {
+        let output_path = test_data_dir().join("stick_cancelled.result.png");
+        let _ = fs::remove_file(&output_path);
+        let cancellation = CancellationToken::new();
+        let cancel_part_way = cancellation.clone();
+
+        let mut encoder = Encoder::new(
+            test_data_dir().join("stick.png"),
+            test_data_dir().join("story.txt"),
+            &output_path,
+        )
+        .unwrap();
+        encoder.set_progress(
+            Progress::new()
+                .with_cancellation(cancellation)
+                .with_observer(move |processed, _| {
+                    if processed > 0 {
+                        cancel_part_way.cancel();
+                    }
+                }),
+        );
+        let error = encoder.encode().unwrap_err();
+
+        assert!(matches!(error.error_type, ErrorType::Cancelled));
+        assert!(!output_path.exists());
+    }
+
+    #[test]
+    fn cancelled_decode_into_a_directory_writes_nothing() {
+        let output_dir = test_data_dir().join("cancelled_decode.result.dir");
+        let _ = fs::remove_dir_all(&output_dir);
+        fs::create_dir(&output_dir).unwrap();
+        let cancellation = CancellationToken::new();
+        let cancel_part_way = cancellation.clone();
+        let reports = Arc::new(Mutex::new(0));
+        let observed = reports.clone();
+
+        let mut decoder =
+            Decoder::new(test_data_dir().join("stick_with_secret.png"), &output_dir).unwrap();
+        decoder.set_progress(
+            Progress::new()
+                .with_cancellation(cancellation)
+                .with_observer(move |processed, _| {
+                    *observed.lock().unwrap() += 1;
+                    if processed > 0 {
+                        cancel_part_way.cancel();
+                    }
+                }),
+        );
+        let error = decoder.decode().unwrap_err();
+
+        assert!(matches!(error.error_type, ErrorType::Cancelled));
+        assert!(*reports.lock().unwrap() > 0);
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,165 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+
+use crate::{CorruptionType, ErrorType};
+
+/// The number of bytes of random salt fed into the key derivation.
+const SALT_BYTES: usize = 16;
+
+/// The number of bytes in a ChaCha20-Poly1305 nonce.
+const NONCE_BYTES: usize = 12;
+
+/// The number of bytes used to store the Argon2 memory, iteration and parallelism costs.
+const PARAMS_BYTES: usize = 12;
+
+/// The number of bytes in a Poly1305 authentication tag.
+const TAG_BYTES: usize = 16;
+
+/// The number of bytes encryption adds to the plaintext.
+pub const OVERHEAD: usize = PARAMS_BYTES + SALT_BYTES + NONCE_BYTES + TAG_BYTES;
+
+/// Encrypts the plaintext with a key derived from the password.
+///
+/// The output starts with the Argon2id parameters, salt and nonce needed to decrypt it, followed
+/// by the ciphertext and its authentication tag. The header flags describing the plaintext are
+/// authenticated along with it, so they can't be changed without decryption failing.
+pub fn encrypt(password: &str, plaintext: &[u8], flags: u16) -> Vec<u8> {
+    let params = Params::default();
+
+    let mut salt = [0; SALT_BYTES];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let key = derive_key(password, &salt, params.clone())
+        .expect("The default Argon2 parameters are valid");
+    let ciphertext = ChaCha20Poly1305::new(&key)
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad: &flags.to_be_bytes(),
+            },
+        )
+        .expect("Encrypting into a Vec can't fail");
+    log::trace!("Encrypted the payload");
+
+    let mut bytes = Vec::with_capacity(PARAMS_BYTES + SALT_BYTES + NONCE_BYTES + ciphertext.len());
+    bytes.extend(params.m_cost().to_be_bytes());
+    bytes.extend(params.t_cost().to_be_bytes());
+    bytes.extend(params.p_cost().to_be_bytes());
+    bytes.extend(salt);
+    bytes.extend(nonce);
+    bytes.extend(ciphertext);
+
+    bytes
+}
+
+/// Decrypts bytes produced by `encrypt` using the password.
+///
+/// Returns `ErrorType::WrongPassword` if the ciphertext and flags don't authenticate with the key
+/// derived from the password. The Argon2 costs are read from the carrier, so any above the ones
+/// `encrypt` writes are rejected as incorrect encryption parameters rather than spending
+/// unbounded memory and time deriving the key.
+pub fn decrypt(password: &str, bytes: &[u8], flags: u16) -> Result<Vec<u8>, ErrorType> {
+    if bytes.len() < PARAMS_BYTES + SALT_BYTES + NONCE_BYTES {
+        return Err(CorruptionType::IncorrectEncryptionParameters.into());
+    }
+
+    let (params, rest) = bytes.split_at(PARAMS_BYTES);
+    let (salt, rest) = rest.split_at(SALT_BYTES);
+    let (nonce, ciphertext) = rest.split_at(NONCE_BYTES);
+
+    let cost = |index: usize| u32::from_be_bytes(params[index * 4..][..4].try_into().unwrap());
+    if cost(0) > Params::DEFAULT_M_COST
+        || cost(1) > Params::DEFAULT_T_COST
+        || cost(2) > Params::DEFAULT_P_COST
+    {
+        log::debug!("The Argon2 costs were higher than any encrypt writes");
+        return Err(CorruptionType::IncorrectEncryptionParameters.into());
+    }
+    let params = Params::new(cost(0), cost(1), cost(2), None)
+        .map_err(|_| CorruptionType::IncorrectEncryptionParameters)?;
+
+    let key = derive_key(password, salt, params)
+        .map_err(|_| CorruptionType::IncorrectEncryptionParameters)?;
+    let plaintext = ChaCha20Poly1305::new(&key)
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: &flags.to_be_bytes(),
+            },
+        )
+        .map_err(|_| ErrorType::WrongPassword)?;
+    log::trace!("Decrypted the payload");
+
+    Ok(plaintext)
+}
+
+/// Derives a 256 bit key from the password using Argon2id.
+fn derive_key(password: &str, salt: &[u8], params: Params) -> Result<Key, argon2::Error> {
+    let mut key = Key::default();
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params).hash_password_into(
+        password.as_bytes(),
+        salt,
+        &mut key,
+    )?;
+
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        encryption::{decrypt, encrypt},
+        CorruptionType, ErrorType,
+    };
+
+    #[test]
+    fn round_trip() {
+        let ciphertext = encrypt("hunter2", b"secret contents", 0);
+
+        assert_ne!(&ciphertext[ciphertext.len() - 15..], b"secret contents");
+        assert_eq!(
+            decrypt("hunter2", &ciphertext, 0).unwrap(),
+            b"secret contents"
+        );
+    }
+
+    #[test]
+    fn wrong_password() {
+        let ciphertext = encrypt("hunter2", b"secret contents", 0);
+
+        assert!(matches!(
+            decrypt("hunter3", &ciphertext, 0),
+            Err(ErrorType::WrongPassword)
+        ));
+    }
+
+    #[test]
+    fn authenticates_flags() {
+        let ciphertext = encrypt("hunter2", b"secret contents", 0b10);
+
+        assert!(matches!(
+            decrypt("hunter2", &ciphertext, 0b11),
+            Err(ErrorType::WrongPassword)
+        ));
+    }
+
+    #[test]
+    fn rejects_excessive_costs() {
+        let mut ciphertext = encrypt("hunter2", b"secret contents", 0);
+        // Asking for 4 TiB of memory
+        ciphertext[0..4].copy_from_slice(&u32::MAX.to_be_bytes());
+
+        assert!(matches!(
+            decrypt("hunter2", &ciphertext, 0),
+            Err(ErrorType::CorruptedFile(
+                CorruptionType::IncorrectEncryptionParameters
+            ))
+        ));
+    }
+}
@@ -1,7 +1,8 @@
 use std::{io::Error as IOError, path::Path};
 
 use image::ImageError;
-use serde::Serialize;
+
+use crate::file_types::supported_file::SupportedFileType;
 
 /// A shorthand for Result<T, crate::Error>.
 ///
@@ -23,17 +24,19 @@
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// Contains information about an error that occurred while processing a request.
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Error {
     /// Which files the error is associated with. Most often a single file.
     pub contexts: Vec<ErrorContext>,
     /// What the type of error was that occurred.
-    #[serde(flatten)]
+    #[cfg_attr(feature = "serde", serde(flatten))]
     pub error_type: ErrorType,
 }
 
 /// A representation of which file in the steganography process caused the error.
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum ErrorContext {
     /// The file into which data is encoded.
     BaseFile,
@@ -53,7 +56,7 @@ macro_rules! with_contexts {
             let error_type = $crate::ErrorType::from(err);
             let contexts = vec![$($context,)+];
 
-            log::error!("Context {contexts:?} - {error_type:?}");
+            $crate::log::error!("Context {contexts:?} - {error_type:?}");
 
             $crate::Error {
                 contexts,
@@ -62,7 +65,7 @@ macro_rules! with_contexts {
         })
     };
     ($result:expr, $($context:expr,)+) => {
-        with_contexts!($result, $($context),+)
+        $crate::with_contexts!($result, $($context),+)
     };
 }
 
@@ -99,13 +102,17 @@ macro_rules! encoded_context {
 }
 
 /// Different types of errors associated with Encoding and Decoding files.
-#[derive(Debug, Serialize)]
-#[serde(tag = "type", content = "content")]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "content"))]
 pub enum ErrorType {
     /// An error due to reading or writing from the file system.
     IOError(String),
     /// An error due to trying to perform an operation on an unsupported file type.
     UnsupportedFileType(Box<Path>), // TODO: Should this return just the file extension instead?
+    /// An error due to reading a file as a type that no carrier handles, such as one defined
+    /// outside of this crate that was never registered.
+    UnregisteredFileType(SupportedFileType),
     /// An error due to trying to encode a secret file into a base file too small to contain it.
     BaseFileNotBigEnough {
         /// The amount of space available in the base file for encoding.
@@ -113,12 +120,30 @@ pub enum ErrorType {
         /// The amount of space the secret file requires to be encoded into.
         secret_file_size: u64,
     },
+    /// An error due to a file's contents being a different type than its extension says.
+    FileTypeMismatch {
+        /// The type of file the extension says it is, or that it was said to be when read from a
+        /// reader.
+        from_extension: SupportedFileType,
+        /// The type of file its contents are.
+        from_contents: SupportedFileType,
+    },
+    /// An error due to asking for a number of bits per channel the base file can't be encoded with.
+    UnsupportedBitsPerChannel(u8),
     /// An error due to trying to perform an operation with the same file serving multiple roles.
     DuplicateFiles(WhichDuplicates),
     /// An error due to an image file in an uninterpretable format.
     ImageError(String),
+    /// An error due to an audio file in an uninterpretable format.
+    AudioError(String),
     /// An error due to an encoded file in an uninterpretable format.
     CorruptedFile(CorruptionType),
+    /// An error due to trying to decode an encrypted secret file without a password.
+    PasswordRequired,
+    /// An error due to the password not matching the one the secret file was encrypted with.
+    WrongPassword,
+    /// An error due to the job being cancelled before it finished.
+    Cancelled,
 }
 
 impl From<IOError> for ErrorType {
@@ -137,8 +162,15 @@ fn from(value: ImageError) -> Self {
     }
 }
 
+impl From<CorruptionType> for ErrorType {
+    fn from(value: CorruptionType) -> Self {
+        ErrorType::CorruptedFile(value)
+    }
+}
+
 /// Represents different sets of files that may be duplicates when encoding.
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum WhichDuplicates {
     BaseAndSecret,
     SecretAndOutput,
@@ -147,10 +179,29 @@ pub enum WhichDuplicates {
 }
 
 /// Represents how an encoded file was corrupted.
-#[derive(Debug, Serialize)]
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum CorruptionType {
     /// The header to the secret data was in an uninterpretable format.
     IncorrectHeader,
     /// The encoded file is too small to contain data.
     FileTooSmallForHeader,
+    /// The encoded file doesn't contain a payload hidden by this program.
+    NoPayloadFound,
+    /// The secret file's name and content type couldn't be read.
+    IncorrectMetadata,
+    /// The parameters needed to decrypt the secret file couldn't be read.
+    IncorrectEncryptionParameters,
+    /// The secret file couldn't be decompressed.
+    IncorrectCompression,
+    /// The secret file doesn't match the checksum it was hidden with, so it was damaged after
+    /// being hidden.
+    ChecksumMismatch,
+    /// The files hidden together couldn't be read back out of their archive.
+    IncorrectArchive,
+    /// A file hidden in an archive has a path that would be written outside of the output
+    /// directory. Contains the path.
+    UnsafeArchivePath(String),
+    /// The payload was written by a newer version of this program. Contains the payload's version.
+    UnsupportedVersion(u8),
 }
@@ -0,0 +1,489 @@
+use crate::{
+    header::{Header, HEADER_BYTES, REDUNDANT_HEADER_COPIES},
+    CorruptionType, Redundancy,
+};
+
+/// The most bytes a Reed-Solomon codeword over bytes can have, including its parity bytes.
+const CODEWORD_BYTES: usize = 255;
+
+/// The exponents and logarithms of the elements of GF(2^8), built from the primitive polynomial
+/// x^8 + x^4 + x^3 + x^2 + 1 with 2 as the generator.
+struct Field {
+    /// Twice as long as needed, so the sum of two logarithms can be looked up directly.
+    exp: [u8; 512],
+    log: [u8; 256],
+}
+
+const FIELD: Field = Field::new();
+
+impl Field {
+    const fn new() -> Field {
+        let mut exp = [0; 512];
+        let mut log = [0; 256];
+        let mut value: u16 = 1;
+        let mut power = 0;
+        while power < 255 {
+            exp[power] = value as u8;
+            exp[power + 255] = value as u8;
+            log[value as usize] = power as u8;
+            value <<= 1;
+            if value & 0x100 != 0 {
+                value ^= 0x11D;
+            }
+            power += 1;
+        }
+
+        Field { exp, log }
+    }
+
+    fn mul(&self, a: u8, b: u8) -> u8 {
+        if a == 0 || b == 0 {
+            return 0;
+        }
+        self.exp[self.log[a as usize] as usize + self.log[b as usize] as usize]
+    }
+
+    /// Divides a by b, which must not be zero.
+    fn div(&self, a: u8, b: u8) -> u8 {
+        if a == 0 {
+            return 0;
+        }
+        self.exp[self.log[a as usize] as usize + 255 - self.log[b as usize] as usize]
+    }
+
+    /// The generator raised to the power.
+    fn alpha(&self, power: usize) -> u8 {
+        self.exp[power % 255]
+    }
+}
+
+impl Redundancy {
+    /// The header flag recording that a payload was encoded with this redundancy, if any.
+    pub fn flag(self) -> u16 {
+        match self {
+            Redundancy::None => 0,
+            Redundancy::Low => Header::LOW_REDUNDANCY,
+            Redundancy::Medium => Header::MEDIUM_REDUNDANCY,
+            Redundancy::High => Header::HIGH_REDUNDANCY,
+        }
+    }
+
+    /// Finds the redundancy a payload was encoded with from its header's flags.
+    pub fn from_flags(flags: u16) -> Result<Redundancy, CorruptionType> {
+        match flags & Header::REDUNDANCY_FLAGS {
+            0 => Ok(Redundancy::None),
+            Header::LOW_REDUNDANCY => Ok(Redundancy::Low),
+            Header::MEDIUM_REDUNDANCY => Ok(Redundancy::Medium),
+            Header::HIGH_REDUNDANCY => Ok(Redundancy::High),
+            _ => Err(CorruptionType::IncorrectHeader),
+        }
+    }
+
+    /// The number of parity bytes added to each codeword, which can correct half as many
+    /// damaged bytes.
+    fn parity_bytes(self) -> usize {
+        match self {
+            Redundancy::None => 0,
+            Redundancy::Low => 16,
+            Redundancy::Medium => 32,
+            Redundancy::High => 64,
+        }
+    }
+}
+
+/// The number of bytes of data each codeword of a payload with the given size holds, with any
+/// extra bytes going to the first codewords.
+fn data_lengths(data_bytes: usize, codewords: usize) -> impl Iterator<Item = usize> {
+    (0..codewords)
+        .map(move |codeword| data_bytes / codewords + (codeword < data_bytes % codewords) as usize)
+}
+
+/// The size of the largest payload that fits in the given number of bytes after the header once
+/// encoded, along with the extra copies of the header written before it.
+pub fn data_capacity(redundancy: Redundancy, capacity: u64) -> u64 {
+    let parity = redundancy.parity_bytes() as u64;
+    if parity == 0 {
+        return capacity;
+    }
+    let capacity = capacity.saturating_sub((REDUNDANT_HEADER_COPIES - 1) * HEADER_BYTES);
+
+    // Each full codeword holds its share of data, and a partial one whatever its parity leaves
+    let full_codewords = capacity / CODEWORD_BYTES as u64;
+    let partial_codeword = capacity % CODEWORD_BYTES as u64;
+    full_codewords * (CODEWORD_BYTES as u64 - parity) + partial_codeword.saturating_sub(parity)
+}
+
+/// Adds Reed-Solomon parity to the payload so that damaged bytes can be corrected.
+///
+/// The payload is split evenly into codewords of at most 255 bytes, and the bytes of the
+/// codewords are interleaved. A run of damaged bytes, such as a few rows of an image, is then
+/// spread thinly across every codeword.
+pub fn encode(redundancy: Redundancy, data: &[u8]) -> Vec<u8> {
+    let parity = redundancy.parity_bytes();
+    if parity == 0 {
+        return data.to_vec();
+    }
+
+    let generator = generator_polynomial(parity);
+    let count = data.len().div_ceil(CODEWORD_BYTES - parity);
+    let mut rest = data;
+    let codewords: Vec<Vec<u8>> = data_lengths(data.len(), count)
+        .map(|length| {
+            let (data, tail) = rest.split_at(length);
+            rest = tail;
+
+            let mut codeword = data.to_vec();
+            codeword.extend(parity_of(data, &generator));
+            codeword
+        })
+        .collect();
+    log::trace!("Split the payload into {count} codewords with {parity} parity bytes each");
+
+    // The first codewords are the longest, so their columns are full
+    let longest = codewords.first().map_or(0, Vec::len);
+    (0..longest)
+        .flat_map(|column| {
+            codewords
+                .iter()
+                .filter_map(move |codeword| codeword.get(column).copied())
+        })
+        .collect()
+}
+
+/// Corrects what damage it can to a payload encoded with `encode`, and returns its data.
+///
+/// Codewords with more damage than their parity can correct are left as they are, so that the
+/// payload's checksum can report the damage.
+pub fn decode(redundancy: Redundancy, bytes: &[u8]) -> Result<Vec<u8>, CorruptionType> {
+    let parity = redundancy.parity_bytes();
+    if parity == 0 {
+        return Ok(bytes.to_vec());
+    }
+
+    let count = bytes.len().div_ceil(CODEWORD_BYTES);
+    let data_bytes = bytes
+        .len()
+        .checked_sub(count * parity)
+        .ok_or(CorruptionType::IncorrectHeader)?;
+
+    let lengths: Vec<usize> = data_lengths(data_bytes, count)
+        .map(|length| length + parity)
+        .collect();
+    let mut codewords: Vec<Vec<u8>> = lengths
+        .iter()
+        .map(|length| Vec::with_capacity(*length))
+        .collect();
+    let mut bytes = bytes.iter();
+    for column in 0..lengths.first().copied().unwrap_or_default() {
+        for (codeword, length) in codewords.iter_mut().zip(&lengths) {
+            if column < *length {
+                codeword.extend(bytes.next());
+            }
+        }
+    }
+
+    let mut corrected = 0;
+    let mut uncorrectable = 0;
+    for codeword in &mut codewords {
+        match correct(codeword, parity) {
+            Some(errors) => corrected += errors,
+            None => uncorrectable += 1,
+        }
+    }
+    if uncorrectable > 0 {
+        log::warn!("{uncorrectable} of {count} codewords had too much damage to correct");
+    }
+    log::trace!("Corrected {corrected} damaged bytes of the payload");
+
+    Ok(codewords
+        .into_iter()
+        .flat_map(|mut codeword| {
+            codeword.truncate(codeword.len() - parity);
+            codeword
+        })
+        .collect())
+}
+
+/// Whether the parity of a payload of `size` bytes could correct its end being cut off after
+/// only `read` bytes, were the missing bytes filled in.
+///
+/// Damaged bytes aren't located ahead of time, so each codeword can only correct half as many
+/// as it has parity bytes. Interleaving spreads the missing bytes across every codeword.
+pub fn can_recover_truncated(redundancy: Redundancy, size: usize, read: usize) -> bool {
+    let parity = redundancy.parity_bytes();
+    if read >= size {
+        return true;
+    }
+    let count = size.div_ceil(CODEWORD_BYTES);
+    // Checked before anything else, as the size is untrusted
+    if parity == 0 || size - read > count * (parity / 2) {
+        return false;
+    }
+    let Some(data_bytes) = size.checked_sub(count * parity) else {
+        return false;
+    };
+
+    // Every codeword has a byte in each of the first columns, and only the longest in the last
+    let lengths: Vec<usize> = data_lengths(data_bytes, count)
+        .map(|length| length + parity)
+        .collect();
+    let full_columns = lengths[count - 1];
+    lengths.iter().enumerate().all(|(codeword, length)| {
+        let read_of_codeword = if read <= full_columns * count {
+            read / count + (codeword < read % count) as usize
+        } else {
+            full_columns + (codeword < read - full_columns * count) as usize
+        };
+        length - read_of_codeword <= parity / 2
+    })
+}
+
+/// The polynomial whose roots are the first `parity` powers of the generator, with its highest
+/// power first.
+fn generator_polynomial(parity: usize) -> Vec<u8> {
+    let mut generator = vec![1];
+    for power in 0..parity {
+        let root = FIELD.alpha(power);
+        let mut next = vec![0; generator.len() + 1];
+        for (index, coefficient) in generator.iter().enumerate() {
+            next[index] ^= coefficient;
+            next[index + 1] ^= FIELD.mul(*coefficient, root);
+        }
+        generator = next;
+    }
+
+    generator
+}
+
+/// The remainder of dividing the data, shifted past the parity, by the generator polynomial.
+fn parity_of(data: &[u8], generator: &[u8]) -> Vec<u8> {
+    let mut remainder = vec![0; generator.len() - 1];
+    for byte in data {
+        let feedback = byte ^ remainder[0];
+        remainder.rotate_left(1);
+        *remainder.last_mut().unwrap() = 0;
+        for (term, coefficient) in remainder.iter_mut().zip(&generator[1..]) {
+            *term ^= FIELD.mul(feedback, *coefficient);
+        }
+    }
+
+    remainder
+}
+
+/// Evaluates a polynomial with its lowest power first.
+fn evaluate(polynomial: &[u8], x: u8) -> u8 {
+    polynomial
+        .iter()
+        .rev()
+        .fold(0, |sum, coefficient| FIELD.mul(sum, x) ^ coefficient)
+}
+
+/// Finds the syndromes of a codeword, which are all zero if it's undamaged.
+fn syndromes_of(codeword: &[u8], parity: usize) -> Vec<u8> {
+    (0..parity)
+        .map(|power| {
+            let x = FIELD.alpha(power);
+            codeword
+                .iter()
+                .fold(0, |sum, byte| FIELD.mul(sum, x) ^ byte)
+        })
+        .collect()
+}
+
+/// Finds the error locator polynomial from the syndromes with the Berlekamp-Massey algorithm,
+/// with its lowest power first. Its degree is the number of damaged bytes.
+fn error_locator(syndromes: &[u8]) -> Vec<u8> {
+    let mut locator = vec![1];
+    let mut previous = vec![1];
+    let mut errors = 0;
+    let mut shift = 1;
+    let mut previous_discrepancy = 1;
+
+    for (step, syndrome) in syndromes.iter().enumerate() {
+        let discrepancy = (1..=errors).fold(*syndrome, |discrepancy, index| {
+            let coefficient = locator.get(index).copied().unwrap_or_default();
+            discrepancy ^ FIELD.mul(coefficient, syndromes[step - index])
+        });
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+
+        let scale = FIELD.div(discrepancy, previous_discrepancy);
+        let mut next = locator.clone();
+        next.resize(next.len().max(previous.len() + shift), 0);
+        for (index, coefficient) in previous.iter().enumerate() {
+            next[index + shift] ^= FIELD.mul(scale, *coefficient);
+        }
+
+        if 2 * errors <= step {
+            previous = std::mem::replace(&mut locator, next);
+            errors = step + 1 - errors;
+            previous_discrepancy = discrepancy;
+            shift = 1;
+        } else {
+            locator = next;
+            shift += 1;
+        }
+    }
+
+    locator.resize(errors + 1, 0);
+    locator
+}
+
+/// Corrects the damaged bytes of a codeword in place, returning how many there were, or None if
+/// there were too many to correct.
+fn correct(codeword: &mut [u8], parity: usize) -> Option<usize> {
+    let syndromes = syndromes_of(codeword, parity);
+    if syndromes.iter().all(|syndrome| *syndrome == 0) {
+        return Some(0);
+    }
+
+    let locator = error_locator(&syndromes);
+    let errors = locator.len() - 1;
+    if errors * 2 > parity {
+        return None;
+    }
+
+    // The byte at each index is the coefficient of x to the power of its distance from the end
+    let length = codeword.len();
+    let positions: Vec<usize> = (0..length)
+        .filter(|index| evaluate(&locator, FIELD.alpha(255 - (length - 1 - index) % 255)) == 0)
+        .collect();
+    if positions.len() != errors {
+        return None;
+    }
+
+    // Forney's algorithm, using the error evaluator and the formal derivative of the locator
+    let mut evaluator = vec![0; parity];
+    for (i, syndrome) in syndromes.iter().enumerate() {
+        for (j, coefficient) in locator.iter().enumerate().take(parity - i) {
+            evaluator[i + j] ^= FIELD.mul(*syndrome, *coefficient);
+        }
+    }
+    let derivative: Vec<u8> = locator
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(power, coefficient)| if power % 2 == 1 { *coefficient } else { 0 })
+        .collect();
+
+    for index in positions {
+        let location = FIELD.alpha(length - 1 - index);
+        let inverse = FIELD.div(1, location);
+        let denominator = evaluate(&derivative, inverse);
+        if denominator == 0 {
+            return None;
+        }
+        let magnitude = FIELD.mul(location, evaluate(&evaluator, inverse));
+        codeword[index] ^= FIELD.div(magnitude, denominator);
+    }
+
+    syndromes_of(codeword, parity)
+        .iter()
+        .all(|syndrome| *syndrome == 0)
+        .then_some(errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error_correction::{can_recover_truncated, data_capacity, decode, encode, CODEWORD_BYTES},
+        header::HEADER_BYTES,
+        Redundancy,
+    };
+
+    /// The number of bytes a payload of the given size takes up once encoded.
+    fn encoded_size(redundancy: Redundancy, data_bytes: u64) -> u64 {
+        let parity = redundancy.parity_bytes() as u64;
+        let codewords = data_bytes.div_ceil(CODEWORD_BYTES as u64 - parity);
+        data_bytes + codewords * parity
+    }
+
+    /// Bytes that don't repeat in a way that could hide a mistake.
+    fn data(length: usize) -> Vec<u8> {
+        (0..length)
+            .map(|index| (index * 151 + index / 7) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn sizes_match_encoding() {
+        for redundancy in [Redundancy::Low, Redundancy::Medium, Redundancy::High] {
+            for length in [0, 1, 100, 239, 240, 1000] {
+                let encoded = encode(redundancy, &data(length));
+                assert_eq!(
+                    encoded.len() as u64,
+                    encoded_size(redundancy, length as u64)
+                );
+                assert_eq!(decode(redundancy, &encoded).unwrap(), data(length));
+            }
+
+            // The extra copies of the header take up space too
+            let header_copies = 2 * HEADER_BYTES;
+            for capacity in [100, 255, 1000, 5000] {
+                let fits = data_capacity(redundancy, capacity + header_copies);
+                assert!(encoded_size(redundancy, fits) <= capacity);
+                assert!(encoded_size(redundancy, fits + 1) > capacity);
+            }
+            assert_eq!(data_capacity(redundancy, header_copies), 0);
+        }
+
+        assert_eq!(encode(Redundancy::None, &data(10)), data(10));
+    }
+
+    #[test]
+    fn corrects_scattered_damage() {
+        let mut encoded = encode(Redundancy::Low, &data(1000));
+        // Five codewords, each of which can correct eight damaged bytes
+        for index in (0..encoded.len()).step_by(37) {
+            encoded[index] ^= 0x5A;
+        }
+
+        assert_eq!(decode(Redundancy::Low, &encoded).unwrap(), data(1000));
+    }
+
+    #[test]
+    fn corrects_a_run_of_damage() {
+        let mut encoded = encode(Redundancy::Medium, &data(2000));
+        // Interleaving spreads the run across every codeword
+        encoded[500..580].fill(0);
+
+        assert_eq!(decode(Redundancy::Medium, &encoded).unwrap(), data(2000));
+    }
+
+    #[test]
+    fn leaves_too_much_damage_alone() {
+        let mut encoded = encode(Redundancy::Low, &data(200));
+        encoded[..40].fill(0xFF);
+
+        let decoded = decode(Redundancy::Low, &encoded).unwrap();
+        assert_eq!(decoded.len(), 200);
+        assert_ne!(decoded, data(200));
+    }
+
+    #[test]
+    fn recovers_a_truncated_end() {
+        for redundancy in [Redundancy::Low, Redundancy::Medium, Redundancy::High] {
+            for length in [10, 239, 1000] {
+                let encoded = encode(redundancy, &data(length));
+                let recoverable = (0..=encoded.len())
+                    .rev()
+                    .take_while(|read| can_recover_truncated(redundancy, encoded.len(), *read))
+                    .last()
+                    .unwrap();
+
+                let mut padded = encoded[..recoverable].to_vec();
+                padded.resize(encoded.len(), 0);
+                assert_eq!(decode(redundancy, &padded).unwrap(), data(length));
+                assert!(!can_recover_truncated(
+                    redundancy,
+                    encoded.len(),
+                    recoverable - 1
+                ));
+            }
+        }
+
+        assert!(!can_recover_truncated(Redundancy::None, 100, 99));
+    }
+}
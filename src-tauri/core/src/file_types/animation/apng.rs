@@ -0,0 +1,768 @@
+use std::{
+    io::{BufWriter, Write},
+    ops::RangeInclusive,
+};
+
+use png::{AnimationControl, BitDepth, ColorType, FrameControl, Transformations};
+
+use crate::{
+    base_context, encoded_context,
+    error::ErrorType,
+    file_types::{
+        bits::{join_bits, split_bits},
+        carrier::Carrier,
+        image::{alpha_flags, chunks, Opacity},
+        supported_file::SupportedFileType,
+    },
+    header::{Algorithm, Header, HEADER_BYTES},
+    output_context,
+    payload::SealedPayload,
+    progress::Progress,
+    slot_order::SlotOrder,
+    AlphaMode, CorruptionType, DecodeOptions, EncodeOptions, Result,
+};
+
+/// The numbers of least significant bits per channel that can be used to store data.
+///
+/// Past four bits, the changes to the frames become plainly visible.
+const BITS_PER_CHANNEL: RangeInclusive<u8> = 1..=4;
+
+/// Every frame of an APNG, in the PNG's own colour type and bit depth.
+struct Animation {
+    width: u32,
+    height: u32,
+    color_type: ColorType,
+    bit_depth: BitDepth,
+    trns: Option<Vec<u8>>,
+    animation_control: AnimationControl,
+    frames: Vec<AnimationFrame>,
+}
+
+/// A frame's pixels along with how it is placed and timed.
+struct AnimationFrame {
+    /// Missing for a default image that isn't part of the animation.
+    control: Option<FrameControl>,
+    data: Vec<u8>,
+}
+
+/// Converts an error from the png crate, keeping IO errors as they are.
+pub fn png_error(error: png::DecodingError) -> ErrorType {
+    match error {
+        png::DecodingError::IoError(error) => error.into(),
+        error => ErrorType::ImageError(error.to_string()),
+    }
+}
+
+/// Hides data in the least significant bits of every frame of an animated PNG.
+pub struct ApngCarrier;
+
+impl Carrier for ApngCarrier {
+    fn file_type(&self) -> SupportedFileType {
+        SupportedFileType::Apng
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["png", "apng"]
+    }
+
+    fn detect(&self, contents: &[u8]) -> bool {
+        is_animated(contents)
+    }
+
+    fn capacity(&self, contents: &[u8], options: &EncodeOptions) -> Result<u64> {
+        base_context!(available_size_of(
+            contents,
+            options.bits_per_channel,
+            options.alpha
+        ))
+    }
+
+    fn embed(
+        &self,
+        contents: &[u8],
+        payload: &SealedPayload,
+        options: &EncodeOptions,
+        progress: &Progress,
+        output: &mut dyn Write,
+    ) -> Result<()> {
+        let mut encoded = vec![];
+        encode(
+            contents,
+            payload,
+            options,
+            &SlotOrder::new(options.key.as_deref()),
+            progress,
+            &mut encoded,
+        )?;
+
+        // Only the animation is written out, so the rest of the PNG is copied over
+        let encoded = base_context!(chunks::copy_ancillary_chunks(
+            contents,
+            &encoded,
+            options.strip_metadata
+        ))?;
+        output_context!(output.write_all(&encoded))
+    }
+
+    fn extract(
+        &self,
+        contents: &[u8],
+        options: &DecodeOptions,
+        progress: &Progress,
+    ) -> Result<(Header, Vec<u8>)> {
+        decode(contents, &SlotOrder::new(options.key.as_deref()), progress)
+    }
+}
+
+/// Returns true if the contents are a PNG with an animation control chunk.
+///
+/// Only the chunks before the image data are read.
+pub fn is_animated(contents: &[u8]) -> bool {
+    let animated = png::Decoder::new(contents)
+        .read_info()
+        .is_ok_and(|reader| reader.info().animation_control.is_some());
+    log::trace!("Checked whether a PNG is animated: {animated}");
+
+    animated
+}
+
+/// Reads every frame of the APNG.
+fn read_animation(contents: &[u8]) -> std::result::Result<Animation, ErrorType> {
+    let mut decoder = png::Decoder::new(contents);
+    decoder.set_transformations(Transformations::IDENTITY);
+    let mut reader = decoder.read_info().map_err(png_error)?;
+
+    let info = reader.info();
+    if !matches!(info.bit_depth, BitDepth::Eight | BitDepth::Sixteen)
+        || info.color_type == ColorType::Indexed
+    {
+        return Err(ErrorType::ImageError(
+            "Only 8 and 16 bit APNGs without a palette can be used".to_owned(),
+        ));
+    }
+    let animation_control = info
+        .animation_control
+        .ok_or_else(|| ErrorType::ImageError("The PNG isn't animated".to_owned()))?;
+
+    // A default image without its own frame control is shown before the animation, as well as
+    // the frames the animation control counts
+    let default_image_is_frame = info.frame_control.is_some();
+    let image_count = animation_control.num_frames as usize + !default_image_is_frame as usize;
+
+    let mut animation = Animation {
+        width: info.width,
+        height: info.height,
+        color_type: info.color_type,
+        bit_depth: info.bit_depth,
+        trns: info.trns.as_deref().map(<[u8]>::to_vec),
+        animation_control,
+        frames: vec![],
+    };
+    for image_index in 0..image_count {
+        let mut data = vec![0; reader.output_buffer_size()];
+        let output_info = reader.next_frame(&mut data).map_err(png_error)?;
+        data.truncate(output_info.buffer_size());
+
+        animation.frames.push(AnimationFrame {
+            control: reader
+                .info()
+                .frame_control
+                .filter(|_| image_index > 0 || default_image_is_frame),
+            data,
+        });
+    }
+    log::trace!("Read {} frames of APNG", animation.frames.len());
+
+    Ok(animation)
+}
+
+impl Animation {
+    /// The number of bytes in each sample.
+    fn bytes_per_sample(&self) -> usize {
+        match self.bit_depth {
+            BitDepth::Sixteen => 2,
+            _ => 1,
+        }
+    }
+
+    /// Whether each pixel has an alpha channel.
+    fn has_alpha(&self) -> bool {
+        matches!(self.color_type, ColorType::GrayscaleAlpha | ColorType::Rgba)
+    }
+
+    /// The number of channels in each pixel that aren't an alpha channel.
+    fn non_alpha_channels(&self) -> usize {
+        self.color_type.samples() - self.has_alpha() as usize
+    }
+
+    /// Finds the opacity of every pixel, frame by frame. None if the frames have no alpha
+    /// channel.
+    fn opacity(&self) -> Option<Vec<Opacity>> {
+        if !self.has_alpha() {
+            return None;
+        }
+
+        let bytes_per_sample = self.bytes_per_sample();
+        let pixel_bytes = self.color_type.samples() * bytes_per_sample;
+        let opacity = self
+            .frames
+            .iter()
+            .flat_map(|frame| frame.data.chunks_exact(pixel_bytes))
+            .map(|pixel| {
+                let alpha = &pixel[pixel_bytes - bytes_per_sample..];
+                if alpha.iter().all(|byte| *byte == 0) {
+                    Opacity::Transparent
+                } else if alpha.iter().all(|byte| *byte == u8::MAX) {
+                    Opacity::Opaque
+                } else {
+                    Opacity::Translucent
+                }
+            })
+            .collect();
+
+        Some(opacity)
+    }
+
+    /// Iterates over the byte holding the lowest bits of every channel used by a payload with the
+    /// given header flags, frame by frame.
+    ///
+    /// Only the channels that aren't alpha are used, unless the flags include the alpha channel.
+    /// The flags can also leave out pixels depending on their opacity, which is needed if they
+    /// do. Samples are big endian, so this is the last byte of each sample.
+    fn low_bytes_mut<'a>(
+        &'a mut self,
+        opacity: Option<&'a [Opacity]>,
+        flags: u16,
+    ) -> impl Iterator<Item = &'a mut u8> {
+        let bytes_per_sample = self.bytes_per_sample();
+        let pixel_bytes = self.color_type.samples() * bytes_per_sample;
+        let channel_bytes = if flags & Header::ALPHA_CHANNEL != 0 {
+            pixel_bytes
+        } else {
+            self.non_alpha_channels() * bytes_per_sample
+        };
+        let is_used = move |pixel: usize| {
+            let transparent = opacity.is_some_and(|opacity| opacity[pixel] == Opacity::Transparent);
+            if flags & Header::SKIPS_TRANSPARENT != 0 {
+                !transparent
+            } else if flags & Header::ONLY_TRANSPARENT != 0 {
+                transparent
+            } else {
+                true
+            }
+        };
+
+        self.frames
+            .iter_mut()
+            .flat_map(move |frame| frame.data.chunks_exact_mut(pixel_bytes))
+            .enumerate()
+            .filter(move |(pixel, _)| is_used(*pixel))
+            .flat_map(move |(_, pixel)| {
+                pixel[..channel_bytes]
+                    .chunks_exact_mut(bytes_per_sample)
+                    .map(|sample| sample.last_mut().unwrap())
+            })
+    }
+
+    /// The header flags the frames can hold a payload with, in the order they're tried.
+    fn alpha_flag_choices(&self) -> &'static [u16] {
+        if self.has_alpha() {
+            &[
+                0,
+                Header::ALPHA_CHANNEL,
+                Header::SKIPS_TRANSPARENT,
+                Header::ONLY_TRANSPARENT,
+            ]
+        } else {
+            &[0]
+        }
+    }
+
+    /// Writes the animation out as an APNG with the same frames and timing.
+    fn write(&self, output: &mut dyn Write) -> std::result::Result<(), png::EncodingError> {
+        let mut encoder = png::Encoder::new(BufWriter::new(output), self.width, self.height);
+        encoder.set_color(self.color_type);
+        encoder.set_depth(self.bit_depth);
+        if let Some(trns) = &self.trns {
+            encoder.set_trns(trns.as_slice());
+        }
+        encoder.set_animated(
+            self.animation_control.num_frames,
+            self.animation_control.num_plays,
+        )?;
+        encoder.set_sep_def_img(
+            self.frames
+                .first()
+                .is_some_and(|frame| frame.control.is_none()),
+        )?;
+
+        let mut writer = encoder.write_header()?;
+        for frame in &self.frames {
+            if let Some(control) = frame.control {
+                // The position is reset first, as a frame has to fit inside of the image
+                // at its old position when its dimensions change
+                writer.reset_frame_position()?;
+                writer.set_frame_dimension(control.width, control.height)?;
+                writer.set_frame_position(control.x_offset, control.y_offset)?;
+                writer.set_frame_delay(control.delay_num, control.delay_den)?;
+                writer.set_dispose_op(control.dispose_op)?;
+                writer.set_blend_op(control.blend_op)?;
+            }
+            writer.write_image_data(&frame.data)?;
+        }
+
+        writer.finish()
+    }
+}
+
+/// Returns an error if the frames can't be encoded with the number of bits per channel.
+fn check_bits_per_channel(bits_per_channel: u8) -> std::result::Result<(), ErrorType> {
+    if BITS_PER_CHANNEL.contains(&bits_per_channel) {
+        Ok(())
+    } else {
+        Err(ErrorType::UnsupportedBitsPerChannel(bits_per_channel))
+    }
+}
+
+/// Finds the amount of space in bytes, that can be used to store a secret file.
+///
+/// Every channel except for the alpha channel, of every frame, provides `bits_per_channel` bits.
+/// The alpha mode can add the alpha channel, or leave out pixels depending on their transparency.
+pub fn available_size_of(
+    contents: &[u8],
+    bits_per_channel: u8,
+    alpha: AlphaMode,
+) -> std::result::Result<u64, ErrorType> {
+    check_bits_per_channel(bits_per_channel)?;
+
+    let mut animation = read_animation(contents)?;
+    let opacity = animation.opacity();
+    let flags = alpha_flags(opacity.as_deref(), alpha);
+    let slot_count = animation.low_bytes_mut(opacity.as_deref(), flags).count() as u64;
+    log::trace!("Found {slot_count} usable channels in an APNG");
+
+    Ok((slot_count * bits_per_channel as u64 / 8).saturating_sub(HEADER_BYTES))
+}
+
+/// Encodes the payload into the base APNG, and writes the results to the output file.
+///
+/// The payload is stored after its header `bits_per_channel` bits at a time, in the order given
+/// by the slot order. The header records which channels and pixels the alpha mode chose.
+pub fn encode(
+    base_image: &[u8],
+    payload: &SealedPayload,
+    options: &EncodeOptions,
+    order: &SlotOrder,
+    progress: &Progress,
+    output_image: &mut dyn Write,
+) -> Result<()> {
+    log::info!("Beginning the encoding process into an APNG");
+
+    let bits_per_channel = options.bits_per_channel;
+    base_context!(check_bits_per_channel(bits_per_channel))?;
+    let mut animation = base_context!(read_animation(base_image))?;
+    log::trace!("Parsed the base APNG");
+
+    let opacity = animation.opacity();
+    let flags = alpha_flags(opacity.as_deref(), options.alpha);
+    let payload =
+        payload.with_carrier_flags(Algorithm::LeastSignificantBits, bits_per_channel, flags);
+
+    let mut channels: Vec<&mut u8> = animation.low_bytes_mut(opacity.as_deref(), flags).collect();
+    let mask = (1 << bits_per_channel) - 1;
+    let secret_data = progress.track(
+        split_bits(&payload, bits_per_channel),
+        bits_per_channel,
+        payload.len() as u64,
+    );
+    for (slot, secret_bits) in order.iter(channels.len() as u64).zip(secret_data) {
+        let value = &mut channels[slot as usize];
+        **value = (**value & !mask) | secret_bits;
+    }
+    output_context!(progress.check())?;
+
+    log::trace!("Updated the frames with the secret file's data");
+
+    output_context!(animation.write(output_image).map_err(|error| match error {
+        png::EncodingError::IoError(error) => error.into(),
+        error => ErrorType::ImageError(error.to_string()),
+    }))
+}
+
+/// Decodes the encoded APNG, returning the header and the payload that follows it.
+///
+/// The slot order must match the one the payload was encoded with. The number of bits per
+/// channel, and the channels and pixels the alpha mode chose, are found by looking for a header
+/// that was written with them.
+pub fn decode(
+    encoded_image: &[u8],
+    order: &SlotOrder,
+    progress: &Progress,
+) -> Result<(Header, Vec<u8>)> {
+    log::info!("Beginning the decoding process from an APNG");
+
+    let mut animation = encoded_context!(read_animation(encoded_image))?;
+    log::trace!("Parsed the encoded APNG");
+
+    let opacity = animation.opacity();
+    let mut header_error = CorruptionType::NoPayloadFound;
+    for &flags in animation.alpha_flag_choices() {
+        let channels: Vec<u8> = animation
+            .low_bytes_mut(opacity.as_deref(), flags)
+            .map(|value| *value)
+            .collect();
+        let channels = &channels;
+        let found = Header::find(
+            BITS_PER_CHANNEL,
+            Algorithm::LeastSignificantBits,
+            |bits_per_channel| {
+                let mask = (1 << bits_per_channel) - 1;
+                let chunks = order
+                    .iter(channels.len() as u64)
+                    .map(move |slot| channels[slot as usize] & mask);
+                join_bits(chunks, bits_per_channel)
+            },
+        );
+
+        match found {
+            // The channels chosen by different alpha modes can start in the same places
+            Ok((header, _)) if header.flags & Header::ALPHA_FLAGS != flags => {}
+            Ok((header, secret_data)) => {
+                log::trace!(
+                    "Decoded the header. Payload size of {} stored with {} bits per channel",
+                    header.payload_size,
+                    header.depth
+                );
+
+                let payload =
+                    header.read_payload(progress.track(secret_data, 8, header.payload_size));
+                encoded_context!(progress.check())?;
+                let payload = encoded_context!(payload)?;
+                log::trace!("Read the payload from the APNG");
+
+                return Ok((header, payload));
+            }
+            Err(CorruptionType::NoPayloadFound) => {}
+            // Too few transparent pixels for a header just means that mode wasn't used
+            Err(CorruptionType::FileTooSmallForHeader) if flags != 0 => {}
+            Err(err) => header_error = err,
+        };
+    }
+
+    encoded_context!(Err(header_error))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, path::PathBuf};
+
+    use png::{BlendOp, ColorType, DisposeOp, ScaledFloat};
+
+    use crate::{
+        base_context, encoded_context,
+        file_types::{
+            animation::apng::{
+                available_size_of, decode, encode, is_animated, read_animation, ApngCarrier,
+            },
+            carrier::Carrier,
+            supported_file::{SupportedFile, SupportedFileType},
+        },
+        header::HEADER_BYTES,
+        output_context,
+        payload::Payload,
+        progress::Progress,
+        slot_order::SlotOrder,
+        AlphaMode, DecodeOptions, EncodeOptions, Result,
+    };
+
+    fn test_data_dir() -> PathBuf {
+        "./test_data".into()
+    }
+
+    /// Writes an APNG with a separate default image, then frames of different sizes and timings.
+    /// It has a gamma and a text chunk, which aren't part of the animation.
+    fn base_apng(name: &str) -> PathBuf {
+        let path = test_data_dir().join(format!("{name}.result.png"));
+        let (width, height) = (40, 30);
+
+        let mut encoder = png::Encoder::new(File::create(&path).unwrap(), width, height);
+        encoder.set_color(ColorType::Rgba);
+        encoder.set_source_gamma(ScaledFloat::new(1.0 / 2.2));
+        encoder
+            .add_text_chunk("Author".to_owned(), "Someone".to_owned())
+            .unwrap();
+        encoder.set_animated(3, 2).unwrap();
+        encoder.set_sep_def_img(true).unwrap();
+        let mut writer = encoder.write_header().unwrap();
+
+        let pixels = |width: u32, height: u32, seed: u32| -> Vec<u8> {
+            (0..width * height * 4)
+                .map(|index| (index.wrapping_mul(seed) % 251) as u8)
+                .collect()
+        };
+        writer.write_image_data(&pixels(width, height, 3)).unwrap();
+        for frame in 0..3 {
+            let (frame_width, frame_height) = (width - frame * 10, height - frame * 10);
+            writer.reset_frame_position().unwrap();
+            writer
+                .set_frame_dimension(frame_width, frame_height)
+                .unwrap();
+            writer.set_frame_position(frame * 5, frame * 5).unwrap();
+            writer.set_frame_delay(frame as u16 + 1, 10).unwrap();
+            writer.set_dispose_op(DisposeOp::Background).unwrap();
+            writer.set_blend_op(BlendOp::Over).unwrap();
+            writer
+                .write_image_data(&pixels(frame_width, frame_height, frame + 5))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        path
+    }
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let base_path = base_apng("apng_round_trip");
+        let output_path = base_path.with_extension("encoded.png");
+        let payload = Payload::new("secret.txt", b"Hidden across the frames".to_vec());
+
+        let base_image = base_context!(std::fs::read(&base_path))?;
+        assert_eq!(
+            base_context!(SupportedFile::open(&base_path))?.file_type(),
+            SupportedFileType::Apng
+        );
+
+        let options = EncodeOptions {
+            bits_per_channel: 2,
+            ..Default::default()
+        };
+        let mut output_image = output_context!(File::create(&output_path))?;
+        encode(
+            &base_image,
+            &payload.seal(&options),
+            &options,
+            &SlotOrder::keyed("key"),
+            &Progress::default(),
+            &mut output_image,
+        )?;
+
+        let encoded_image = encoded_context!(std::fs::read(&output_path))?;
+        assert!(is_animated(&encoded_image));
+        let (header, bytes) = decode(
+            &encoded_image,
+            &SlotOrder::keyed("key"),
+            &Progress::default(),
+        )?;
+        let decoded = encoded_context!(Payload::unseal(&header, bytes, &DecodeOptions::default()))?;
+        assert_eq!(decoded, payload);
+
+        // The animation itself is unchanged
+        let base = encoded_context!(read_animation(&base_image))?;
+        let encoded = encoded_context!(read_animation(&encoded_image))?;
+        let plays = |animation: &super::Animation| {
+            (
+                animation.animation_control.num_frames,
+                animation.animation_control.num_plays,
+            )
+        };
+        assert_eq!(plays(&encoded), plays(&base));
+        assert_eq!(base.frames.len(), encoded.frames.len());
+        for (base, encoded) in base.frames.iter().zip(&encoded.frames) {
+            let control = |frame: &super::AnimationFrame| {
+                frame.control.map(|control| {
+                    (
+                        control.width,
+                        control.height,
+                        control.x_offset,
+                        control.y_offset,
+                        control.delay_num,
+                        control.delay_den,
+                        control.dispose_op,
+                        control.blend_op,
+                    )
+                })
+            };
+            assert_eq!(control(base), control(encoded));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_ancillary_chunks() -> Result<()> {
+        let base_path = base_apng("apng_chunks");
+        let base_image = base_context!(std::fs::read(&base_path))?;
+        let payload = Payload::new("secret.txt", b"Hidden across the frames".to_vec());
+
+        for strip_metadata in [false, true] {
+            let options = EncodeOptions {
+                strip_metadata,
+                ..Default::default()
+            };
+            let mut encoded_image = vec![];
+            ApngCarrier.embed(
+                &base_image,
+                &payload.seal(&options),
+                &options,
+                &Progress::default(),
+                &mut encoded_image,
+            )?;
+
+            let reader = png::Decoder::new(encoded_image.as_slice())
+                .read_info()
+                .unwrap();
+            let info = reader.info();
+            assert!(info.animation_control.is_some());
+            assert_eq!(info.source_gamma, Some(ScaledFloat::new(1.0 / 2.2)));
+            assert_eq!(info.uncompressed_latin1_text.is_empty(), strip_metadata);
+
+            let (header, bytes) = ApngCarrier.extract(
+                &encoded_image,
+                &DecodeOptions::default(),
+                &Progress::default(),
+            )?;
+            let decoded =
+                encoded_context!(Payload::unseal(&header, bytes, &DecodeOptions::default()))?;
+            assert_eq!(decoded, payload);
+        }
+
+        Ok(())
+    }
+
+    /// Writes a two frame APNG whose left half is fully transparent, unless it's opaque.
+    fn half_transparent_apng(name: &str, opaque: bool) -> Vec<u8> {
+        let (width, height) = (32, 32);
+        let mut apng = vec![];
+        let mut encoder = png::Encoder::new(&mut apng, width, height);
+        encoder.set_color(ColorType::Rgba);
+        encoder.set_animated(2, 0).unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        for frame in 0..2 {
+            let pixels: Vec<u8> = (0..width * height)
+                .flat_map(|pixel| {
+                    let (x, y) = (pixel % width, pixel / width);
+                    let alpha = if opaque || x >= 16 { 255 } else { 0 };
+                    [(x * 8) as u8, (y * 8) as u8, frame * 100, alpha]
+                })
+                .collect();
+            writer.write_image_data(&pixels).unwrap();
+        }
+        writer.finish().unwrap();
+
+        std::fs::write(test_data_dir().join(format!("{name}.result.png")), &apng).unwrap();
+        apng
+    }
+
+    /// Checks that a secret survives being encoded into the APNG with the alpha mode, then returns
+    /// every pixel of every frame before and after.
+    fn assert_alpha_round_trip(apng: &[u8], alpha: AlphaMode) -> Result<(Vec<u8>, Vec<u8>)> {
+        let options = EncodeOptions {
+            alpha,
+            ..Default::default()
+        };
+        let payload = Payload::new("secret.txt", b"Hidden by the alpha mode".to_vec());
+        let mut encoded_image = vec![];
+        ApngCarrier.embed(
+            apng,
+            &payload.seal(&options),
+            &options,
+            &Progress::default(),
+            &mut encoded_image,
+        )?;
+
+        let (header, bytes) = ApngCarrier.extract(
+            &encoded_image,
+            &DecodeOptions::default(),
+            &Progress::default(),
+        )?;
+        let decoded = encoded_context!(Payload::unseal(&header, bytes, &DecodeOptions::default()))?;
+        assert_eq!(decoded, payload);
+
+        let pixels_of = |animation: super::Animation| -> Vec<u8> {
+            animation
+                .frames
+                .into_iter()
+                .flat_map(|frame| frame.data)
+                .collect()
+        };
+        Ok((
+            pixels_of(base_context!(read_animation(apng))?),
+            pixels_of(encoded_context!(read_animation(&encoded_image))?),
+        ))
+    }
+
+    #[test]
+    fn alpha_modes_change_the_space() -> Result<()> {
+        let size_of = |apng: &[u8], alpha| base_context!(available_size_of(apng, 2, alpha));
+        let opaque = half_transparent_apng("apng_opaque", true);
+        let half_transparent = half_transparent_apng("apng_half_transparent", false);
+
+        // Each of the 2048 pixels has 6 bits in its colour channels and 8 with its alpha channel
+        assert_eq!(
+            size_of(&opaque, AlphaMode::ColourOnly)?,
+            2048 * 6 / 8 - HEADER_BYTES
+        );
+        assert_eq!(
+            size_of(&opaque, AlphaMode::IncludeAlpha)?,
+            2048 * 8 / 8 - HEADER_BYTES
+        );
+        assert_eq!(
+            size_of(&half_transparent, AlphaMode::IncludeAlpha)?,
+            2048 * 6 / 8 - HEADER_BYTES
+        );
+        assert_eq!(
+            size_of(&half_transparent, AlphaMode::SkipTransparent)?,
+            1024 * 6 / 8 - HEADER_BYTES
+        );
+        assert_eq!(
+            size_of(&half_transparent, AlphaMode::OnlyTransparent)?,
+            1024 * 6 / 8 - HEADER_BYTES
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn include_alpha_round_trip() -> Result<()> {
+        let opaque = half_transparent_apng("apng_include_alpha", true);
+        let (base, encoded) = assert_alpha_round_trip(&opaque, AlphaMode::IncludeAlpha)?;
+        let alphas =
+            |pixels: &[u8]| -> Vec<u8> { pixels.iter().skip(3).step_by(4).copied().collect() };
+        assert_ne!(alphas(&base), alphas(&encoded));
+
+        Ok(())
+    }
+
+    #[test]
+    fn skip_transparent_round_trip() -> Result<()> {
+        let half_transparent = half_transparent_apng("apng_skip_transparent", false);
+        let (base, encoded) =
+            assert_alpha_round_trip(&half_transparent, AlphaMode::SkipTransparent)?;
+        assert_ne!(base, encoded);
+        for (base, encoded) in base.chunks(4).zip(encoded.chunks(4)) {
+            if base[3] == 0 {
+                assert_eq!(
+                    base, encoded,
+                    "Transparent pixels should be left as they are"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn only_transparent_round_trip() -> Result<()> {
+        let half_transparent = half_transparent_apng("apng_only_transparent", false);
+        let (base, encoded) =
+            assert_alpha_round_trip(&half_transparent, AlphaMode::OnlyTransparent)?;
+        assert_ne!(base, encoded);
+        for (base, encoded) in base.chunks(4).zip(encoded.chunks(4)) {
+            if base[3] != 0 {
+                assert_eq!(base, encoded, "Visible pixels should be left as they are");
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,498 @@
+use std::io::{BufWriter, Write};
+
+use gif::{ColorOutput, Frame, Repeat};
+use image::ImageFormat;
+
+use crate::{
+    base_context, encoded_context,
+    error::ErrorType,
+    file_types::{
+        bits::{join_bits, split_bits},
+        carrier::Carrier,
+        supported_file::SupportedFileType,
+    },
+    header::{Algorithm, Header, HEADER_BYTES},
+    output_context,
+    payload::SealedPayload,
+    progress::Progress,
+    slot_order::SlotOrder,
+    CorruptionType, DecodeOptions, EncodeOptions, Result,
+};
+
+/// Every frame of a GIF, as palette indices.
+struct Animation {
+    width: u16,
+    height: u16,
+    global_palette: Option<Vec<u8>>,
+    repeat: Repeat,
+    frames: Vec<Frame<'static>>,
+}
+
+/// Converts an error from the gif crate, keeping IO errors as they are.
+fn gif_error(error: gif::DecodingError) -> ErrorType {
+    match error {
+        gif::DecodingError::Io(error) => error.into(),
+        error => ErrorType::ImageError(error.to_string()),
+    }
+}
+
+/// Hides data in the palette indices of every frame of a GIF.
+pub struct GifCarrier;
+
+impl Carrier for GifCarrier {
+    fn file_type(&self) -> SupportedFileType {
+        SupportedFileType::Gif
+    }
+
+    fn extensions(&self) -> &[&str] {
+        ImageFormat::Gif.extensions_str()
+    }
+
+    fn detect(&self, contents: &[u8]) -> bool {
+        ::image::guess_format(contents).is_ok_and(|format| format == ImageFormat::Gif)
+    }
+
+    fn capacity(&self, contents: &[u8], _options: &EncodeOptions) -> Result<u64> {
+        // GIFs always store a single bit per pixel
+        base_context!(available_size_of(contents))
+    }
+
+    fn embed(
+        &self,
+        contents: &[u8],
+        payload: &SealedPayload,
+        options: &EncodeOptions,
+        progress: &Progress,
+        output: &mut dyn Write,
+    ) -> Result<()> {
+        encode(
+            contents,
+            &payload.with_header(Algorithm::PaletteIndices, 1),
+            &SlotOrder::new(options.key.as_deref()),
+            progress,
+            output,
+        )
+    }
+
+    fn extract(
+        &self,
+        contents: &[u8],
+        options: &DecodeOptions,
+        progress: &Progress,
+    ) -> Result<(Header, Vec<u8>)> {
+        decode(contents, &SlotOrder::new(options.key.as_deref()), progress)
+    }
+}
+
+/// Reads every frame of the GIF.
+fn read_animation(contents: &[u8]) -> std::result::Result<Animation, ErrorType> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(ColorOutput::Indexed);
+    let mut decoder = options.read_info(contents).map_err(gif_error)?;
+
+    let mut animation = Animation {
+        width: decoder.width(),
+        height: decoder.height(),
+        global_palette: decoder.global_palette().map(<[u8]>::to_vec),
+        repeat: decoder.repeat(),
+        frames: vec![],
+    };
+    while let Some(frame) = decoder.read_next_frame().map_err(gif_error)? {
+        animation.frames.push(frame.clone());
+    }
+    log::trace!("Read {} frames of GIF", animation.frames.len());
+
+    Ok(animation)
+}
+
+/// Whether a pixel can hold a bit of the payload.
+///
+/// Changing the lowest bit of a palette index swaps between a pair of colours, so pixels whose
+/// pair includes the transparent colour are skipped to keep transparency the same.
+fn is_usable(index: u8, transparent: Option<u8>) -> bool {
+    transparent.is_none_or(|transparent| transparent >> 1 != index >> 1)
+}
+
+/// Iterates over every pixel that can hold a bit of the payload, frame by frame.
+fn usable_pixels<'a>(frames: &'a [Frame]) -> impl Iterator<Item = &'a u8> {
+    frames.iter().flat_map(|frame| {
+        frame
+            .buffer
+            .iter()
+            .filter(|index| is_usable(**index, frame.transparent))
+    })
+}
+
+/// Sorts the palette by brightness and pads it to the size it will be written with, returning
+/// where each old index moved to.
+///
+/// Sorting means the pair of colours an index's lowest bit chooses between look alike, so that
+/// the payload is hard to see. The padding repeats the brightest colour, so a colour paired with
+/// the padding looks the same whichever of the two is chosen.
+fn sort_palette(palette: &mut Vec<u8>) -> [u8; 256] {
+    let colours: Vec<[u8; 3]> = palette
+        .chunks_exact(3)
+        .take(256)
+        .map(|colour| [colour[0], colour[1], colour[2]])
+        .collect();
+    let size = colours.len().clamp(2, 256).next_power_of_two();
+
+    let mut order: Vec<usize> = (0..colours.len()).collect();
+    order.sort_by_key(|index| {
+        let [red, green, blue] = colours[*index].map(u32::from);
+        299 * red + 587 * green + 114 * blue
+    });
+
+    // Indices past the end of the palette aren't moved
+    let mut moved_to: [u8; 256] = std::array::from_fn(|index| index as u8);
+    palette.clear();
+    for (new_index, old_index) in order.into_iter().enumerate() {
+        palette.extend(colours[old_index]);
+        moved_to[old_index] = new_index as u8;
+    }
+    let brightest = palette.last_chunk::<3>().copied().unwrap_or_default();
+    while palette.len() < size * 3 {
+        palette.extend(brightest);
+    }
+
+    moved_to
+}
+
+/// Sorts every palette of the animation, moving the frames' indices to match.
+fn sort_palettes(animation: &mut Animation) {
+    let global_moved_to = animation.global_palette.as_mut().map(sort_palette);
+    for frame in &mut animation.frames {
+        let moved_to = match (&mut frame.palette, &global_moved_to) {
+            (Some(palette), _) => sort_palette(palette),
+            (None, Some(global_moved_to)) => *global_moved_to,
+            (None, None) => continue,
+        };
+        remap_frame(frame, &moved_to);
+
+        // Frames are decoded into rows in order, which is how they are written back out
+        frame.interlaced = false;
+    }
+}
+
+/// Moves a frame's indices to match its sorted palette.
+fn remap_frame(frame: &mut Frame, moved_to: &[u8; 256]) {
+    for index in frame.buffer.to_mut() {
+        *index = moved_to[*index as usize];
+    }
+    frame.transparent = frame.transparent.map(|index| moved_to[index as usize]);
+}
+
+/// Finds the amount of space in bytes, that can be used to store a secret file.
+///
+/// Every pixel of every frame provides a single bit, except those near the transparent colour
+/// once the palettes are sorted the way `encode` sorts them.
+pub fn available_size_of(contents: &[u8]) -> std::result::Result<u64, ErrorType> {
+    let mut animation = read_animation(contents)?;
+    sort_palettes(&mut animation);
+    let usable_pixels = usable_pixels(&animation.frames).count() as u64;
+    log::trace!("Found {usable_pixels} usable pixels in a GIF");
+
+    Ok((usable_pixels / 8).saturating_sub(HEADER_BYTES))
+}
+
+/// Encodes the payload into the base GIF, and writes the results to the output file.
+///
+/// The payload is expected to start with its header, and is stored in the lowest bit of each
+/// usable pixel's palette index, in the order given by the slot order.
+pub fn encode(
+    base_image: &[u8],
+    payload: &[u8],
+    order: &SlotOrder,
+    progress: &Progress,
+    output_image: &mut dyn Write,
+) -> Result<()> {
+    log::info!("Beginning the encoding process into a GIF");
+
+    let mut animation = base_context!(read_animation(base_image))?;
+    log::trace!("Parsed the base GIF");
+
+    sort_palettes(&mut animation);
+    log::trace!("Sorted the palettes by brightness");
+
+    let mut pixels: Vec<&mut u8> = animation
+        .frames
+        .iter_mut()
+        .flat_map(|frame| {
+            let transparent = frame.transparent;
+            frame
+                .buffer
+                .to_mut()
+                .iter_mut()
+                .filter(move |index| is_usable(**index, transparent))
+        })
+        .collect();
+
+    // The payload is checked against the capacity before encoding, so this is only a safeguard
+    // against a payload being silently cut short
+    let available_bits = pixels.len() as u64;
+    if available_bits < payload.len() as u64 * 8 {
+        return base_context!(Err(ErrorType::BaseFileNotBigEnough {
+            available_size: (available_bits / 8).saturating_sub(HEADER_BYTES),
+            secret_file_size: (payload.len() as u64).saturating_sub(HEADER_BYTES),
+        }));
+    }
+
+    let secret_data = progress.track(split_bits(payload, 1), 1, payload.len() as u64);
+    for (slot, bit) in order.iter(pixels.len() as u64).zip(secret_data) {
+        let index = &mut pixels[slot as usize];
+        **index = (**index & !1) | bit;
+    }
+    output_context!(progress.check())?;
+
+    log::trace!("Updated the frames with the secret file's data");
+
+    let write = |animation: &Animation| -> std::result::Result<(), gif::EncodingError> {
+        let mut encoder = gif::Encoder::new(
+            BufWriter::new(output_image),
+            animation.width,
+            animation.height,
+            animation.global_palette.as_deref().unwrap_or_default(),
+        )?;
+        // A GIF without a loop count plays once, which is what is read back as zero repeats
+        if animation.repeat != Repeat::Finite(0) {
+            encoder.set_repeat(animation.repeat)?;
+        }
+        for frame in &animation.frames {
+            encoder.write_frame(frame)?;
+        }
+
+        Ok(())
+    };
+    output_context!(write(&animation).map_err(|error| match error {
+        gif::EncodingError::Io(error) => error.into(),
+        error => ErrorType::ImageError(error.to_string()),
+    }))
+}
+
+/// Decodes the encoded GIF, returning the header and the payload that follows it.
+///
+/// The slot order must match the one the payload was encoded with.
+pub fn decode(
+    encoded_image: &[u8],
+    order: &SlotOrder,
+    progress: &Progress,
+) -> Result<(Header, Vec<u8>)> {
+    log::info!("Beginning the decoding process from a GIF");
+
+    let animation = encoded_context!(read_animation(encoded_image))?;
+    log::trace!("Parsed the encoded GIF");
+
+    let pixels: Vec<u8> = usable_pixels(&animation.frames)
+        .map(|index| index & 1)
+        .collect();
+    let bits = order
+        .iter(pixels.len() as u64)
+        .map(|slot| pixels[slot as usize]);
+    let mut secret_data = join_bits(bits, 1);
+
+    let header = encoded_context!(Header::from_bytes(&mut secret_data))?;
+    if header.algorithm != Algorithm::PaletteIndices {
+        return encoded_context!(Err(CorruptionType::IncorrectHeader));
+    }
+    log::trace!(
+        "Decoded the header. Payload size of {}",
+        header.payload_size
+    );
+
+    let payload = header.read_payload(progress.track(secret_data, 8, header.payload_size));
+    encoded_context!(progress.check())?;
+    let payload = encoded_context!(payload)?;
+    log::trace!("Read the payload from the GIF");
+
+    Ok((header, payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, path::PathBuf};
+
+    use gif::{DisposalMethod, Frame, Repeat};
+
+    use crate::{
+        base_context, encoded_context,
+        file_types::animation::gif::{
+            available_size_of, decode, encode, read_animation, sort_palette,
+        },
+        header::{Algorithm, Header, HEADER_BYTES},
+        output_context,
+        payload::Payload,
+        progress::Progress,
+        slot_order::SlotOrder,
+        DecodeOptions, EncodeOptions, ErrorType, Result,
+    };
+
+    fn test_data_dir() -> PathBuf {
+        "./test_data".into()
+    }
+
+    /// Writes an animated GIF whose frames have different timings, palettes and transparency.
+    fn base_gif(name: &str) -> PathBuf {
+        let path = test_data_dir().join(format!("{name}.result.gif"));
+        let global_palette: Vec<u8> = (0..16u8)
+            .flat_map(|index| [index * 16, 255 - index * 16, 128])
+            .collect();
+
+        let mut encoder =
+            gif::Encoder::new(File::create(&path).unwrap(), 64, 48, &global_palette).unwrap();
+        encoder.set_repeat(Repeat::Finite(3)).unwrap();
+        for frame_index in 0..4u16 {
+            let (width, height) = (64 - frame_index * 8, 48 - frame_index * 8);
+            let pixels: Vec<u8> = (0..width as usize * height as usize)
+                .map(|pixel| ((pixel * 7 + frame_index as usize) % 16) as u8)
+                .collect();
+
+            let mut frame = Frame::from_indexed_pixels(width, height, pixels, Some(3));
+            frame.delay = 10 * (frame_index + 1);
+            frame.dispose = match frame_index {
+                0 => DisposalMethod::Keep,
+                1 => DisposalMethod::Background,
+                _ => DisposalMethod::Previous,
+            };
+            frame.left = frame_index * 4;
+            frame.top = frame_index * 4;
+            // The last frame brings its own palette
+            if frame_index == 3 {
+                frame.palette = Some(global_palette.iter().rev().copied().collect());
+            }
+            encoder.write_frame(&frame).unwrap();
+        }
+
+        path
+    }
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let base_path = base_gif("gif_round_trip");
+        let output_path = base_path.with_extension("encoded.gif");
+        let payload = Payload::new("secret.txt", b"Hidden across the frames".to_vec());
+
+        let base_image = base_context!(std::fs::read(&base_path))?;
+        let mut output_image = output_context!(File::create(&output_path))?;
+        encode(
+            &base_image,
+            &payload
+                .seal(&EncodeOptions::default())
+                .with_header(Algorithm::PaletteIndices, 1),
+            &SlotOrder::keyed("key"),
+            &Progress::default(),
+            &mut output_image,
+        )?;
+
+        let encoded_image = encoded_context!(std::fs::read(&output_path))?;
+        let (header, bytes) = decode(
+            &encoded_image,
+            &SlotOrder::keyed("key"),
+            &Progress::default(),
+        )?;
+        let decoded = encoded_context!(Payload::unseal(&header, bytes, &DecodeOptions::default()))?;
+        assert_eq!(decoded, payload);
+
+        // The animation itself is unchanged
+        let base = encoded_context!(read_animation(&base_image))?;
+        let encoded = encoded_context!(read_animation(&encoded_image))?;
+        assert_eq!(encoded.repeat, Repeat::Finite(3));
+        assert_eq!(base.frames.len(), encoded.frames.len());
+        for (base, encoded) in base.frames.iter().zip(&encoded.frames) {
+            assert_eq!(
+                (
+                    base.delay,
+                    base.dispose,
+                    base.left,
+                    base.top,
+                    base.width,
+                    base.height
+                ),
+                (
+                    encoded.delay,
+                    encoded.dispose,
+                    encoded.left,
+                    encoded.top,
+                    encoded.width,
+                    encoded.height
+                )
+            );
+            assert_eq!(base.transparent.is_some(), encoded.transparent.is_some());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn capacity_is_the_sum_over_frames() -> std::result::Result<(), ErrorType> {
+        let file = std::fs::read(base_gif("gif_capacity"))?;
+
+        // Pixels using the transparent colour, or the colour paired with it, can't be used
+        let usable_pixels: usize = (0..4)
+            .map(|frame_index| {
+                let pixels = (64 - frame_index * 8) * (48 - frame_index * 8);
+                (0..pixels)
+                    .filter(|pixel| !matches!((pixel * 7 + frame_index) % 16, 2 | 3))
+                    .count()
+            })
+            .sum();
+        assert_eq!(
+            available_size_of(&file)?,
+            usable_pixels as u64 / 8 - HEADER_BYTES
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn padding_repeats_the_brightest_colour() {
+        let mut palette = vec![200, 200, 200, 0, 0, 0, 100, 100, 100];
+        let moved_to = sort_palette(&mut palette);
+
+        assert_eq!(
+            palette,
+            [0, 0, 0, 100, 100, 100, 200, 200, 200, 200, 200, 200]
+        );
+        assert_eq!(moved_to[..4], [2, 0, 1, 3]);
+    }
+
+    #[test]
+    fn capacity_matches_the_space_encode_finds() -> Result<()> {
+        // Sorting the greys by brightness pairs the transparent colour with a different one
+        let palette = [0, 0, 0, 200, 200, 200, 100, 100, 100, 150, 150, 150];
+        let mut base_image = vec![];
+        let mut encoder = gif::Encoder::new(&mut base_image, 64, 48, &palette).unwrap();
+        let pixels = (0..64 * 48)
+            .map(|pixel| if pixel % 3 == 2 { 0 } else { 3 })
+            .collect::<Vec<u8>>();
+        let frame = Frame::from_indexed_pixels(64, 48, pixels, Some(1));
+        encoder.write_frame(&frame).unwrap();
+        drop(encoder);
+
+        // Only the pixels using the darkest grey are usable once it's paired with the middle one
+        let capacity = base_context!(available_size_of(&base_image))?;
+        assert_eq!(capacity, 64 * 48 / 3 / 8 - HEADER_BYTES);
+
+        let hide = |size: u64| {
+            let mut payload = Header::new(Algorithm::PaletteIndices, 1, size)
+                .to_bytes()
+                .to_vec();
+            payload.extend((0..size).map(|index| index as u8));
+            encode(
+                &base_image,
+                &payload,
+                &SlotOrder::keyed("key"),
+                &Progress::default(),
+                &mut vec![],
+            )
+        };
+
+        assert!(hide(capacity).is_ok());
+        let error = hide(capacity + 1).unwrap_err();
+        assert!(matches!(
+            error.error_type,
+            ErrorType::BaseFileNotBigEnough { .. }
+        ));
+
+        Ok(())
+    }
+}
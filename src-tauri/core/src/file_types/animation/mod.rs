@@ -0,0 +1,7 @@
+//! Carriers for animated images, which spread a payload over every frame in order.
+//!
+//! Unlike our image module, these keep each frame's timing and disposal as they were, rather
+//! than only keeping the first frame.
+
+pub mod apng;
+pub mod gif;
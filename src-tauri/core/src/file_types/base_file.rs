@@ -0,0 +1,103 @@
+use std::{
+    io::{Read, Seek, Write},
+    path::Path,
+};
+
+use crate::{
+    base_context, error_correction,
+    file_types::supported_file::{SupportedFile, SupportedFileType},
+    payload::SealedPayload,
+    progress::Progress,
+    EncodeOptions, ErrorType, Result,
+};
+
+/// A collection of properties about the base file.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FileProperties {
+    pub available_space: u64,
+    pub file_type: SupportedFileType,
+}
+
+/// A base file is a file that will have another secret file encoded into it.
+pub struct BaseFile {
+    file: SupportedFile,
+}
+
+impl BaseFile {
+    /// Opens a file that will be used as the base of an encoded file.
+    pub fn open(file_path: impl AsRef<Path>) -> Result<BaseFile> {
+        base_context!(SupportedFile::open(file_path)).map(|file| BaseFile { file })
+    }
+
+    /// Reads a base file of the given type from a reader, from its start.
+    pub fn from_reader(reader: impl Read + Seek, file_type: SupportedFileType) -> Result<BaseFile> {
+        base_context!(SupportedFile::from_reader(reader, file_type)).map(|file| BaseFile { file })
+    }
+
+    /// Returns the number of bytes available to encode a file with the given options.
+    ///
+    /// Any error correction is stored in the same space, so it's left out of the bytes available.
+    pub fn available_space(&self, options: &EncodeOptions) -> Result<u64> {
+        let capacity = self
+            .file
+            .carrier()
+            .capacity(self.file.contents(), options)?;
+        Ok(error_correction::data_capacity(
+            options.redundancy,
+            capacity,
+        ))
+    }
+
+    /// Gets the properties of the BaseFile when encoding with the given options.
+    pub fn get_properties(&self, options: &EncodeOptions) -> Result<FileProperties> {
+        Ok(FileProperties {
+            available_space: self.available_space(options)?,
+            file_type: self.file.file_type(),
+        })
+    }
+
+    /// Encodes the secret payload into this base file and outputs the results.
+    ///
+    /// Nothing is written to the output if the progress is cancelled.
+    pub fn encode_to(
+        &mut self,
+        payload: &SealedPayload,
+        options: &EncodeOptions,
+        progress: &Progress,
+        output_file: &mut impl Write,
+    ) -> Result<()> {
+        let available_size = self.available_space(options)?;
+
+        // The secret's name, content type and any encryption parameters are stored alongside it,
+        // so they count towards its size
+        let secret_file_size = payload.size();
+
+        if secret_file_size > available_size {
+            log::debug!("Cancelling encoding due to lack of space in base file. {secret_file_size}/{available_size}");
+            return base_context!(Err(ErrorType::BaseFileNotBigEnough {
+                available_size,
+                secret_file_size,
+            }));
+        }
+
+        log::trace!("Verified that the base file is large enough to hide the secret file");
+
+        self.file.carrier().embed(
+            self.file.contents(),
+            payload,
+            options,
+            progress,
+            output_file,
+        )
+    }
+}
+
+/// Gets the properties of a file including its type and how much space is available when
+/// encoding with the given options.
+pub fn get_properties(
+    base_file_path: impl AsRef<Path>,
+    options: &EncodeOptions,
+) -> Result<FileProperties> {
+    BaseFile::open(base_file_path)?.get_properties(options)
+}
@@ -0,0 +1,67 @@
+/// Splits bytes into chunks of `bits` bits, starting from the most significant bits of each byte.
+///
+/// If the bits don't divide evenly, the final chunk is padded with zeros.
+pub fn split_bits(bytes: &[u8], bits: u8) -> impl Iterator<Item = u8> + '_ {
+    debug_assert!((1..=8).contains(&bits));
+    let bits = bits as usize;
+    let mask = (1 << bits) - 1;
+
+    (0..(bytes.len() * 8).div_ceil(bits)).map(move |chunk| {
+        // A chunk can straddle two bytes, so we read it from a 16 bit window
+        let start = chunk * bits;
+        let high = bytes[start / 8] as u16;
+        let low = bytes.get(start / 8 + 1).copied().unwrap_or_default() as u16;
+        let window = (high << 8) | low;
+
+        ((window >> (16 - start % 8 - bits)) & mask) as u8
+    })
+}
+
+/// Joins chunks of `bits` bits back into bytes, the reverse of `split_bits`.
+///
+/// Any bits left over at the end that don't make up a full byte are discarded.
+pub fn join_bits(chunks: impl Iterator<Item = u8>, bits: u8) -> impl Iterator<Item = u8> {
+    debug_assert!((1..=8).contains(&bits));
+    let mut chunks = chunks;
+    let mut buffer: u16 = 0;
+    let mut buffered_bits = 0;
+
+    std::iter::from_fn(move || {
+        while buffered_bits < 8 {
+            buffer = (buffer << bits) | chunks.next()? as u16;
+            buffered_bits += bits;
+        }
+
+        buffered_bits -= 8;
+        let byte = (buffer >> buffered_bits) as u8;
+        buffer &= (1 << buffered_bits) - 1;
+
+        Some(byte)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file_types::bits::{join_bits, split_bits};
+
+    #[test]
+    fn split_and_join() {
+        let bytes = b"Any bit depth should round trip";
+        for bits in 1..=8 {
+            let chunks: Vec<u8> = split_bits(bytes, bits).collect();
+
+            assert!(chunks.iter().all(|chunk| (*chunk as u16) < 1 << bits));
+            assert_eq!(
+                join_bits(chunks.into_iter(), bits).collect::<Vec<_>>(),
+                bytes
+            );
+        }
+    }
+
+    #[test]
+    fn splits_most_significant_bits_first() {
+        let chunks: Vec<u8> = split_bits(&[0b1011_0010], 3).collect();
+
+        assert_eq!(chunks, [0b101, 0b100, 0b100]);
+    }
+}
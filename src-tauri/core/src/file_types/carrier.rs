@@ -0,0 +1,283 @@
+use std::{io::Write, sync::RwLock};
+
+use image::ImageFormat;
+
+use crate::{
+    file_types::{
+        animation::{apng::ApngCarrier, gif::GifCarrier},
+        image::ImageCarrier,
+        jpeg::JpegCarrier,
+        supported_file::SupportedFileType,
+        wav::WavCarrier,
+    },
+    header::Header,
+    payload::SealedPayload,
+    progress::Progress,
+    DecodeOptions, EncodeOptions, Result,
+};
+
+/// A type of file that secret files can be hidden in.
+///
+/// Carriers are given the whole contents of a file. Adding a carrier to the registry with
+/// `register_carrier` lets files of its type be used everywhere a file path or reader is taken.
+pub trait Carrier: Send + Sync {
+    /// The type of file this carrier handles.
+    fn file_type(&self) -> SupportedFileType;
+
+    /// The extensions, in lower case, that files of this type are saved with.
+    ///
+    /// Used to find the type of files whose contents aren't recognised by any carrier, and to
+    /// check that a file's extension agrees with its contents.
+    fn extensions(&self) -> &[&str];
+
+    /// Returns true if the contents are a file of this type, usually from their magic bytes.
+    fn detect(&self, contents: &[u8]) -> bool;
+
+    /// Finds the number of bytes of sealed payload that can be hidden with the given options.
+    fn capacity(&self, contents: &[u8], options: &EncodeOptions) -> Result<u64>;
+
+    /// Hides the payload, along with a header written with `SealedPayload::with_header`, and
+    /// writes the resulting file to the output.
+    ///
+    /// The payload is never larger than the capacity found with the same options. Its chunks
+    /// should be taken through `Progress::track`, and nothing written if the progress is
+    /// cancelled.
+    fn embed(
+        &self,
+        contents: &[u8],
+        payload: &SealedPayload,
+        options: &EncodeOptions,
+        progress: &Progress,
+        output: &mut dyn Write,
+    ) -> Result<()>;
+
+    /// Finds the header and the sealed payload that follows it in the contents.
+    ///
+    /// The bytes of the payload should be taken through `Progress::track`, and a Cancelled error
+    /// returned if the progress is cancelled.
+    fn extract(
+        &self,
+        contents: &[u8],
+        options: &DecodeOptions,
+        progress: &Progress,
+    ) -> Result<(Header, Vec<u8>)>;
+}
+
+/// Carriers added with `register_carrier`, checked before the built-in carriers.
+static REGISTERED: RwLock<Vec<&'static dyn Carrier>> = RwLock::new(Vec::new());
+
+/// The carriers that are always available.
+///
+/// PNGs come before animated PNGs, so that a `.png` extension on its own means a still image.
+static BUILT_IN: [&dyn Carrier; 11] = [
+    &ImageCarrier::new(SupportedFileType::Png, ImageFormat::Png),
+    &ImageCarrier::new(SupportedFileType::Bmp, ImageFormat::Bmp),
+    &ImageCarrier::new(SupportedFileType::Tiff, ImageFormat::Tiff),
+    &ImageCarrier::new(SupportedFileType::Tga, ImageFormat::Tga),
+    &ImageCarrier::new(SupportedFileType::Qoi, ImageFormat::Qoi),
+    &ImageCarrier::new(SupportedFileType::Pnm, ImageFormat::Pnm),
+    &ImageCarrier::new(SupportedFileType::WebP, ImageFormat::WebP),
+    &JpegCarrier,
+    &GifCarrier,
+    &ApngCarrier,
+    &WavCarrier,
+];
+
+/// Adds a carrier to the registry.
+///
+/// Registered carriers are checked before the built-in ones, most recently registered first, so
+/// a carrier can replace the handling of a built-in file type. Registration lasts for the rest
+/// of the process, as there's no way to remove a carrier once it's been added.
+pub fn register_carrier(carrier: &'static dyn Carrier) {
+    log::info!("Registering a carrier for {:?}", carrier.file_type());
+    REGISTERED
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .insert(0, carrier);
+}
+
+/// Every carrier, in the order they are checked.
+pub fn carriers() -> Vec<&'static dyn Carrier> {
+    let registered = REGISTERED
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    registered.iter().chain(&BUILT_IN).copied().collect()
+}
+
+/// Finds the carrier that recognises the contents.
+pub fn from_contents(contents: &[u8]) -> Option<&'static dyn Carrier> {
+    carriers()
+        .into_iter()
+        .find(|carrier| carrier.detect(contents))
+}
+
+/// Finds the carrier for files saved with the extension, ignoring case.
+pub fn from_extension(extension: &str) -> Option<&'static dyn Carrier> {
+    carriers()
+        .into_iter()
+        .find(|carrier| has_extension(*carrier, extension))
+}
+
+/// Finds the carrier for the file type.
+pub fn from_file_type(file_type: SupportedFileType) -> Option<&'static dyn Carrier> {
+    carriers()
+        .into_iter()
+        .find(|carrier| carrier.file_type() == file_type)
+}
+
+/// Returns true if the carriers save files with any of the same extensions, like PNGs and
+/// animated PNGs do. A file detected as one can reasonably be named as the other.
+pub fn share_an_extension(first: &dyn Carrier, second: &dyn Carrier) -> bool {
+    first
+        .extensions()
+        .iter()
+        .any(|extension| has_extension(second, extension))
+}
+
+fn has_extension(carrier: &dyn Carrier, extension: &str) -> bool {
+    carrier
+        .extensions()
+        .iter()
+        .any(|candidate| candidate.eq_ignore_ascii_case(extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, sync::Once};
+
+    use crate::{
+        decode_bytes, encode_bytes, encoded_context, file_types::carrier::from_extension,
+        join_bits, output_context, register_carrier, split_bits, Algorithm, Carrier,
+        CorruptionType, DecodeOptions, EncodeOptions, ErrorType, Header, Progress, Result,
+        SealedPayload, SlotOrder, SupportedFileType, HEADER_BYTES,
+    };
+
+    /// Registering a carrier is permanent and affects every other test running in the process,
+    /// so the toy carrier only recognises contents no other test would have.
+    const TOY_MAGIC: &[u8] = b"\0stegosaurusography toy carrier\0";
+
+    /// Registers the toy carrier the first time any test needs it.
+    fn register_toy_carrier() {
+        static REGISTER: Once = Once::new();
+        REGISTER.call_once(|| register_carrier(&ToyCarrier));
+    }
+
+    /// A carrier like one defined outside of the crate, storing a bit in every byte after its
+    /// magic bytes.
+    struct ToyCarrier;
+
+    impl Carrier for ToyCarrier {
+        fn file_type(&self) -> SupportedFileType {
+            SupportedFileType::Other("Toy")
+        }
+
+        fn extensions(&self) -> &[&str] {
+            &["toy"]
+        }
+
+        fn detect(&self, contents: &[u8]) -> bool {
+            contents.starts_with(TOY_MAGIC)
+        }
+
+        fn capacity(&self, contents: &[u8], _options: &EncodeOptions) -> Result<u64> {
+            let slots = contents.len().saturating_sub(TOY_MAGIC.len()) as u64;
+            Ok((slots / 8).saturating_sub(HEADER_BYTES))
+        }
+
+        fn embed(
+            &self,
+            contents: &[u8],
+            payload: &SealedPayload,
+            options: &EncodeOptions,
+            progress: &Progress,
+            output: &mut dyn Write,
+        ) -> Result<()> {
+            let start = TOY_MAGIC.len().min(contents.len());
+            let mut contents = contents.to_vec();
+            let slots = &mut contents[start..];
+            let order = SlotOrder::new(options.key.as_deref());
+            let payload = payload.with_header(Algorithm::LeastSignificantBits, 1);
+            let bits = progress.track(split_bits(&payload, 1), 1, payload.len() as u64);
+            for (slot, bit) in order.iter(slots.len() as u64).zip(bits) {
+                slots[slot as usize] = (slots[slot as usize] & !1) | bit;
+            }
+            output_context!(progress.check())?;
+
+            output_context!(output.write_all(&contents))
+        }
+
+        fn extract(
+            &self,
+            contents: &[u8],
+            options: &DecodeOptions,
+            progress: &Progress,
+        ) -> Result<(Header, Vec<u8>)> {
+            let slots = contents.get(TOY_MAGIC.len()..).unwrap_or_default();
+            let order = SlotOrder::new(options.key.as_deref());
+            let bits = order
+                .iter(slots.len() as u64)
+                .map(|slot| slots[slot as usize] & 1);
+            let mut bytes = join_bits(bits, 1);
+
+            let header = encoded_context!(Header::from_bytes(&mut bytes))?;
+            let payload = header.read_payload(progress.track(bytes, 8, header.payload_size));
+            encoded_context!(progress.check())?;
+            Ok((header, encoded_context!(payload)?))
+        }
+    }
+
+    #[test]
+    fn registered_carrier_round_trip() -> Result<()> {
+        register_toy_carrier();
+        let mut base = TOY_MAGIC.to_vec();
+        base.extend((0..4096).map(|index| (index * 7) as u8));
+
+        let options = EncodeOptions {
+            key: Some("key".to_owned()),
+            ..Default::default()
+        };
+        let encoded = encode_bytes(&base, ToyCarrier.file_type(), b"secret", &options)?;
+        assert_eq!(
+            SupportedFileType::from_contents(&encoded),
+            Some(SupportedFileType::Other("Toy"))
+        );
+
+        let options = DecodeOptions {
+            key: Some("key".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            decode_bytes(&encoded, ToyCarrier.file_type(), &options)?,
+            b"secret"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn registered_carrier_is_found_by_extension() {
+        register_toy_carrier();
+
+        let carrier = from_extension("TOY").unwrap();
+        assert_eq!(carrier.file_type(), SupportedFileType::Other("Toy"));
+        assert_eq!(
+            from_extension("png").unwrap().file_type(),
+            SupportedFileType::Png
+        );
+    }
+
+    #[test]
+    fn contents_shorter_than_the_magic_hold_nothing() {
+        let options = EncodeOptions::default();
+        assert_eq!(ToyCarrier.capacity(b"toy", &options).unwrap(), 0);
+
+        let error = ToyCarrier
+            .extract(b"toy", &DecodeOptions::default(), &Progress::default())
+            .unwrap_err();
+        assert!(matches!(
+            error.error_type,
+            ErrorType::CorruptedFile(CorruptionType::FileTooSmallForHeader)
+        ));
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    io::{Read, Seek, Write},
+    path::Path,
+};
+
+use crate::{
+    archive::{Archive, ArchiveEntry, EntryKind},
+    encoded_context,
+    file_types::supported_file::{SupportedFile, SupportedFileType},
+    output_context,
+    payload::{Payload, SecretProperties},
+    progress::Progress,
+    CorruptionType, DecodeOptions, Result,
+};
+
+/// An encoded file is a file with another secret file encoded into it.
+pub struct EncodedFile {
+    file: SupportedFile,
+}
+
+impl EncodedFile {
+    /// Opens a file that has another secret file encoded in it.
+    pub fn open(file_path: impl AsRef<Path>) -> Result<EncodedFile> {
+        encoded_context!(SupportedFile::open(file_path)).map(|file| EncodedFile { file })
+    }
+
+    /// Reads an encoded file of the given type from a reader, from its start.
+    pub fn from_reader(
+        reader: impl Read + Seek,
+        file_type: SupportedFileType,
+    ) -> Result<EncodedFile> {
+        encoded_context!(SupportedFile::from_reader(reader, file_type))
+            .map(|file| EncodedFile { file })
+    }
+
+    /// Reads the properties of the secret file inside this one, without writing it anywhere.
+    ///
+    /// The properties of a damaged secret file are only returned for a best effort recovery.
+    pub fn inspect(&self, options: &DecodeOptions) -> Result<SecretProperties> {
+        self.payload(options, &Progress::default())
+            .map(|(payload, _)| payload.properties)
+    }
+
+    /// Lists the files and directories hidden inside this one, without writing them anywhere.
+    ///
+    /// A single secret file is listed under the name it would be decoded to.
+    pub fn list(&self, options: &DecodeOptions) -> Result<Vec<ArchiveEntry>> {
+        let (payload, _) = self.payload(options, &Progress::default())?;
+        if payload.properties.archive {
+            return encoded_context!(Archive::read(&payload.contents))
+                .map(|archive| archive.entries());
+        }
+
+        Ok(vec![ArchiveEntry {
+            path: payload.properties.suggested_file_name(),
+            kind: EntryKind::File,
+            size: payload.contents.len() as u64,
+            mode: None,
+        }])
+    }
+
+    /// Decodes the secret file inside this one to the output file.
+    ///
+    /// Returns the properties of the secret file, which include the name it was encoded with.
+    /// An archive of several secret files is written as it is, and its properties say so.
+    /// Nothing is written to the output if the progress is cancelled, or if the secret file was
+    /// damaged and a best effort recovery wasn't asked for.
+    pub fn decode_to(
+        &self,
+        output_file: &mut impl Write,
+        options: &DecodeOptions,
+        progress: &Progress,
+    ) -> Result<SecretProperties> {
+        let (payload, intact) = self.payload(options, progress)?;
+        output_context!(output_file.write_all(&payload.contents))?;
+        log::trace!("Wrote the secret file's contents to the output file");
+
+        if !intact {
+            return encoded_context!(Err(CorruptionType::ChecksumMismatch));
+        }
+        Ok(payload.properties)
+    }
+
+    /// Extracts the payload hidden in this file, along with whether it matched its checksum.
+    ///
+    /// A payload that doesn't match its checksum is an error unless a best effort recovery was
+    /// asked for.
+    pub(crate) fn payload(
+        &self,
+        options: &DecodeOptions,
+        progress: &Progress,
+    ) -> Result<(Payload, bool)> {
+        let (header, bytes) =
+            self.file
+                .carrier()
+                .extract(self.file.contents(), options, progress)?;
+
+        if options.best_effort {
+            encoded_context!(Payload::unseal_damaged(&header, bytes, options))
+        } else {
+            encoded_context!(Payload::unseal(&header, bytes, options))
+                .map(|payload| (payload, true))
+        }
+    }
+}
+
+/// Gets the properties of the secret file hidden inside of an encoded file.
+pub fn inspect(
+    encoded_file_path: impl AsRef<Path>,
+    options: &DecodeOptions,
+) -> Result<SecretProperties> {
+    EncodedFile::open(encoded_file_path)?.inspect(options)
+}
+
+/// Lists the files and directories hidden inside of an encoded file, without recovering them.
+pub fn list_entries(
+    encoded_file_path: impl AsRef<Path>,
+    options: &DecodeOptions,
+) -> Result<Vec<ArchiveEntry>> {
+    EncodedFile::open(encoded_file_path)?.list(options)
+}
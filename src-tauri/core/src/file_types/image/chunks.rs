@@ -0,0 +1,339 @@
+use image::codecs::png::CompressionType;
+
+use crate::error::ErrorType;
+
+/// The bytes every PNG starts with.
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Ancillary chunks that describe where the file came from rather than how to display it.
+const IDENTIFYING_CHUNKS: [&[u8; 4]; 5] = [b"tEXt", b"zTXt", b"iTXt", b"eXIf", b"tIME"];
+
+/// Ancillary chunks whose contents are only valid for the colour type and bit depth they were
+/// written for.
+const COLOUR_TYPE_CHUNKS: [&[u8; 4]; 4] = [b"tRNS", b"bKGD", b"sBIT", b"hIST"];
+
+/// Ancillary chunks that make up an APNG's animation, which a still image can't keep.
+const ANIMATION_CHUNKS: [&[u8; 4]; 3] = [b"acTL", b"fcTL", b"fdAT"];
+
+/// Standard ancillary chunks that stay valid when the image data changes, even though most of
+/// them are marked as unsafe to copy.
+const KNOWN_CHUNKS: [&[u8; 4]; 9] = [
+    b"gAMA", b"cHRM", b"sRGB", b"iCCP", b"cICP", b"mDCv", b"cLLi", b"pHYs", b"sPLT",
+];
+
+/// A chunk of a PNG, borrowed from the file it's in.
+struct Chunk<'a> {
+    kind: [u8; 4],
+    data: &'a [u8],
+    /// The whole chunk, including its length, type and CRC.
+    bytes: &'a [u8],
+}
+
+impl Chunk<'_> {
+    /// Ancillary chunks have a lowercase first letter, and images can be shown without them.
+    fn is_ancillary(&self) -> bool {
+        self.kind[0].is_ascii_lowercase()
+    }
+
+    /// Chunks with a lowercase last letter don't depend on the image data, so they can be copied
+    /// by something that doesn't understand them.
+    fn is_safe_to_copy(&self) -> bool {
+        self.kind[3].is_ascii_lowercase()
+    }
+
+    /// Whether the chunk is one of the standard ones we know how to handle.
+    fn is_known(&self) -> bool {
+        [
+            KNOWN_CHUNKS.as_slice(),
+            &IDENTIFYING_CHUNKS,
+            &COLOUR_TYPE_CHUNKS,
+            &ANIMATION_CHUNKS,
+        ]
+        .iter()
+        .any(|chunks| chunks.contains(&&self.kind))
+    }
+}
+
+/// The error used for any PNG whose chunks can't be read.
+fn malformed(reason: &str) -> ErrorType {
+    ErrorType::ImageError(format!("Malformed PNG file: {reason}"))
+}
+
+/// Splits a PNG into its chunks, without checking their contents.
+fn chunks_of(contents: &[u8]) -> Result<Vec<Chunk<'_>>, ErrorType> {
+    let mut rest = contents
+        .strip_prefix(&SIGNATURE)
+        .ok_or_else(|| malformed("missing signature"))?;
+
+    let mut chunks = vec![];
+    while !rest.is_empty() {
+        if rest.len() < 12 {
+            return Err(malformed("truncated chunk"));
+        }
+        let length = u32::from_be_bytes(rest[0..4].try_into().unwrap()) as usize;
+        let end = length
+            .checked_add(12)
+            .filter(|end| *end <= rest.len())
+            .ok_or_else(|| malformed("truncated chunk"))?;
+
+        chunks.push(Chunk {
+            kind: rest[4..8].try_into().unwrap(),
+            data: &rest[8..8 + length],
+            bytes: &rest[..end],
+        });
+        rest = &rest[end..];
+    }
+
+    Ok(chunks)
+}
+
+/// Finds the contents of the first chunk of the given type in a PNG.
+pub fn data_of<'a>(contents: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    let chunks = chunks_of(contents).ok()?;
+    chunks
+        .into_iter()
+        .find(|chunk| &chunk.kind == kind)
+        .map(|chunk| chunk.data)
+}
+
+/// Finds how hard the PNG's image data was compressed, from the level recorded in its zlib
+/// header, so the output can be compressed the same way.
+pub fn compression_of(contents: &[u8]) -> CompressionType {
+    let level = chunks_of(contents).ok().and_then(|chunks| {
+        let data = chunks.iter().find(|chunk| &chunk.kind == b"IDAT")?.data;
+        data.get(1).map(|flags| flags >> 6)
+    });
+
+    match level {
+        Some(0 | 1) => CompressionType::Fast,
+        Some(3) => CompressionType::Best,
+        _ => CompressionType::Default,
+    }
+}
+
+/// Copies the base PNG's ancillary chunks, such as colour profiles, physical dimensions and text,
+/// into the encoded PNG in the same places relative to its palette and image data. In an APNG,
+/// chunks that belong before the image data go before the first frame's control chunk too.
+///
+/// Identifying chunks are left out if `strip_metadata` is set. Chunks tied to the colour type are
+/// only copied if the encoded PNG kept the base PNG's colour type and bit depth, and chunks the
+/// encoder wrote itself are kept instead of the base PNG's chunks of the same type. Chunks we
+/// don't know are only copied if they're marked as safe to copy, as the image data they could
+/// depend on has changed.
+pub fn copy_ancillary_chunks(
+    base: &[u8],
+    encoded: &[u8],
+    strip_metadata: bool,
+) -> Result<Vec<u8>, ErrorType> {
+    let base_chunks = chunks_of(base)?;
+    let encoded_chunks = chunks_of(encoded)?;
+
+    // The bit depth and colour type are the 9th and 10th bytes of the header chunk
+    let pixel_format = |chunks: &[Chunk]| {
+        chunks
+            .first()
+            .filter(|chunk| &chunk.kind == b"IHDR")
+            .and_then(|chunk| chunk.data.get(8..10))
+            .map(<[u8]>::to_vec)
+    };
+    let same_pixel_format = pixel_format(&base_chunks) == pixel_format(&encoded_chunks);
+    let is_copied = |chunk: &Chunk| {
+        let kind = &chunk.kind;
+        let written = encoded_chunks.iter().any(|written| &written.kind == kind);
+        let dropped = ANIMATION_CHUNKS.contains(&kind)
+            || (strip_metadata && IDENTIFYING_CHUNKS.contains(&kind))
+            || (!same_pixel_format && COLOUR_TYPE_CHUNKS.contains(&kind))
+            || (!chunk.is_known() && !chunk.is_safe_to_copy());
+
+        chunk.is_ancillary() && !written && !dropped
+    };
+
+    // Some chunks must come before the palette, some after it, and some after the image data
+    let mut before_palette = vec![];
+    let mut before_data = vec![];
+    let mut after_data = vec![];
+    let mut seen_palette = false;
+    let mut seen_data = false;
+    for chunk in base_chunks {
+        match &chunk.kind {
+            b"PLTE" => seen_palette = true,
+            b"IDAT" => seen_data = true,
+            _ if !is_copied(&chunk) => {}
+            _ if seen_data => after_data.push(chunk),
+            _ if seen_palette => before_data.push(chunk),
+            _ => before_palette.push(chunk),
+        }
+    }
+    log::trace!(
+        "Copying {} ancillary chunks from the base PNG",
+        before_palette.len() + before_data.len() + after_data.len()
+    );
+
+    let mut output = Vec::with_capacity(encoded.len() + base.len().min(1 << 16));
+    output.extend_from_slice(&SIGNATURE);
+    let mut written_data = false;
+    for chunk in &encoded_chunks {
+        match &chunk.kind {
+            b"IHDR" => {
+                output.extend_from_slice(chunk.bytes);
+                before_palette
+                    .iter()
+                    .for_each(|chunk| output.extend_from_slice(chunk.bytes));
+                continue;
+            }
+            b"IDAT" | b"fcTL" if !written_data => {
+                before_data
+                    .iter()
+                    .for_each(|chunk| output.extend_from_slice(chunk.bytes));
+                written_data = true;
+            }
+            b"IEND" => after_data
+                .iter()
+                .for_each(|chunk| output.extend_from_slice(chunk.bytes)),
+            _ => {}
+        }
+        output.extend_from_slice(chunk.bytes);
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::codecs::png::CompressionType;
+
+    use crate::file_types::image::chunks::{
+        chunks_of, compression_of, copy_ancillary_chunks, data_of, SIGNATURE,
+    };
+
+    /// Builds a chunk with a valid length and CRC.
+    fn chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(data);
+        let crc = crc32fast::hash(&chunk[4..]);
+        chunk.extend_from_slice(&crc.to_be_bytes());
+        chunk
+    }
+
+    fn png(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut png = SIGNATURE.to_vec();
+        chunks.iter().for_each(|chunk| png.extend_from_slice(chunk));
+        png
+    }
+
+    fn kinds(png: &[u8]) -> Vec<[u8; 4]> {
+        chunks_of(png)
+            .unwrap()
+            .iter()
+            .map(|chunk| chunk.kind)
+            .collect()
+    }
+
+    /// A header chunk for an 8 bit image of the given colour type.
+    fn header(colour_type: u8) -> Vec<u8> {
+        chunk(b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, colour_type, 0, 0, 0])
+    }
+
+    #[test]
+    fn copies_chunks_into_place() {
+        let base = png(&[
+            header(2),
+            chunk(b"gAMA", &[0, 0, 0xB1, 0x8F]),
+            chunk(b"PLTE", &[0; 3]),
+            chunk(b"bKGD", &[0; 6]),
+            chunk(b"IDAT", &[0x78, 0xDA]),
+            chunk(b"tEXt", b"Author\0Someone"),
+            chunk(b"IEND", &[]),
+        ]);
+        let encoded = png(&[
+            header(2),
+            chunk(b"gAMA", &[0; 4]),
+            chunk(b"IDAT", &[0x78, 0x01]),
+            chunk(b"IDAT", &[0]),
+            chunk(b"IEND", &[]),
+        ]);
+
+        let output = copy_ancillary_chunks(&base, &encoded, false).unwrap();
+        assert_eq!(
+            kinds(&output),
+            [*b"IHDR", *b"gAMA", *b"bKGD", *b"IDAT", *b"IDAT", *b"tEXt", *b"IEND"]
+        );
+        // The encoder's own gamma is kept rather than the base PNG's
+        assert_eq!(data_of(&output, b"gAMA"), Some([0; 4].as_slice()));
+
+        let output = copy_ancillary_chunks(&base, &encoded, true).unwrap();
+        assert_eq!(
+            kinds(&output),
+            [*b"IHDR", *b"gAMA", *b"bKGD", *b"IDAT", *b"IDAT", *b"IEND"]
+        );
+    }
+
+    #[test]
+    fn drops_chunks_for_another_colour_type() {
+        let base = png(&[
+            header(2),
+            chunk(b"tRNS", &[0; 6]),
+            chunk(b"pHYs", &[0; 9]),
+            chunk(b"IDAT", &[0x78, 0xDA]),
+            chunk(b"IEND", &[]),
+        ]);
+        let encoded = png(&[
+            header(6),
+            chunk(b"IDAT", &[0x78, 0x01]),
+            chunk(b"IEND", &[]),
+        ]);
+
+        let output = copy_ancillary_chunks(&base, &encoded, false).unwrap();
+        assert_eq!(kinds(&output), [*b"IHDR", *b"pHYs", *b"IDAT", *b"IEND"]);
+    }
+
+    #[test]
+    fn drops_unknown_chunks_unsafe_to_copy() {
+        let base = png(&[
+            header(2),
+            chunk(b"sRGB", &[0]),
+            chunk(b"prVT", b"offsets into the image data"),
+            chunk(b"prvt", b"notes"),
+            chunk(b"IDAT", &[0x78, 0xDA]),
+            chunk(b"IEND", &[]),
+        ]);
+        let encoded = png(&[
+            header(2),
+            chunk(b"IDAT", &[0x78, 0x01]),
+            chunk(b"IEND", &[]),
+        ]);
+
+        let output = copy_ancillary_chunks(&base, &encoded, false).unwrap();
+        assert_eq!(
+            kinds(&output),
+            [*b"IHDR", *b"sRGB", *b"prvt", *b"IDAT", *b"IEND"]
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_chunks() {
+        let mut base = png(&[header(2), chunk(b"IEND", &[])]);
+        base.truncate(base.len() - 2);
+
+        assert!(copy_ancillary_chunks(&base, &base, false).is_err());
+    }
+
+    #[test]
+    fn reads_compression_level() {
+        let compressed = |flags| png(&[header(2), chunk(b"IDAT", &[0x78, flags])]);
+
+        assert!(matches!(
+            compression_of(&compressed(0x01)),
+            CompressionType::Fast
+        ));
+        assert!(matches!(
+            compression_of(&compressed(0x9C)),
+            CompressionType::Default
+        ));
+        assert!(matches!(
+            compression_of(&compressed(0xDA)),
+            CompressionType::Best
+        ));
+    }
+}
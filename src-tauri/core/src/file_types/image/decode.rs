@@ -0,0 +1,163 @@
+use std::{borrow::Cow, cell::OnceCell};
+
+use image::{ColorType, DynamicImage, GenericImageView, ImageFormat};
+
+use crate::{
+    encoded_context,
+    error::ErrorType,
+    file_types::{
+        bits::join_bits,
+        image::{image_reader, low_bit_mask, opacity_of, sample_offsets, Slots, BITS_PER_CHANNEL},
+    },
+    header::{Algorithm, Header},
+    progress::Progress,
+    slot_order::SlotOrder,
+    CorruptionType, Result,
+};
+
+/// Decodes the encoded image, returning the header and the payload that follows it.
+///
+/// The slot order must match the one the payload was encoded with. The number of bits per
+/// channel is found by looking for a header that was written with it.
+pub fn decode(
+    encoded_image: &[u8],
+    format: ImageFormat,
+    order: &SlotOrder,
+    progress: &Progress,
+) -> Result<(Header, Vec<u8>)> {
+    log::info!("Beginning the decoding process from an image");
+
+    // Getting the image that contains the secret
+    let reader = image_reader(encoded_image, format);
+    let image = encoded_context!(reader.decode())?;
+    log::trace!("Parsed the encoded image as {:?}", image.color());
+
+    // Starting by reading the header to find the payload size and bits per channel, trying each
+    // of the ways the alpha mode could have chosen the slots
+    let low_bytes = encoded_context!(LowBytes::of(&image))?;
+    let color = image.color();
+    let opacity = OnceCell::new();
+    let mut header_error = CorruptionType::NoPayloadFound;
+    for &flags in alpha_flag_choices(color) {
+        let opacity = match flags & (Header::SKIPS_TRANSPARENT | Header::ONLY_TRANSPARENT) {
+            0 => None,
+            _ => opacity.get_or_init(|| opacity_of(&image)).as_deref(),
+        };
+        let slots = Slots::with_flags(color, opacity, flags);
+
+        // The match is a statement so that the bytes it borrows from the slots are dropped first
+        match low_bytes.find_header(&slots, order) {
+            // The slots chosen by different alpha modes can start in the same places
+            Ok((header, _)) if header.flags & Header::ALPHA_FLAGS != flags => {}
+            Ok((header, secret_data)) => {
+                log::trace!(
+                    "Decoded the header. Payload size of {} stored with {} bits per channel",
+                    header.payload_size,
+                    header.depth
+                );
+
+                let payload =
+                    header.read_payload(progress.track(secret_data, 8, header.payload_size));
+                // A cancelled read ends early, which would otherwise look like a corrupted payload
+                encoded_context!(progress.check())?;
+                let payload = encoded_context!(payload)?;
+                log::trace!("Read the payload from the image");
+
+                return Ok((header, payload));
+            }
+            Err(CorruptionType::NoPayloadFound) => {}
+            // An image can have too few pixels of the right transparency to hold a header
+            Err(CorruptionType::FileTooSmallForHeader) if flags != 0 => {}
+            Err(err) => header_error = err,
+        };
+    }
+
+    encoded_context!(Err(header_error))
+}
+
+/// The header flags a payload could have been hidden in an image of the colour type with.
+fn alpha_flag_choices(color: ColorType) -> &'static [u16] {
+    if color.has_alpha() {
+        &[
+            0,
+            Header::ALPHA_CHANNEL,
+            Header::SKIPS_TRANSPARENT,
+            Header::ONLY_TRANSPARENT,
+        ]
+    } else {
+        &[0]
+    }
+}
+
+/// The lowest byte of every sample in an image, row by row, which is where the payload is kept.
+struct LowBytes<'a> {
+    bytes: Cow<'a, [u8]>,
+    dimensions: (u32, u32),
+}
+
+impl LowBytes<'_> {
+    /// The low bytes of the image's samples in their own colour type, as they are encoded.
+    fn of(image: &DynamicImage) -> std::result::Result<LowBytes<'_>, ErrorType> {
+        let bytes = match image {
+            DynamicImage::ImageLuma8(_)
+            | DynamicImage::ImageLumaA8(_)
+            | DynamicImage::ImageRgb8(_)
+            | DynamicImage::ImageRgba8(_) => Cow::Borrowed(image.as_bytes()),
+            DynamicImage::ImageLuma16(buffer) => low_bytes_of(buffer, |sample| *sample as u8),
+            DynamicImage::ImageLumaA16(buffer) => low_bytes_of(buffer, |sample| *sample as u8),
+            DynamicImage::ImageRgb16(buffer) => low_bytes_of(buffer, |sample| *sample as u8),
+            DynamicImage::ImageRgba16(buffer) => low_bytes_of(buffer, |sample| *sample as u8),
+            DynamicImage::ImageRgb32F(buffer) => {
+                low_bytes_of(buffer, |sample| sample.to_bits() as u8)
+            }
+            DynamicImage::ImageRgba32F(buffer) => {
+                low_bytes_of(buffer, |sample| sample.to_bits() as u8)
+            }
+            _ => {
+                return Err(ErrorType::ImageError(format!(
+                    "Images with {:?} samples aren't supported",
+                    image.color()
+                )))
+            }
+        };
+
+        Ok(LowBytes {
+            bytes,
+            dimensions: image.dimensions(),
+        })
+    }
+
+    /// Searches the slots for a header stored at any of the supported bits per channel, returning
+    /// it along with the bytes that follow it.
+    fn find_header<'a>(
+        &'a self,
+        slots: &'a Slots,
+        order: &'a SlotOrder,
+    ) -> std::result::Result<(Header, impl Iterator<Item = u8> + 'a), CorruptionType> {
+        Header::find(
+            BITS_PER_CHANNEL,
+            Algorithm::LeastSignificantBits,
+            |bits_per_channel| self.byte_iterator(slots, order, bits_per_channel),
+        )
+    }
+
+    /// Iterates over the bytes stored in the last `bits_per_channel` bits of every slot, in the
+    /// slot order.
+    fn byte_iterator<'a>(
+        &'a self,
+        slots: &'a Slots,
+        order: &'a SlotOrder,
+        bits_per_channel: u8,
+    ) -> impl Iterator<Item = u8> + 'a {
+        let mask = low_bit_mask(bits_per_channel);
+        let chunk_iterator = sample_offsets(self.dimensions, slots, order)
+            .map(move |offset| self.bytes[offset] & mask);
+
+        join_bits(chunk_iterator, bits_per_channel)
+    }
+}
+
+/// Collects the low byte of every sample of a buffer with samples wider than a byte.
+fn low_bytes_of<'a, S>(samples: &[S], low_byte: impl Fn(&S) -> u8) -> Cow<'a, [u8]> {
+    Cow::Owned(samples.iter().map(low_byte).collect())
+}
@@ -0,0 +1,141 @@
+use std::io::{Cursor, Write};
+
+use image::{
+    codecs::png::{FilterType, PngEncoder},
+    DynamicImage, GenericImageView, ImageFormat,
+};
+
+use crate::{
+    base_context,
+    error::ErrorType,
+    file_types::{
+        bits::split_bits,
+        image::{
+            alpha_flags, chunks::compression_of, image_reader, low_bit_mask, opacity_of,
+            sample_offsets, Slots,
+        },
+    },
+    header::Algorithm,
+    output_context,
+    payload::SealedPayload,
+    progress::Progress,
+    slot_order::SlotOrder,
+    AlphaMode, EncodeOptions, Result,
+};
+
+/// A sample of an image, in any of the types the image crate stores them as.
+trait Sample {
+    /// Replaces the bits of the sample's lowest byte that are in the mask.
+    fn set_low_bits(&mut self, mask: u8, bits: u8);
+}
+
+impl Sample for u8 {
+    fn set_low_bits(&mut self, mask: u8, bits: u8) {
+        *self = (*self & !mask) | bits;
+    }
+}
+
+impl Sample for u16 {
+    fn set_low_bits(&mut self, mask: u8, bits: u8) {
+        *self = (*self & !(mask as u16)) | bits as u16;
+    }
+}
+
+impl Sample for f32 {
+    /// The lowest bits of the mantissa are used, which changes the value by the least amount.
+    fn set_low_bits(&mut self, mask: u8, bits: u8) {
+        *self = f32::from_bits((self.to_bits() & !(mask as u32)) | bits as u32);
+    }
+}
+
+/// Encodes the payload into the base image, and writes the results to the output image.
+///
+/// The payload is written after a header recording which channels and pixels the alpha mode
+/// used, and is stored `bits_per_channel` bits at a time in the order given by the key, with the
+/// slots numbered row by row. The samples are edited in their own type, so the output has the
+/// same colour type and bit depth.
+pub fn encode(
+    base_image: &[u8],
+    format: ImageFormat,
+    payload: &SealedPayload,
+    options: &EncodeOptions,
+    progress: &Progress,
+    output_image: &mut dyn Write,
+) -> Result<()> {
+    log::info!("Beginning the encoding process into an image");
+
+    // Getting the image we're going to encode with the secret
+    let reader = image_reader(base_image, format);
+    let mut image = base_context!(reader.decode())?;
+    // image is mut since we'll be editing it in place
+    log::trace!("Parsed the base image as {:?}", image.color());
+
+    // The transparency of the pixels is only needed if the alpha mode could use it
+    let color = image.color();
+    let opacity = match options.alpha {
+        AlphaMode::ColourOnly => None,
+        _ => opacity_of(&image),
+    };
+    let flags = alpha_flags(opacity.as_deref(), options.alpha);
+    let slots = Slots::with_flags(color, opacity.as_deref(), flags);
+    log::trace!("Using {:?} with header flags {flags:#06x}", options.alpha);
+
+    let bits_per_channel = options.bits_per_channel;
+    let payload =
+        payload.with_carrier_flags(Algorithm::LeastSignificantBits, bits_per_channel, flags);
+
+    // Splitting the payload into as many bits at a time as we can store in a channel
+    let secret_data = progress.track(
+        split_bits(&payload, bits_per_channel),
+        bits_per_channel,
+        payload.len() as u64,
+    );
+    let mask = low_bit_mask(bits_per_channel);
+
+    // Every chunk has a slot, as we only call encode on a payload small enough to fully store
+    let order = SlotOrder::new(options.key.as_deref());
+    let offsets = sample_offsets(image.dimensions(), &slots, &order);
+    let chunks = offsets.zip(secret_data);
+    match &mut image {
+        DynamicImage::ImageLuma8(buffer) => set_low_bits(buffer, chunks, mask),
+        DynamicImage::ImageLumaA8(buffer) => set_low_bits(buffer, chunks, mask),
+        DynamicImage::ImageRgb8(buffer) => set_low_bits(buffer, chunks, mask),
+        DynamicImage::ImageRgba8(buffer) => set_low_bits(buffer, chunks, mask),
+        DynamicImage::ImageLuma16(buffer) => set_low_bits(buffer, chunks, mask),
+        DynamicImage::ImageLumaA16(buffer) => set_low_bits(buffer, chunks, mask),
+        DynamicImage::ImageRgb16(buffer) => set_low_bits(buffer, chunks, mask),
+        DynamicImage::ImageRgba16(buffer) => set_low_bits(buffer, chunks, mask),
+        DynamicImage::ImageRgb32F(buffer) => set_low_bits(buffer, chunks, mask),
+        DynamicImage::ImageRgba32F(buffer) => set_low_bits(buffer, chunks, mask),
+        _ => {
+            return base_context!(Err(ErrorType::ImageError(format!(
+                "Images with {color:?} samples aren't supported"
+            ))));
+        }
+    }
+    output_context!(progress.check())?;
+
+    log::trace!("Updated the image buffer with the secret file's data");
+
+    // Some formats seek back while they are written, so the image is written to memory first
+    let mut encoded = Cursor::new(vec![]);
+    if format == ImageFormat::Png {
+        // Compressing as hard as the base image was, so the file size stays close
+        let encoder = PngEncoder::new_with_quality(
+            &mut encoded,
+            compression_of(base_image),
+            FilterType::Adaptive,
+        );
+        output_context!(image.write_with_encoder(encoder))?;
+    } else {
+        output_context!(image.write_to(&mut encoded, format))?;
+    }
+    output_context!(output_image.write_all(encoded.get_ref()))
+}
+
+/// Stores each chunk in the lowest bits of the sample at its offset.
+fn set_low_bits<S: Sample>(samples: &mut [S], chunks: impl Iterator<Item = (usize, u8)>, mask: u8) {
+    for (offset, bits) in chunks {
+        samples[offset].set_low_bits(mask, bits);
+    }
+}
@@ -0,0 +1,909 @@
+use std::{
+    io::{Cursor, Write},
+    ops::RangeInclusive,
+};
+
+use image::{
+    ColorType, DynamicImage, ImageBuffer, ImageDecoder, ImageFormat, ImageReader, Pixel, Primitive,
+};
+
+use crate::{
+    base_context,
+    error::ErrorType,
+    file_types::{animation::apng, carrier::Carrier, supported_file::SupportedFileType},
+    header::{Header, HEADER_BYTES},
+    output_context,
+    payload::SealedPayload,
+    progress::Progress,
+    slot_order::SlotOrder,
+    AlphaMode, DecodeOptions, EncodeOptions, Result,
+};
+
+pub mod chunks;
+mod decode;
+mod encode;
+mod palette;
+
+pub use decode::decode;
+pub use encode::encode;
+
+/// The numbers of least significant bits per channel that can be used to store data.
+///
+/// Past four bits, the changes to the image become plainly visible.
+const BITS_PER_CHANNEL: RangeInclusive<u8> = 1..=4;
+
+/// Hides data in the least significant bits of a lossless image's colour channels.
+pub struct ImageCarrier {
+    file_type: SupportedFileType,
+    format: ImageFormat,
+}
+
+impl ImageCarrier {
+    /// A carrier for images of the given format, which the image crate must be able to write.
+    pub const fn new(file_type: SupportedFileType, format: ImageFormat) -> ImageCarrier {
+        ImageCarrier { file_type, format }
+    }
+
+    /// Returns true if the contents are a PNG with a palette, which is kept by hiding data in
+    /// the palette indices instead of the colour channels.
+    fn is_indexed(&self, contents: &[u8]) -> bool {
+        self.format == ImageFormat::Png && palette::is_indexed(contents)
+    }
+}
+
+impl Carrier for ImageCarrier {
+    fn file_type(&self) -> SupportedFileType {
+        self.file_type
+    }
+
+    fn extensions(&self) -> &[&str] {
+        self.format.extensions_str()
+    }
+
+    fn detect(&self, contents: &[u8]) -> bool {
+        // Animated PNGs are left to their own carrier, so that every frame is kept
+        image::guess_format(contents).is_ok_and(|format| format == self.format)
+            && !(self.format == ImageFormat::Png && apng::is_animated(contents))
+    }
+
+    fn capacity(&self, contents: &[u8], options: &EncodeOptions) -> Result<u64> {
+        if self.is_indexed(contents) {
+            // Palette indices hold a single bit each, whatever the bits per channel
+            return base_context!(palette::available_size_of(contents, options.alpha));
+        }
+
+        base_context!(available_size_of(
+            contents,
+            self.format,
+            options.bits_per_channel,
+            options.alpha
+        ))
+    }
+
+    fn embed(
+        &self,
+        contents: &[u8],
+        payload: &SealedPayload,
+        options: &EncodeOptions,
+        progress: &Progress,
+        output: &mut dyn Write,
+    ) -> Result<()> {
+        let order = SlotOrder::new(options.key.as_deref());
+        let mut encoded = vec![];
+        if self.is_indexed(contents) {
+            palette::encode(
+                contents,
+                payload,
+                options.alpha,
+                &order,
+                progress,
+                &mut encoded,
+            )?;
+        } else {
+            encode(
+                contents,
+                self.format,
+                payload,
+                options,
+                progress,
+                &mut encoded,
+            )?;
+        }
+
+        // The image crate only writes the image data, so the rest of a PNG is copied over
+        if self.format == ImageFormat::Png {
+            encoded = base_context!(chunks::copy_ancillary_chunks(
+                contents,
+                &encoded,
+                options.strip_metadata
+            ))?;
+        }
+        output_context!(output.write_all(&encoded))
+    }
+
+    fn extract(
+        &self,
+        contents: &[u8],
+        options: &DecodeOptions,
+        progress: &Progress,
+    ) -> Result<(Header, Vec<u8>)> {
+        let order = SlotOrder::new(options.key.as_deref());
+        if self.is_indexed(contents) {
+            palette::decode(contents, &order, progress)
+        } else {
+            decode(contents, self.format, &order, progress)
+        }
+    }
+}
+
+/// Finds the amount of space in bytes, that can be used to store a secret file.
+///
+/// Every colour channel of every pixel provides `bits_per_channel` bits, so grayscale images have
+/// a third of the space of colour images the same size. The alpha mode can add the alpha channel,
+/// or leave out pixels depending on their transparency.
+pub fn available_size_of(
+    contents: &[u8],
+    format: ImageFormat,
+    bits_per_channel: u8,
+    alpha: AlphaMode,
+) -> std::result::Result<u64, ErrorType> {
+    check_bits_per_channel(bits_per_channel)?;
+
+    let decoder = image_reader(contents, format).into_decoder()?;
+    let dimensions = decoder.dimensions();
+    let color = decoder.color_type();
+    log::trace!("Read the size and colour type of an image: {color:?}");
+
+    // The pixels only need to be read when their transparency changes which are used
+    let slots = if alpha == AlphaMode::ColourOnly || !color.has_alpha() {
+        Slots::with_flags(color, None, 0)
+    } else {
+        let image = DynamicImage::from_decoder(decoder)?;
+        let opacity = opacity_of(&image);
+        let flags = alpha_flags(opacity.as_deref(), alpha);
+        Slots::with_flags(color, opacity.as_deref(), flags)
+    };
+
+    let bits = slots.count(dimensions) * bits_per_channel as u64;
+    Ok((bits / 8).saturating_sub(HEADER_BYTES))
+}
+
+/// Returns an error if the image can't be encoded with the number of bits per channel.
+fn check_bits_per_channel(bits_per_channel: u8) -> std::result::Result<(), ErrorType> {
+    if BITS_PER_CHANNEL.contains(&bits_per_channel) {
+        Ok(())
+    } else {
+        Err(ErrorType::UnsupportedBitsPerChannel(bits_per_channel))
+    }
+}
+
+/// A mask for the given number of least significant bits of a byte.
+fn low_bit_mask(bits: u8) -> u8 {
+    (1 << bits) - 1
+}
+
+/// The number of channels in a pixel of the colour type that aren't an alpha channel.
+fn colour_channels(color: ColorType) -> u8 {
+    color.channel_count() - color.has_alpha() as u8
+}
+
+/// How transparent a pixel is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opacity {
+    Transparent,
+    Translucent,
+    Opaque,
+}
+
+/// Finds the opacity of every pixel, row by row. None if the image has no alpha channel.
+fn opacity_of(image: &DynamicImage) -> Option<Vec<Opacity>> {
+    match image {
+        DynamicImage::ImageLumaA8(buffer) => Some(opacity_of_pixels(buffer)),
+        DynamicImage::ImageRgba8(buffer) => Some(opacity_of_pixels(buffer)),
+        DynamicImage::ImageLumaA16(buffer) => Some(opacity_of_pixels(buffer)),
+        DynamicImage::ImageRgba16(buffer) => Some(opacity_of_pixels(buffer)),
+        DynamicImage::ImageRgba32F(buffer) => Some(opacity_of_pixels(buffer)),
+        _ => None,
+    }
+}
+
+/// Finds the opacity of every pixel of a buffer whose last channel is alpha.
+fn opacity_of_pixels<P: Pixel>(buffer: &ImageBuffer<P, Vec<P::Subpixel>>) -> Vec<Opacity> {
+    buffer
+        .pixels()
+        .map(|pixel| match pixel.channels().last() {
+            Some(alpha) if *alpha == P::Subpixel::DEFAULT_MIN_VALUE => Opacity::Transparent,
+            Some(alpha) if *alpha == P::Subpixel::DEFAULT_MAX_VALUE => Opacity::Opaque,
+            _ => Opacity::Translucent,
+        })
+        .collect()
+}
+
+/// Chooses the header flags recording which parts of an image the alpha mode uses.
+///
+/// Modes that would use the same samples as the colour channels of every pixel are recorded as
+/// no flags at all.
+pub fn alpha_flags(opacity: Option<&[Opacity]>, alpha: AlphaMode) -> u16 {
+    let Some(opacity) = opacity else {
+        return 0;
+    };
+
+    match alpha {
+        AlphaMode::IncludeAlpha if opacity.iter().all(|pixel| *pixel == Opacity::Opaque) => {
+            Header::ALPHA_CHANNEL
+        }
+        AlphaMode::SkipTransparent if opacity.contains(&Opacity::Transparent) => {
+            Header::SKIPS_TRANSPARENT
+        }
+        AlphaMode::OnlyTransparent => Header::ONLY_TRANSPARENT,
+        _ => 0,
+    }
+}
+
+/// The samples of an image that hold a payload.
+struct Slots {
+    /// The number of samples in each pixel.
+    channels: u8,
+    /// The number of samples used in each pixel, counting from the first.
+    used_channels: u8,
+    /// The pixels that are used, row by row, or None if every pixel is.
+    pixels: Option<Vec<u32>>,
+}
+
+impl Slots {
+    /// The samples used by a payload with the given header flags, in an image of the colour type.
+    ///
+    /// The opacity of every pixel is only needed if the flags pick pixels by their transparency.
+    fn with_flags(color: ColorType, opacity: Option<&[Opacity]>, flags: u16) -> Slots {
+        let pixels_where = |wanted: fn(&Opacity) -> bool| {
+            // The image crate can't allocate an image with more pixels than a u32 can count
+            let pixels = opacity.unwrap_or_default().iter().enumerate();
+            pixels
+                .filter(|(_, opacity)| wanted(opacity))
+                .map(|(pixel, _)| pixel as u32)
+                .collect()
+        };
+
+        Slots {
+            channels: color.channel_count(),
+            used_channels: if flags & Header::ALPHA_CHANNEL != 0 {
+                color.channel_count()
+            } else {
+                colour_channels(color)
+            },
+            pixels: if flags & Header::SKIPS_TRANSPARENT != 0 {
+                Some(pixels_where(|opacity| *opacity != Opacity::Transparent))
+            } else if flags & Header::ONLY_TRANSPARENT != 0 {
+                Some(pixels_where(|opacity| *opacity == Opacity::Transparent))
+            } else {
+                None
+            },
+        }
+    }
+
+    /// The number of slots in an image of the given size.
+    fn count(&self, dimensions: (u32, u32)) -> u64 {
+        let pixels = match &self.pixels {
+            Some(pixels) => pixels.len() as u64,
+            None => dimensions.0 as u64 * dimensions.1 as u64,
+        };
+
+        pixels * self.used_channels as u64
+    }
+}
+
+/// Iterates over the positions in an image's samples in a deterministic order decided by the
+/// slots and the slot order.
+///
+/// The samples are stored row by row, with every channel of a pixel next to each other. Keyed
+/// slots are found a block at a time, in parallel with the parallel feature, but the samples
+/// are still read and written one at a time by the caller.
+fn sample_offsets<'a>(
+    dimensions: (u32, u32),
+    slots: &'a Slots,
+    order: &'a SlotOrder,
+) -> impl Iterator<Item = usize> + 'a {
+    let used_channels = slots.used_channels as u64;
+
+    order.iter_blocks(slots.count(dimensions)).map(move |slot| {
+        let pixel = match &slots.pixels {
+            Some(pixels) => pixels[(slot / used_channels) as usize] as u64,
+            None => slot / used_channels,
+        };
+        (pixel * slots.channels as u64 + slot % used_channels) as usize
+    })
+}
+
+/// Creates an ImageReader over the contents of an image in the given format.
+fn image_reader(contents: &[u8], format: ImageFormat) -> ImageReader<Cursor<&[u8]>> {
+    ImageReader::with_format(Cursor::new(contents), format)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        io::{Cursor, Read, Write},
+        path::{Path, PathBuf},
+    };
+
+    use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+
+    use crate::{
+        base_context, decode_bytes, encode_bytes, encoded_context,
+        file_types::{
+            image::{available_size_of, decode, encode},
+            supported_file::{SupportedFile, SupportedFileType},
+        },
+        header::HEADER_BYTES,
+        output_context,
+        payload::Payload,
+        progress::Progress,
+        secret_context,
+        slot_order::SlotOrder,
+        AlphaMode, CorruptionType, DecodeOptions, Decoder, EncodeOptions, ErrorType, Redundancy,
+        Result,
+    };
+
+    fn test_data_dir() -> PathBuf {
+        "./test_data".into()
+    }
+    fn base_file() -> PathBuf {
+        test_data_dir().join("stick.png")
+    }
+    fn encoded_file() -> PathBuf {
+        test_data_dir().join("stick_with_secret.png")
+    }
+    fn secret_file() -> PathBuf {
+        test_data_dir().join("story.txt")
+    }
+
+    fn assert_files_equal<P: AsRef<Path>>(
+        file1: P,
+        file2: P,
+    ) -> std::result::Result<(), ErrorType> {
+        let file1 = file_contents(file1)?;
+        let file2 = file_contents(file2)?;
+
+        assert_eq!(file1, file2);
+
+        Ok(())
+    }
+
+    fn file_contents<P: AsRef<Path>>(file: P) -> std::result::Result<Vec<u8>, ErrorType> {
+        let mut file_contents = vec![];
+        File::open(file)?.read_to_end(&mut file_contents)?;
+
+        Ok(file_contents)
+    }
+
+    #[test]
+    fn size_of() -> std::result::Result<(), ErrorType> {
+        let file = SupportedFile::open(base_file())?;
+
+        assert_eq!(
+            available_size_of(file.contents(), ImageFormat::Png, 2, AlphaMode::ColourOnly)?,
+            98_304 - HEADER_BYTES
+        );
+        assert_eq!(
+            available_size_of(file.contents(), ImageFormat::Png, 1, AlphaMode::ColourOnly)?,
+            49_152 - HEADER_BYTES
+        );
+        assert_eq!(
+            available_size_of(file.contents(), ImageFormat::Png, 4, AlphaMode::ColourOnly)?,
+            196_608 - HEADER_BYTES
+        );
+        assert!(matches!(
+            available_size_of(file.contents(), ImageFormat::Png, 5, AlphaMode::ColourOnly),
+            Err(ErrorType::UnsupportedBitsPerChannel(5))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_encode() -> Result<()> {
+        let output_image_path = test_data_dir().join("stick_encode.result.png");
+        let base_image = base_context!(SupportedFile::open(base_file()))?;
+        let secret_contents = secret_context!(file_contents(secret_file()))?;
+        let payload = Payload::new(secret_file(), secret_contents);
+        let mut output_image = output_context!(File::create(&output_image_path))?;
+        encode(
+            base_image.contents(),
+            ImageFormat::Png,
+            &payload.seal(&EncodeOptions::default()),
+            &EncodeOptions::default(),
+            &Progress::default(),
+            &mut output_image,
+        )?;
+
+        output_context!(assert_files_equal(output_image_path, encoded_file()))?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_decode() -> Result<()> {
+        let output_file_path = test_data_dir().join("decoded_story.result.txt");
+        let encoded_file = encoded_context!(SupportedFile::open(encoded_file()))?;
+        let mut output_file = output_context!(File::create(&output_file_path))?;
+        let (header, bytes) = decode(
+            encoded_file.contents(),
+            ImageFormat::Png,
+            &SlotOrder::sequential(),
+            &Progress::default(),
+        )?;
+        let payload = encoded_context!(Payload::unseal(&header, bytes, &DecodeOptions::default()))?;
+        output_context!(output_file.write_all(&payload.contents))?;
+
+        assert_eq!(payload.properties.file_name.as_deref(), Some("story.txt"));
+
+        output_context!(assert_files_equal(output_file_path, secret_file()))?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn decode_without_payload() -> Result<()> {
+        let encoded_file = encoded_context!(SupportedFile::open(base_file()))?;
+        let error = decode(
+            encoded_file.contents(),
+            ImageFormat::Png,
+            &SlotOrder::sequential(),
+            &Progress::default(),
+        )
+        .unwrap_err();
+
+        assert!(matches!(
+            error.error_type,
+            ErrorType::CorruptedFile(CorruptionType::NoPayloadFound)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn damaged_payload_is_only_kept_for_best_effort() -> Result<()> {
+        let damaged_path = test_data_dir().join("stick_damaged.result.png");
+        let output_path = test_data_dir().join("story_damaged.result.txt");
+        let _ = fs::remove_file(&output_path);
+
+        // The 21 byte header and 28 bytes of metadata come first, and each byte takes up four
+        // samples, so the 11th letter of the story starts in the 236th sample
+        let mut image = encoded_context!(::image::open(encoded_file()))?.into_rgba8();
+        image.get_pixel_mut(78, 0)[2] ^= 1;
+        output_context!(image.save(&damaged_path))?;
+
+        let decode = |best_effort| {
+            let options = DecodeOptions {
+                best_effort,
+                ..Default::default()
+            };
+            Decoder::with_options(&damaged_path, &output_path, options)?.decode()
+        };
+        let is_checksum_mismatch = |error: crate::Error| {
+            matches!(
+                error.error_type,
+                ErrorType::CorruptedFile(CorruptionType::ChecksumMismatch)
+            )
+        };
+
+        assert!(decode(false).is_err_and(is_checksum_mismatch));
+        assert!(!output_path.exists());
+
+        assert!(decode(true).is_err_and(is_checksum_mismatch));
+        let story = secret_context!(file_contents(secret_file()))?;
+        let recovered = output_context!(file_contents(&output_path))?;
+        let differences = story
+            .iter()
+            .zip(&recovered)
+            .filter(|(before, after)| before != after)
+            .count();
+        assert_eq!((recovered.len(), differences), (story.len(), 1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn redundancy_corrects_damaged_pixels() -> Result<()> {
+        let base = base_context!(fs::read(base_file()))?;
+        let story = secret_context!(file_contents(secret_file()))?;
+        let encode_with = |redundancy| {
+            let options = EncodeOptions {
+                password: Some("hunter2".to_owned()),
+                redundancy,
+                ..Default::default()
+            };
+            encode_bytes(&base, SupportedFileType::Png, &story, &options)
+        };
+        let options = DecodeOptions {
+            password: Some("hunter2".to_owned()),
+            ..Default::default()
+        };
+
+        for (redundancy, recovered) in [(Redundancy::None, false), (Redundancy::High, true)] {
+            // Flipping a bit of the first copy of the header, and every low bit of a run of pixels
+            // after the header's copies, like a scratch would
+            let mut image = encoded_context!(::image::load_from_memory(&encode_with(redundancy)?))?
+                .into_rgba8();
+            image.get_pixel_mut(1, 0)[0] ^= 0b01;
+            for x in 100..120 {
+                for channel in 0..3 {
+                    image.get_pixel_mut(x, 0)[channel] ^= 0b11;
+                }
+            }
+            let mut damaged = Cursor::new(vec![]);
+            output_context!(image.write_to(&mut damaged, ImageFormat::Png))?;
+
+            let decoded = decode_bytes(damaged.get_ref(), SupportedFileType::Png, &options);
+            assert_eq!(decoded.ok(), recovered.then(|| story.clone()));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn keyed_round_trip_with_other_depth() -> Result<()> {
+        let output_image_path = test_data_dir().join("stick_keyed.result.png");
+        let base_image = base_context!(SupportedFile::open(base_file()))?;
+        let payload = Payload::new(
+            secret_file(),
+            secret_context!(file_contents(secret_file()))?,
+        );
+        let options = EncodeOptions {
+            key: Some("key".to_string()),
+            bits_per_channel: 3,
+            ..Default::default()
+        };
+        let mut output_image = output_context!(File::create(&output_image_path))?;
+        encode(
+            base_image.contents(),
+            ImageFormat::Png,
+            &payload.seal(&options),
+            &options,
+            &Progress::default(),
+            &mut output_image,
+        )?;
+
+        let encoded_file = encoded_context!(SupportedFile::open(&output_image_path))?;
+        let (header, bytes) = decode(
+            encoded_file.contents(),
+            ImageFormat::Png,
+            &SlotOrder::keyed("key"),
+            &Progress::default(),
+        )?;
+        let decoded = encoded_context!(Payload::unseal(&header, bytes, &DecodeOptions::default()))?;
+        assert_eq!(decoded, payload);
+
+        let encoded_file = encoded_context!(SupportedFile::open(&output_image_path))?;
+        let error = decode(
+            encoded_file.contents(),
+            ImageFormat::Png,
+            &SlotOrder::sequential(),
+            &Progress::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error.error_type,
+            ErrorType::CorruptedFile(CorruptionType::NoPayloadFound)
+        ));
+
+        Ok(())
+    }
+
+    /// Converts the base file into another format, then checks that a payload survives being
+    /// encoded into it and decoded back out.
+    fn assert_round_trip_in(format: ImageFormat, extension: &str) -> Result<()> {
+        let converted_path = test_data_dir().join(format!("stick_converted.result.{extension}"));
+        let output_image_path = test_data_dir().join(format!("stick_encoded.result.{extension}"));
+        base_context!(::image::open(base_file()).and_then(|image| {
+            // Not every format can store an alpha channel
+            let image = match format {
+                ImageFormat::Pnm => image.to_rgb8().into(),
+                _ => image,
+            };
+            image.save_with_format(&converted_path, format)
+        }))?;
+
+        let base_image = base_context!(SupportedFile::open(&converted_path))?;
+        let payload = Payload::new(
+            secret_file(),
+            secret_context!(file_contents(secret_file()))?,
+        );
+        let mut output_image = output_context!(File::create(&output_image_path))?;
+        encode(
+            base_image.contents(),
+            format,
+            &payload.seal(&EncodeOptions::default()),
+            &EncodeOptions::default(),
+            &Progress::default(),
+            &mut output_image,
+        )?;
+
+        // The output is read back in the format its extension gives, the same as the base file
+        let encoded_file = encoded_context!(SupportedFile::open(&output_image_path))?;
+        let (header, bytes) = decode(
+            encoded_file.contents(),
+            format,
+            &SlotOrder::sequential(),
+            &Progress::default(),
+        )?;
+        let decoded = encoded_context!(Payload::unseal(&header, bytes, &DecodeOptions::default()))?;
+        assert_eq!(decoded, payload);
+
+        Ok(())
+    }
+
+    #[test]
+    fn bmp_round_trip() -> Result<()> {
+        assert_round_trip_in(ImageFormat::Bmp, "bmp")
+    }
+
+    #[test]
+    fn tiff_round_trip() -> Result<()> {
+        assert_round_trip_in(ImageFormat::Tiff, "tiff")
+    }
+
+    #[test]
+    fn tga_round_trip() -> Result<()> {
+        assert_round_trip_in(ImageFormat::Tga, "tga")
+    }
+
+    #[test]
+    fn qoi_round_trip() -> Result<()> {
+        assert_round_trip_in(ImageFormat::Qoi, "qoi")
+    }
+
+    #[test]
+    fn pnm_round_trip() -> Result<()> {
+        assert_round_trip_in(ImageFormat::Pnm, "ppm")
+    }
+
+    #[test]
+    fn webp_round_trip() -> Result<()> {
+        assert_round_trip_in(ImageFormat::WebP, "webp")
+    }
+
+    /// Converts the base file into another colour type, then checks that a payload survives being
+    /// encoded into it and that the output keeps the colour type. Returns the converted and
+    /// encoded images.
+    fn assert_round_trip_as(
+        convert: fn(DynamicImage) -> DynamicImage,
+        name: &str,
+    ) -> Result<(DynamicImage, DynamicImage)> {
+        let converted_path = test_data_dir().join(format!("stick_{name}.result.png"));
+        let output_image_path = test_data_dir().join(format!("stick_{name}_encoded.result.png"));
+        let converted = convert(base_context!(::image::open(base_file()))?);
+        base_context!(converted.save(&converted_path))?;
+
+        let base_image = base_context!(SupportedFile::open(&converted_path))?;
+        let payload = Payload::new(secret_file(), b"A short secret".to_vec());
+        let options = EncodeOptions {
+            key: Some("key".to_string()),
+            ..Default::default()
+        };
+        let mut output_image = output_context!(File::create(&output_image_path))?;
+        encode(
+            base_image.contents(),
+            ImageFormat::Png,
+            &payload.seal(&options),
+            &options,
+            &Progress::default(),
+            &mut output_image,
+        )?;
+
+        let encoded_file = encoded_context!(SupportedFile::open(&output_image_path))?;
+        let (header, bytes) = decode(
+            encoded_file.contents(),
+            ImageFormat::Png,
+            &SlotOrder::keyed("key"),
+            &Progress::default(),
+        )?;
+        let decoded = encoded_context!(Payload::unseal(&header, bytes, &DecodeOptions::default()))?;
+        assert_eq!(decoded, payload);
+
+        let encoded = encoded_context!(::image::open(&output_image_path))?;
+        assert_eq!(encoded.color(), converted.color());
+
+        Ok((converted, encoded))
+    }
+
+    #[test]
+    fn grayscale_round_trip() -> Result<()> {
+        assert_round_trip_as(|image| image.to_luma8().into(), "l8")?;
+        assert_round_trip_as(|image| image.to_luma_alpha8().into(), "la8")?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn sixteen_bit_round_trip_only_changes_low_bits() -> Result<()> {
+        assert_round_trip_as(|image| image.to_rgb16().into(), "rgb16")?;
+        let (converted, encoded) =
+            assert_round_trip_as(|image| image.to_rgba16().into(), "rgba16")?;
+
+        let converted = converted.into_rgba16();
+        let encoded = encoded.into_rgba16();
+        assert_ne!(converted, encoded);
+        for (before, after) in converted.iter().zip(encoded.iter()) {
+            assert_eq!(before & !0b11, after & !0b11);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_png_metadata_unless_stripped() -> Result<()> {
+        let mut base = base_context!(file_contents(base_file()))?;
+        // Adding a text chunk just after the 33 bytes of the signature and header chunk
+        let mut text = b"tEXtAuthor\0Someone".to_vec();
+        let crc = crc32fast::hash(&text);
+        text.splice(0..0, 14u32.to_be_bytes());
+        text.extend_from_slice(&crc.to_be_bytes());
+        base.splice(33..33, text);
+
+        let info_of = |png: &[u8]| -> Result<png::Info<'static>> {
+            let reader = encoded_context!(png::Decoder::new(png)
+                .read_info()
+                .map_err(|error| ErrorType::ImageError(error.to_string())))?;
+            Ok(reader.info().clone())
+        };
+        let options = EncodeOptions::default();
+        let kept = info_of(&encode_bytes(
+            &base,
+            SupportedFileType::Png,
+            b"secret",
+            &options,
+        )?)?;
+        assert!(kept.srgb.is_some() && kept.gama_chunk.is_some() && kept.pixel_dims.is_some());
+        assert_eq!(kept.uncompressed_latin1_text[0].text, "Someone");
+
+        let options = EncodeOptions {
+            strip_metadata: true,
+            ..Default::default()
+        };
+        let stripped = info_of(&encode_bytes(
+            &base,
+            SupportedFileType::Png,
+            b"secret",
+            &options,
+        )?)?;
+        assert!(stripped.srgb.is_some() && stripped.pixel_dims.is_some());
+        assert!(stripped.uncompressed_latin1_text.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn grayscale_has_a_third_of_the_space() -> Result<()> {
+        let converted_path = test_data_dir().join("stick_gray_size.result.png");
+        base_context!(::image::open(base_file())
+            .and_then(|image| image.to_luma_alpha8().save(&converted_path)))?;
+        let file = base_context!(SupportedFile::open(&converted_path))?;
+
+        assert_eq!(
+            base_context!(available_size_of(
+                file.contents(),
+                ImageFormat::Png,
+                2,
+                AlphaMode::ColourOnly
+            ))?,
+            98_304 / 3 - HEADER_BYTES
+        );
+
+        Ok(())
+    }
+
+    /// A PNG with a gradient of colours, whose left half is fully transparent unless `opaque`.
+    fn half_transparent_png(opaque: bool) -> Result<Vec<u8>> {
+        let image = RgbaImage::from_fn(64, 64, |x, y| {
+            let alpha = if opaque || x >= 32 { 255 } else { 0 };
+            Rgba([(x * 4) as u8, (y * 4) as u8, 128, alpha])
+        });
+        let mut png = Cursor::new(vec![]);
+        base_context!(image.write_to(&mut png, ImageFormat::Png))?;
+
+        Ok(png.into_inner())
+    }
+
+    #[test]
+    fn alpha_modes_change_the_space() -> Result<()> {
+        let size_of =
+            |png: &[u8], alpha| base_context!(available_size_of(png, ImageFormat::Png, 2, alpha));
+        let opaque = half_transparent_png(true)?;
+        let half_transparent = half_transparent_png(false)?;
+
+        // Each of the 4096 pixels has 6 bits in its colour channels and 8 with its alpha channel
+        assert_eq!(
+            size_of(&opaque, AlphaMode::ColourOnly)?,
+            3072 - HEADER_BYTES
+        );
+        assert_eq!(
+            size_of(&opaque, AlphaMode::IncludeAlpha)?,
+            4096 - HEADER_BYTES
+        );
+        assert_eq!(
+            size_of(&half_transparent, AlphaMode::IncludeAlpha)?,
+            3072 - HEADER_BYTES
+        );
+        assert_eq!(
+            size_of(&half_transparent, AlphaMode::SkipTransparent)?,
+            1536 - HEADER_BYTES
+        );
+        assert_eq!(
+            size_of(&half_transparent, AlphaMode::OnlyTransparent)?,
+            1536 - HEADER_BYTES
+        );
+
+        Ok(())
+    }
+
+    /// Checks that a secret survives being encoded into the PNG with the alpha mode, then returns
+    /// the base and encoded images.
+    fn assert_alpha_round_trip(png: &[u8], alpha: AlphaMode) -> Result<(RgbaImage, RgbaImage)> {
+        let secret: Vec<u8> = (0..1200u32).map(|byte| (byte * 7) as u8).collect();
+        let options = EncodeOptions {
+            key: Some("key".to_string()),
+            alpha,
+            ..Default::default()
+        };
+        let encoded = encode_bytes(png, SupportedFileType::Png, &secret, &options)?;
+
+        let options = DecodeOptions {
+            key: Some("key".to_string()),
+            ..Default::default()
+        };
+        let decoded = decode_bytes(&encoded, SupportedFileType::Png, &options)?;
+        assert_eq!(decoded, secret);
+
+        Ok((
+            base_context!(::image::load_from_memory(png))?.into_rgba8(),
+            encoded_context!(::image::load_from_memory(&encoded))?.into_rgba8(),
+        ))
+    }
+
+    #[test]
+    fn include_alpha_round_trip() -> Result<()> {
+        let (base, encoded) =
+            assert_alpha_round_trip(&half_transparent_png(true)?, AlphaMode::IncludeAlpha)?;
+
+        assert!(base
+            .pixels()
+            .zip(encoded.pixels())
+            .any(|(before, after)| before[3] != after[3]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn skip_transparent_round_trip_leaves_transparent_pixels() -> Result<()> {
+        let (base, encoded) =
+            assert_alpha_round_trip(&half_transparent_png(false)?, AlphaMode::SkipTransparent)?;
+
+        for (x, y, before) in base.enumerate_pixels() {
+            if x < 32 {
+                assert_eq!(before, encoded.get_pixel(x, y));
+            }
+        }
+        assert_ne!(base, encoded);
+
+        Ok(())
+    }
+
+    #[test]
+    fn only_transparent_round_trip_leaves_visible_pixels() -> Result<()> {
+        let (base, encoded) =
+            assert_alpha_round_trip(&half_transparent_png(false)?, AlphaMode::OnlyTransparent)?;
+
+        for (x, y, before) in base.enumerate_pixels() {
+            let after = encoded.get_pixel(x, y);
+            assert_eq!(before[3], after[3]);
+            if x >= 32 {
+                assert_eq!(before, after);
+            }
+        }
+        assert_ne!(base, encoded);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,620 @@
+use std::io::{BufWriter, Write};
+
+use image::codecs::png::CompressionType;
+use png::{chunk::ChunkType, BitDepth, ColorType, Compression, FilterType, Transformations};
+
+use crate::{
+    base_context, encoded_context,
+    error::ErrorType,
+    file_types::{
+        animation::apng::png_error,
+        bits::{join_bits, split_bits},
+        image::{
+            alpha_flags,
+            chunks::{compression_of, data_of},
+            Opacity,
+        },
+    },
+    header::{Algorithm, Header, HEADER_BYTES},
+    output_context,
+    payload::SealedPayload,
+    progress::Progress,
+    slot_order::SlotOrder,
+    AlphaMode, CorruptionType, Result,
+};
+
+/// The header flags a payload in an indexed PNG can be stored with, in the order they're tried.
+const ALPHA_FLAG_CHOICES: [u16; 3] = [0, Header::SKIPS_TRANSPARENT, Header::ONLY_TRANSPARENT];
+
+/// An indexed PNG, with its palette and the palette index of every pixel.
+struct IndexedImage {
+    width: u32,
+    height: u32,
+    bit_depth: BitDepth,
+    /// Three bytes for each colour.
+    palette: Vec<u8>,
+    /// The alpha of each colour, which is opaque for colours past the end of the tRNS chunk.
+    alpha: Vec<u8>,
+    /// The palette index of every pixel, one byte each, row by row.
+    indices: Vec<u8>,
+    /// The palette index of the background colour, from the bKGD chunk.
+    background: Option<u8>,
+    /// How often each colour is used, from the hIST chunk.
+    histogram: Option<Vec<u8>>,
+}
+
+/// Returns true if the contents are a PNG whose pixels are palette indices.
+///
+/// Only the chunks before the image data are read.
+pub fn is_indexed(contents: &[u8]) -> bool {
+    png::Decoder::new(contents)
+        .read_info()
+        .is_ok_and(|reader| reader.info().color_type == ColorType::Indexed)
+}
+
+/// Reads the palette and pixels of an indexed PNG.
+fn read_image(contents: &[u8]) -> std::result::Result<IndexedImage, ErrorType> {
+    let mut decoder = png::Decoder::new(contents);
+    decoder.set_transformations(Transformations::IDENTITY);
+    let mut reader = decoder.read_info().map_err(png_error)?;
+
+    let mut data = vec![0; reader.output_buffer_size()];
+    let output_info = reader.next_frame(&mut data).map_err(png_error)?;
+
+    let info = reader.info();
+    let palette = info
+        .palette
+        .as_deref()
+        .filter(|_| info.color_type == ColorType::Indexed)
+        .ok_or_else(|| ErrorType::ImageError("The PNG doesn't have a palette".to_owned()))?
+        .to_vec();
+    let mut alpha = info.trns.as_deref().unwrap_or_default().to_vec();
+    alpha.resize(palette.len() / 3, u8::MAX);
+
+    let image = IndexedImage {
+        width: info.width,
+        height: info.height,
+        bit_depth: info.bit_depth,
+        indices: unpack(
+            &data[..output_info.buffer_size()],
+            output_info.line_size,
+            info.width,
+            info.bit_depth as u8,
+        ),
+        palette,
+        alpha,
+        background: data_of(contents, b"bKGD").and_then(|data| data.first().copied()),
+        histogram: data_of(contents, b"hIST").map(<[u8]>::to_vec),
+    };
+    log::trace!(
+        "Read an indexed PNG with {} colours at {} bits per pixel",
+        image.alpha.len(),
+        image.bit_depth as u8
+    );
+
+    Ok(image)
+}
+
+/// Splits rows of packed palette indices into one byte per index.
+fn unpack(data: &[u8], line_size: usize, width: u32, bit_depth: u8) -> Vec<u8> {
+    let per_byte = (8 / bit_depth) as usize;
+    let mask = ((1u16 << bit_depth) - 1) as u8;
+
+    data.chunks_exact(line_size)
+        .flat_map(|row| {
+            (0..width as usize).map(move |x| {
+                let shift = 8 - bit_depth as usize * (x % per_byte + 1);
+                (row[x / per_byte] >> shift) & mask
+            })
+        })
+        .collect()
+}
+
+/// Packs one byte per palette index back into rows, with each row starting on a new byte.
+fn pack(indices: &[u8], width: u32, bit_depth: u8) -> Vec<u8> {
+    let per_byte = (8 / bit_depth) as usize;
+
+    indices
+        .chunks_exact(width as usize)
+        .flat_map(|row| {
+            row.chunks(per_byte).map(|group| {
+                group.iter().enumerate().fold(0, |byte, (position, index)| {
+                    byte | index << (8 - bit_depth as usize * (position + 1))
+                })
+            })
+        })
+        .collect()
+}
+
+impl IndexedImage {
+    /// Whether a pixel can hold a bit of the payload.
+    ///
+    /// Changing the lowest bit of a palette index swaps between a pair of colours, so both have
+    /// to be in the palette, and have the same alpha to keep transparency the same.
+    fn is_usable(&self, index: u8) -> bool {
+        let pair = index ^ 1;
+        match (
+            self.alpha.get(index as usize),
+            self.alpha.get(pair as usize),
+        ) {
+            (Some(alpha), Some(pair_alpha)) => alpha == pair_alpha,
+            _ => false,
+        }
+    }
+
+    /// Whether the pixel's colour is fully transparent.
+    fn is_transparent(&self, index: u8) -> bool {
+        self.alpha.get(index as usize) == Some(&0)
+    }
+
+    /// Whether a pixel holds a bit of a payload stored with the header flags, which can leave out
+    /// pixels depending on their transparency.
+    fn is_slot(&self, index: u8, flags: u16) -> bool {
+        let wanted = if flags & Header::SKIPS_TRANSPARENT != 0 {
+            !self.is_transparent(index)
+        } else if flags & Header::ONLY_TRANSPARENT != 0 {
+            self.is_transparent(index)
+        } else {
+            true
+        };
+
+        wanted && self.is_usable(index)
+    }
+
+    /// The number of pixels that each hold a bit of a payload stored with the header flags.
+    fn slot_count(&self, flags: u16) -> u64 {
+        self.indices
+            .iter()
+            .filter(|index| self.is_slot(**index, flags))
+            .count() as u64
+    }
+
+    /// Chooses the header flags recording which pixels the alpha mode uses.
+    ///
+    /// A pixel's transparency comes from its colour, so there's no alpha channel to include, and
+    /// a palette without a tRNS chunk is used like an image without an alpha channel.
+    fn alpha_flags(&self, alpha: AlphaMode) -> u16 {
+        if self.alpha.iter().all(|alpha| *alpha == u8::MAX) {
+            return 0;
+        }
+
+        let opacity: Vec<Opacity> = self
+            .indices
+            .iter()
+            .map(|index| match self.alpha.get(*index as usize).copied() {
+                Some(0) => Opacity::Transparent,
+                Some(u8::MAX) | None => Opacity::Opaque,
+                Some(_) => Opacity::Translucent,
+            })
+            .collect();
+        alpha_flags(Some(&opacity), alpha) & !Header::ALPHA_CHANNEL
+    }
+
+    /// Sorts the palette by alpha and then brightness, moving every index to match.
+    ///
+    /// Sorting means the pair of colours an index's lowest bit chooses between look alike, so that
+    /// the payload is hard to see. The palette keeps its size.
+    fn sort_palette(&mut self) {
+        let colours: Vec<[u8; 4]> = self
+            .palette
+            .chunks_exact(3)
+            .zip(&self.alpha)
+            .map(|(colour, alpha)| [colour[0], colour[1], colour[2], *alpha])
+            .collect();
+        let mut order: Vec<usize> = (0..colours.len()).collect();
+        order.sort_by_key(|index| {
+            let [red, green, blue, alpha] = colours[*index].map(u32::from);
+            (alpha, 299 * red + 587 * green + 114 * blue)
+        });
+
+        // Indices past the end of the palette aren't moved
+        let mut moved_to: [u8; 256] = std::array::from_fn(|index| index as u8);
+        for (new_index, old_index) in order.iter().enumerate() {
+            let [red, green, blue, alpha] = colours[*old_index];
+            self.palette[new_index * 3..][..3].copy_from_slice(&[red, green, blue]);
+            self.alpha[new_index] = alpha;
+            moved_to[*old_index] = new_index as u8;
+        }
+
+        for index in &mut self.indices {
+            *index = moved_to[*index as usize];
+        }
+        self.background = self.background.map(|index| moved_to[index as usize]);
+        // The histogram has two bytes for each colour, and is dropped if it doesn't
+        self.histogram = self
+            .histogram
+            .take()
+            .filter(|histogram| histogram.len() == colours.len() * 2)
+            .map(|histogram| {
+                order
+                    .iter()
+                    .flat_map(|index| [histogram[index * 2], histogram[index * 2 + 1]])
+                    .collect()
+            });
+    }
+
+    /// Writes the image out as an indexed PNG with the same palette size and bit depth.
+    fn write(
+        &self,
+        compression: Compression,
+        output: &mut dyn Write,
+    ) -> std::result::Result<(), png::EncodingError> {
+        let mut encoder = png::Encoder::new(BufWriter::new(output), self.width, self.height);
+        encoder.set_color(ColorType::Indexed);
+        encoder.set_depth(self.bit_depth);
+        encoder.set_palette(self.palette.as_slice());
+        // Opaque colours at the end of the palette are left out, as they usually are
+        let translucent_colours = self
+            .alpha
+            .iter()
+            .rposition(|alpha| *alpha != u8::MAX)
+            .map_or(0, |last| last + 1);
+        if translucent_colours > 0 {
+            encoder.set_trns(&self.alpha[..translucent_colours]);
+        }
+        encoder.set_compression(compression);
+        // Filtering rarely helps palette indices compress
+        encoder.set_filter(FilterType::NoFilter);
+
+        let mut writer = encoder.write_header()?;
+        if let Some(background) = self.background {
+            writer.write_chunk(ChunkType(*b"bKGD"), &[background])?;
+        }
+        if let Some(histogram) = &self.histogram {
+            writer.write_chunk(ChunkType(*b"hIST"), histogram)?;
+        }
+        writer.write_image_data(&pack(&self.indices, self.width, self.bit_depth as u8))?;
+
+        writer.finish()
+    }
+}
+
+/// Finds the amount of space in bytes, that can be used to store a secret file.
+///
+/// Every pixel provides a single bit, except those whose colour can't be swapped for another,
+/// and those the alpha mode leaves out because of their transparency.
+pub fn available_size_of(contents: &[u8], alpha: AlphaMode) -> std::result::Result<u64, ErrorType> {
+    // The pairs of colours are only known once the palette is sorted the way encoding sorts it
+    let mut image = read_image(contents)?;
+    image.sort_palette();
+    let slot_count = image.slot_count(image.alpha_flags(alpha));
+    log::trace!("Found {slot_count} usable pixels in an indexed PNG");
+
+    Ok((slot_count / 8).saturating_sub(HEADER_BYTES))
+}
+
+/// Encodes the payload into the base indexed PNG, and writes the results to the output image.
+///
+/// The payload is stored after its header in the lowest bit of each usable pixel's palette
+/// index, in the order given by the slot order. The header records which pixels the alpha mode
+/// chose.
+pub fn encode(
+    base_image: &[u8],
+    payload: &SealedPayload,
+    alpha: AlphaMode,
+    order: &SlotOrder,
+    progress: &Progress,
+    output_image: &mut dyn Write,
+) -> Result<()> {
+    log::info!("Beginning the encoding process into an indexed PNG");
+
+    let mut image = base_context!(read_image(base_image))?;
+    image.sort_palette();
+    log::trace!("Sorted the palette by brightness");
+
+    let flags = image.alpha_flags(alpha);
+    let payload = payload.with_carrier_flags(Algorithm::PaletteIndices, 1, flags);
+    let slot_count = image.slot_count(flags);
+    let usable: Vec<bool> = (0..=u8::MAX)
+        .map(|index| image.is_slot(index, flags))
+        .collect();
+    let mut pixels: Vec<&mut u8> = image
+        .indices
+        .iter_mut()
+        .filter(|index| usable[**index as usize])
+        .collect();
+
+    let secret_data = progress.track(split_bits(&payload, 1), 1, payload.len() as u64);
+    for (slot, bit) in order.iter(slot_count).zip(secret_data) {
+        let index = &mut pixels[slot as usize];
+        **index = (**index & !1) | bit;
+    }
+    output_context!(progress.check())?;
+
+    log::trace!("Updated the palette indices with the secret file's data");
+
+    // Compressing as hard as the base image was, so the file size stays close
+    let compression = match compression_of(base_image) {
+        CompressionType::Fast => Compression::Fast,
+        CompressionType::Best => Compression::Best,
+        _ => Compression::Default,
+    };
+    output_context!(image
+        .write(compression, output_image)
+        .map_err(|error| match error {
+            png::EncodingError::IoError(error) => error.into(),
+            error => ErrorType::ImageError(error.to_string()),
+        }))
+}
+
+/// Decodes the encoded indexed PNG, returning the header and the payload that follows it.
+///
+/// The slot order must match the one the payload was encoded with. The pixels the alpha mode
+/// chose are found by looking for a header that records them.
+pub fn decode(
+    encoded_image: &[u8],
+    order: &SlotOrder,
+    progress: &Progress,
+) -> Result<(Header, Vec<u8>)> {
+    log::info!("Beginning the decoding process from an indexed PNG");
+
+    let image = encoded_context!(read_image(encoded_image))?;
+    log::trace!("Parsed the encoded indexed PNG");
+
+    let mut header_error = CorruptionType::NoPayloadFound;
+    for flags in ALPHA_FLAG_CHOICES {
+        let pixels: Vec<u8> = image
+            .indices
+            .iter()
+            .filter(|index| image.is_slot(**index, flags))
+            .map(|index| index & 1)
+            .collect();
+        let bits = order
+            .iter(pixels.len() as u64)
+            .map(|slot| pixels[slot as usize]);
+        let mut secret_data = join_bits(bits, 1);
+
+        match Header::from_bytes(&mut secret_data) {
+            Ok(header) if header.algorithm != Algorithm::PaletteIndices => {
+                header_error = CorruptionType::IncorrectHeader;
+            }
+            // The pixels chosen by different alpha modes can start in the same places
+            Ok(header) if header.flags & Header::ALPHA_FLAGS != flags => {}
+            Ok(header) => {
+                log::trace!(
+                    "Decoded the header. Payload size of {}",
+                    header.payload_size
+                );
+
+                let payload =
+                    header.read_payload(progress.track(secret_data, 8, header.payload_size));
+                encoded_context!(progress.check())?;
+                let payload = encoded_context!(payload)?;
+                log::trace!("Read the payload from the indexed PNG");
+
+                return Ok((header, payload));
+            }
+            Err(CorruptionType::NoPayloadFound) => {}
+            // Too few transparent pixels for a header just means that mode wasn't used
+            Err(CorruptionType::FileTooSmallForHeader) if flags != 0 => {}
+            Err(err) => header_error = err,
+        }
+    }
+
+    encoded_context!(Err(header_error))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, path::PathBuf};
+
+    use png::{BitDepth, ColorType};
+
+    use crate::{
+        base_context, decode_bytes, encode_bytes, encoded_context,
+        file_types::{
+            image::palette::{available_size_of, pack, read_image, IndexedImage},
+            supported_file::SupportedFileType,
+        },
+        header::HEADER_BYTES,
+        AlphaMode, DecodeOptions, EncodeOptions, ErrorType, Result,
+    };
+
+    fn test_data_dir() -> PathBuf {
+        "./test_data".into()
+    }
+
+    /// Writes an indexed PNG with a palette of `colours` greys from dark to light, whose first
+    /// colours have the given alphas.
+    fn base_png(name: &str, bit_depth: BitDepth, colours: u8, trns: &[u8]) -> PathBuf {
+        let path = test_data_dir().join(format!("{name}.result.png"));
+        let (width, height) = (128, 96);
+        let palette: Vec<u8> = (0..colours as u32)
+            .flat_map(|index| [(index * 255 / colours as u32) as u8; 3])
+            .collect();
+        let indices: Vec<u8> = (0..width * height)
+            .map(|pixel| ((pixel * 7 + pixel / width) % colours as u32) as u8)
+            .collect();
+
+        let mut encoder = png::Encoder::new(File::create(&path).unwrap(), width, height);
+        encoder.set_color(ColorType::Indexed);
+        encoder.set_depth(bit_depth);
+        encoder.set_palette(palette);
+        encoder.set_trns(trns);
+        let mut writer = encoder.write_header().unwrap();
+        writer
+            .write_image_data(&pack(&indices, width, bit_depth as u8))
+            .unwrap();
+        writer.finish().unwrap();
+
+        path
+    }
+
+    /// The colour and alpha of every pixel.
+    fn pixels_of(image: &IndexedImage) -> Vec<[u8; 4]> {
+        image
+            .indices
+            .iter()
+            .map(|index| {
+                let [red, green, blue] = image.palette[*index as usize * 3..][..3] else {
+                    unreachable!()
+                };
+                [red, green, blue, image.alpha[*index as usize]]
+            })
+            .collect()
+    }
+
+    /// Checks that a secret survives being hidden in the indexed PNG, that the output is still
+    /// indexed with the same palette, and that every pixel keeps its transparency.
+    fn assert_round_trip(name: &str, bit_depth: BitDepth, colours: u8) -> Result<()> {
+        let base = std::fs::read(base_png(name, bit_depth, colours, &[255, 128])).unwrap();
+        let options = EncodeOptions {
+            key: Some("key".to_owned()),
+            ..Default::default()
+        };
+        let encoded = encode_bytes(&base, SupportedFileType::Png, b"A palette secret", &options)?;
+
+        let decode_options = DecodeOptions {
+            key: Some("key".to_owned()),
+            ..Default::default()
+        };
+        let decoded = decode_bytes(&encoded, SupportedFileType::Png, &decode_options)?;
+        assert_eq!(decoded, b"A palette secret");
+
+        let base = read_image(&base).unwrap();
+        let encoded = read_image(&encoded).unwrap();
+        assert_eq!(encoded.bit_depth, base.bit_depth);
+        assert_eq!(encoded.palette.len(), base.palette.len());
+        assert_ne!(encoded.indices, base.indices);
+
+        let mut base_colours: Vec<&[u8]> = base.palette.chunks(3).collect();
+        let mut encoded_colours: Vec<&[u8]> = encoded.palette.chunks(3).collect();
+        base_colours.sort();
+        encoded_colours.sort();
+        assert_eq!(base_colours, encoded_colours);
+
+        let alpha_of = |image: &IndexedImage| -> Vec<u8> {
+            image
+                .indices
+                .iter()
+                .map(|index| image.alpha[*index as usize])
+                .collect()
+        };
+        assert_eq!(alpha_of(&base), alpha_of(&encoded));
+
+        Ok(())
+    }
+
+    #[test]
+    fn eight_bit_round_trip() -> Result<()> {
+        assert_round_trip("palette_8_bit", BitDepth::Eight, 200)
+    }
+
+    #[test]
+    fn four_bit_round_trip() -> Result<()> {
+        assert_round_trip("palette_4_bit", BitDepth::Four, 13)
+    }
+
+    #[test]
+    fn capacity_skips_unpaired_colours() -> std::result::Result<(), ErrorType> {
+        let base = std::fs::read(base_png(
+            "palette_capacity",
+            BitDepth::Four,
+            13,
+            &[255, 128],
+        ))?;
+
+        // Sorting pairs the half transparent colour with the darkest opaque colour, and leaves the
+        // lightest of an odd number of colours without a pair, so none of them can be swapped
+        let (width, height) = (128u32, 96u32);
+        let usable_pixels = (0..width * height)
+            .filter(|pixel| !matches!((pixel * 7 + pixel / width) % 13, 0 | 1 | 12))
+            .count() as u64;
+        assert_eq!(
+            available_size_of(&base, AlphaMode::ColourOnly)?,
+            usable_pixels / 8 - HEADER_BYTES
+        );
+
+        Ok(())
+    }
+
+    /// Checks that a secret survives being hidden in an indexed PNG with two fully transparent
+    /// colours using the alpha mode, then returns the image before and after.
+    fn assert_alpha_round_trip(alpha: AlphaMode) -> Result<(IndexedImage, IndexedImage)> {
+        let base = base_png("palette_alpha", BitDepth::Four, 16, &[0, 0]);
+        let base = base_context!(std::fs::read(base))?;
+        let options = EncodeOptions {
+            key: Some("key".to_owned()),
+            alpha,
+            ..Default::default()
+        };
+        let encoded = encode_bytes(&base, SupportedFileType::Png, b"A palette secret", &options)?;
+
+        let decode_options = DecodeOptions {
+            key: Some("key".to_owned()),
+            ..Default::default()
+        };
+        let decoded = decode_bytes(&encoded, SupportedFileType::Png, &decode_options)?;
+        assert_eq!(decoded, b"A palette secret");
+
+        Ok((
+            base_context!(read_image(&base))?,
+            encoded_context!(read_image(&encoded))?,
+        ))
+    }
+
+    #[test]
+    fn skip_transparent_round_trip() -> Result<()> {
+        let (base, encoded) = assert_alpha_round_trip(AlphaMode::SkipTransparent)?;
+        let (base, encoded) = (pixels_of(&base), pixels_of(&encoded));
+        assert_ne!(base, encoded);
+        for (base, encoded) in base.iter().zip(&encoded) {
+            if base[3] == 0 {
+                assert_eq!(
+                    base, encoded,
+                    "Transparent pixels should be left as they are"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn only_transparent_round_trip() -> Result<()> {
+        let (base, encoded) = assert_alpha_round_trip(AlphaMode::OnlyTransparent)?;
+        let (base, encoded) = (pixels_of(&base), pixels_of(&encoded));
+        assert_ne!(base, encoded);
+        for (base, encoded) in base.iter().zip(&encoded) {
+            if base[3] != 0 {
+                assert_eq!(base, encoded, "Visible pixels should be left as they are");
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn alpha_modes_change_the_space() -> std::result::Result<(), ErrorType> {
+        let base = std::fs::read(base_png("palette_alpha_space", BitDepth::Four, 16, &[0, 0]))?;
+
+        // Two of the sixteen colours are transparent, and each is used by an eighth of the pixels
+        let pixels = 128 * 96;
+        assert_eq!(
+            available_size_of(&base, AlphaMode::ColourOnly)?,
+            pixels / 8 - HEADER_BYTES
+        );
+        assert_eq!(
+            available_size_of(&base, AlphaMode::SkipTransparent)?,
+            pixels * 14 / 16 / 8 - HEADER_BYTES
+        );
+        assert_eq!(
+            available_size_of(&base, AlphaMode::OnlyTransparent)?,
+            pixels * 2 / 16 / 8 - HEADER_BYTES
+        );
+        // The alpha of a pixel can't change without changing its colour
+        assert_eq!(
+            available_size_of(&base, AlphaMode::IncludeAlpha)?,
+            pixels / 8 - HEADER_BYTES
+        );
+
+        // Without any transparency, every mode is the same as only using the colours
+        let opaque = std::fs::read(base_png("palette_opaque", BitDepth::Four, 16, &[]))?;
+        assert_eq!(
+            available_size_of(&opaque, AlphaMode::IncludeAlpha)?,
+            pixels / 8 - HEADER_BYTES
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,743 @@
+use crate::error::ErrorType;
+
+/// The order in which coefficients are stored in a block. Coefficient 0 is the DC coefficient,
+/// the rest are AC coefficients.
+const BLOCK_SIZE: usize = 64;
+
+/// The most blocks a JPEG may have, which take up a gibibyte of coefficients.
+const MAX_BLOCKS: usize = 1 << 22;
+
+/// A sequential Huffman-coded JPEG, decoded as far as its quantized DCT coefficients.
+///
+/// Everything other than the entropy coded data is kept verbatim, so writing the JPEG back out
+/// only changes the coefficients that were edited.
+pub struct Jpeg {
+    segments: Vec<Segment>,
+    components: Vec<Component>,
+    /// The number of MCUs across and down the image.
+    mcus_wide: usize,
+    mcus_high: usize,
+    /// The largest sampling factors of any component.
+    max_horizontal: usize,
+    max_vertical: usize,
+    width: usize,
+    height: usize,
+}
+
+/// A piece of the JPEG file.
+enum Segment {
+    /// Bytes copied to the output as they were, including any marker.
+    Verbatim(Vec<u8>),
+    /// A scan, whose entropy coded data is regenerated from the coefficients when writing.
+    Scan(Scan),
+}
+
+/// A colour component of the image, for example luminance.
+struct Component {
+    id: u8,
+    horizontal: usize,
+    vertical: usize,
+    /// The number of blocks in a row of this component, padded out to whole MCUs.
+    blocks_wide: usize,
+    /// The quantized coefficients of every block, row by row.
+    blocks: Vec<[i32; BLOCK_SIZE]>,
+}
+
+/// A start of scan segment and everything needed to encode its data again.
+struct Scan {
+    /// The SOS segment, kept verbatim.
+    header: Vec<u8>,
+    components: Vec<ScanComponent>,
+    restart_interval: usize,
+}
+
+/// A component within a scan, along with the Huffman tables that were in effect for it.
+struct ScanComponent {
+    index: usize,
+    dc_table: HuffmanTable,
+    ac_table: HuffmanTable,
+}
+
+/// The error used for any JPEG that can't be interpreted.
+fn malformed(reason: &str) -> ErrorType {
+    ErrorType::ImageError(format!("Malformed or unsupported JPEG: {reason}"))
+}
+
+/// The error used for a valid JPEG coded in a way that can't be used.
+fn unsupported(coding: &str) -> ErrorType {
+    ErrorType::ImageError(format!("Unsupported JPEG: {coding}"))
+}
+
+impl Jpeg {
+    /// Parses a JPEG, decoding the Huffman coded coefficients of every scan.
+    ///
+    /// Only sequential Huffman-coded JPEGs are supported. Progressive, lossless, hierarchical and
+    /// arithmetic coded JPEGs return an `ImageError` naming how they are coded.
+    pub fn parse(bytes: &[u8]) -> Result<Jpeg, ErrorType> {
+        if !bytes.starts_with(&[0xFF, 0xD8]) {
+            return Err(malformed("missing start of image marker"));
+        }
+
+        let mut jpeg = Jpeg {
+            segments: vec![Segment::Verbatim(vec![0xFF, 0xD8])],
+            components: vec![],
+            mcus_wide: 0,
+            mcus_high: 0,
+            max_horizontal: 1,
+            max_vertical: 1,
+            width: 0,
+            height: 0,
+        };
+        let mut dc_tables: [Option<HuffmanTable>; 4] = Default::default();
+        let mut ac_tables: [Option<HuffmanTable>; 4] = Default::default();
+        let mut restart_interval = 0;
+
+        let mut position = 2;
+        loop {
+            // Markers may be preceded by any number of fill bytes
+            while bytes.get(position) == Some(&0xFF) && bytes.get(position + 1) == Some(&0xFF) {
+                position += 1;
+            }
+            let marker = match bytes.get(position..position + 2) {
+                Some([0xFF, marker]) => *marker,
+                _ => return Err(malformed("expected a marker")),
+            };
+
+            // Markers without a length
+            if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+                jpeg.segments
+                    .push(Segment::Verbatim(bytes[position..position + 2].to_vec()));
+                position += 2;
+                continue;
+            }
+            if marker == 0xD9 {
+                // Anything after the end of the image is kept as is
+                jpeg.segments
+                    .push(Segment::Verbatim(bytes[position..].to_vec()));
+                break;
+            }
+
+            let length = match bytes.get(position + 2..position + 4) {
+                Some(length) => u16::from_be_bytes([length[0], length[1]]) as usize,
+                None => return Err(malformed("truncated marker segment")),
+            };
+            let segment = bytes
+                .get(position..position + 2 + length)
+                .filter(|_| length >= 2)
+                .ok_or_else(|| malformed("truncated marker segment"))?;
+            let contents = &segment[4..];
+            position += 2 + length;
+
+            match marker {
+                0xC0 | 0xC1 => jpeg.parse_frame(contents, bytes.len() - position)?,
+                0xC2 => return Err(unsupported("Progressive JPEG")),
+                0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => {
+                    return Err(unsupported(
+                        "Lossless, hierarchical or arithmetic coded JPEG",
+                    ))
+                }
+                0xC4 => parse_huffman_tables(contents, &mut dc_tables, &mut ac_tables)?,
+                0xDD => {
+                    let interval = contents
+                        .get(0..2)
+                        .ok_or_else(|| malformed("truncated restart interval"))?;
+                    restart_interval = u16::from_be_bytes([interval[0], interval[1]]) as usize;
+                }
+                0xDA => {
+                    let scan =
+                        jpeg.parse_scan(segment, &dc_tables, &ac_tables, restart_interval)?;
+                    position += jpeg.decode_scan(&scan, &bytes[position..])?;
+                    jpeg.segments.push(Segment::Scan(scan));
+                    continue;
+                }
+                _ => {}
+            }
+
+            jpeg.segments.push(Segment::Verbatim(segment.to_vec()));
+        }
+
+        if jpeg.components.is_empty() {
+            return Err(malformed("missing frame header"));
+        }
+
+        Ok(jpeg)
+    }
+
+    /// Reads the image's dimensions and components from a start of frame segment, followed by
+    /// the given number of bytes of the file.
+    fn parse_frame(&mut self, contents: &[u8], bytes_after: usize) -> Result<(), ErrorType> {
+        if !self.components.is_empty() {
+            return Err(malformed("more than one frame"));
+        }
+        if contents.len() < 6 {
+            return Err(malformed("truncated frame header"));
+        }
+
+        self.height = u16::from_be_bytes([contents[1], contents[2]]) as usize;
+        self.width = u16::from_be_bytes([contents[3], contents[4]]) as usize;
+        let component_count = contents[5] as usize;
+        let component_specs = contents
+            .get(6..6 + component_count * 3)
+            .filter(|_| component_count > 0)
+            .ok_or_else(|| malformed("truncated frame header"))?;
+        if self.width == 0 || self.height == 0 {
+            return Err(malformed("the image has no size"));
+        }
+
+        for spec in component_specs.chunks_exact(3) {
+            let (horizontal, vertical) = ((spec[1] >> 4) as usize, (spec[1] & 0xF) as usize);
+            if !(1..=4).contains(&horizontal) || !(1..=4).contains(&vertical) {
+                return Err(malformed("invalid sampling factors"));
+            }
+
+            self.max_horizontal = self.max_horizontal.max(horizontal);
+            self.max_vertical = self.max_vertical.max(vertical);
+            self.components.push(Component {
+                id: spec[0],
+                horizontal,
+                vertical,
+                blocks_wide: 0,
+                blocks: vec![],
+            });
+        }
+
+        self.mcus_wide = self.width.div_ceil(8 * self.max_horizontal);
+        self.mcus_high = self.height.div_ceil(8 * self.max_vertical);
+
+        // Every block is coded in at least two bits, its DC difference and end of block codes,
+        // so a frame claiming more blocks than the rest of the file could hold is refused before
+        // they're allocated
+        let block_count: usize = self
+            .components
+            .iter()
+            .map(|component| {
+                self.mcus_wide * component.horizontal * self.mcus_high * component.vertical
+            })
+            .sum();
+        if block_count > MAX_BLOCKS || block_count > bytes_after.saturating_mul(4) {
+            log::debug!("The frame claims {block_count} blocks in {bytes_after} bytes");
+            return Err(malformed("more blocks than the file could hold"));
+        }
+
+        for component in &mut self.components {
+            component.blocks_wide = self.mcus_wide * component.horizontal;
+            let blocks_high = self.mcus_high * component.vertical;
+            component.blocks = vec![[0; BLOCK_SIZE]; component.blocks_wide * blocks_high];
+        }
+
+        Ok(())
+    }
+
+    /// Reads which components and tables a scan uses from its start of scan segment.
+    fn parse_scan(
+        &self,
+        segment: &[u8],
+        dc_tables: &[Option<HuffmanTable>; 4],
+        ac_tables: &[Option<HuffmanTable>; 4],
+        restart_interval: usize,
+    ) -> Result<Scan, ErrorType> {
+        let contents = &segment[4..];
+        let component_count = *contents
+            .first()
+            .ok_or_else(|| malformed("truncated scan header"))?
+            as usize;
+        let component_specs = contents
+            .get(1..1 + component_count * 2)
+            .filter(|_| (1..=4).contains(&component_count))
+            .ok_or_else(|| malformed("truncated scan header"))?;
+
+        let mut components = vec![];
+        for spec in component_specs.chunks_exact(2) {
+            let index = self
+                .components
+                .iter()
+                .position(|component| component.id == spec[0])
+                .ok_or_else(|| malformed("scan uses an unknown component"))?;
+            let table = |tables: &[Option<HuffmanTable>; 4], id: u8| {
+                tables
+                    .get(id as usize)
+                    .cloned()
+                    .flatten()
+                    .ok_or_else(|| malformed("scan uses an undefined Huffman table"))
+            };
+
+            components.push(ScanComponent {
+                index,
+                dc_table: table(dc_tables, spec[1] >> 4)?,
+                ac_table: table(ac_tables, spec[1] & 0xF)?,
+            });
+        }
+
+        Ok(Scan {
+            header: segment.to_vec(),
+            components,
+            restart_interval,
+        })
+    }
+
+    /// Lists the blocks of a scan, grouped into MCUs, as (component, block) indices.
+    fn scan_mcus(&self, scan: &Scan) -> Vec<Vec<(usize, usize)>> {
+        if let [scan_component] = scan.components.as_slice() {
+            // A scan of a single component codes each block on its own, and only the blocks
+            // that cover the image rather than every block in the padded MCUs
+            let component = &self.components[scan_component.index];
+            let blocks_wide = (self.width * component.horizontal)
+                .div_ceil(self.max_horizontal)
+                .div_ceil(8);
+            let blocks_high = (self.height * component.vertical)
+                .div_ceil(self.max_vertical)
+                .div_ceil(8);
+
+            return (0..blocks_high)
+                .flat_map(|row| {
+                    (0..blocks_wide).map(move |column| {
+                        vec![(scan_component.index, row * component.blocks_wide + column)]
+                    })
+                })
+                .collect();
+        }
+
+        let mut mcus = vec![];
+        for mcu_row in 0..self.mcus_high {
+            for mcu_column in 0..self.mcus_wide {
+                let mut mcu = vec![];
+                for scan_component in &scan.components {
+                    let component = &self.components[scan_component.index];
+                    for row in 0..component.vertical {
+                        for column in 0..component.horizontal {
+                            let row = mcu_row * component.vertical + row;
+                            let column = mcu_column * component.horizontal + column;
+                            mcu.push((scan_component.index, row * component.blocks_wide + column));
+                        }
+                    }
+                }
+                mcus.push(mcu);
+            }
+        }
+
+        mcus
+    }
+
+    /// Decodes the entropy coded data following a scan header into the components' blocks.
+    ///
+    /// Returns the number of bytes of entropy coded data.
+    fn decode_scan(&mut self, scan: &Scan, bytes: &[u8]) -> Result<usize, ErrorType> {
+        let (intervals, length) = split_entropy_coded_data(bytes);
+        let mut intervals = intervals.into_iter();
+
+        let mut reader = BitReader::new(intervals.next().unwrap_or_default());
+        let mut predictions = vec![0; scan.components.len()];
+
+        for (mcu_index, mcu) in self.scan_mcus(scan).into_iter().enumerate() {
+            if scan.restart_interval > 0 && mcu_index > 0 && mcu_index % scan.restart_interval == 0
+            {
+                reader = BitReader::new(
+                    intervals
+                        .next()
+                        .ok_or_else(|| malformed("missing restart marker"))?,
+                );
+                predictions.fill(0);
+            }
+
+            for (component_index, block_index) in mcu {
+                let position = scan
+                    .components
+                    .iter()
+                    .position(|scan_component| scan_component.index == component_index)
+                    .expect("Every block in an MCU belongs to a component of the scan");
+                let scan_component = &scan.components[position];
+
+                decode_block(
+                    &mut reader,
+                    &scan_component.dc_table,
+                    &scan_component.ac_table,
+                    &mut predictions[position],
+                    &mut self.components[component_index].blocks[block_index],
+                )?;
+            }
+        }
+
+        Ok(length)
+    }
+
+    /// Encodes the components' blocks into the entropy coded data of a scan.
+    fn encode_scan(&self, scan: &Scan, output: &mut Vec<u8>) -> Result<(), ErrorType> {
+        let mut writer = BitWriter::new(output);
+        let mut predictions = vec![0; scan.components.len()];
+
+        for (mcu_index, mcu) in self.scan_mcus(scan).into_iter().enumerate() {
+            if scan.restart_interval > 0 && mcu_index > 0 && mcu_index % scan.restart_interval == 0
+            {
+                let restart_number = (mcu_index / scan.restart_interval - 1) % 8;
+                writer.flush();
+                writer.output.extend([0xFF, 0xD0 + restart_number as u8]);
+                predictions.fill(0);
+            }
+
+            for (component_index, block_index) in mcu {
+                let position = scan
+                    .components
+                    .iter()
+                    .position(|scan_component| scan_component.index == component_index)
+                    .expect("Every block in an MCU belongs to a component of the scan");
+                let scan_component = &scan.components[position];
+
+                encode_block(
+                    &mut writer,
+                    &scan_component.dc_table,
+                    &scan_component.ac_table,
+                    &mut predictions[position],
+                    &self.components[component_index].blocks[block_index],
+                )?;
+            }
+        }
+
+        writer.flush();
+        Ok(())
+    }
+
+    /// Writes the JPEG back out, re-encoding every scan from the current coefficients.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ErrorType> {
+        let mut output = vec![];
+        for segment in &self.segments {
+            match segment {
+                Segment::Verbatim(bytes) => output.extend(bytes),
+                Segment::Scan(scan) => {
+                    output.extend(&scan.header);
+                    self.encode_scan(scan, &mut output)?;
+                }
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// Iterates over every AC coefficient of every block, component by component.
+    ///
+    /// DC coefficients are skipped, as changes to them are visible as blocks changing brightness.
+    pub fn ac_coefficients(&self) -> impl Iterator<Item = &i32> {
+        self.components
+            .iter()
+            .flat_map(|component| component.blocks.iter().flat_map(|block| block[1..].iter()))
+    }
+
+    /// Iterates mutably over the coefficients in the same order as `ac_coefficients`.
+    pub fn ac_coefficients_mut(&mut self) -> impl Iterator<Item = &mut i32> {
+        self.components.iter_mut().flat_map(|component| {
+            component
+                .blocks
+                .iter_mut()
+                .flat_map(|block| block[1..].iter_mut())
+        })
+    }
+}
+
+/// Reads the Huffman tables defined in a DHT segment.
+fn parse_huffman_tables(
+    contents: &[u8],
+    dc_tables: &mut [Option<HuffmanTable>; 4],
+    ac_tables: &mut [Option<HuffmanTable>; 4],
+) -> Result<(), ErrorType> {
+    let mut remaining = contents;
+    while let [class_and_id, rest @ ..] = remaining {
+        let counts: [u8; 16] = rest
+            .get(..16)
+            .and_then(|counts| counts.try_into().ok())
+            .ok_or_else(|| malformed("truncated Huffman table"))?;
+        let symbol_count = counts.iter().map(|count| *count as usize).sum::<usize>();
+        let symbols = rest
+            .get(16..16 + symbol_count)
+            .ok_or_else(|| malformed("truncated Huffman table"))?;
+
+        let table = Some(HuffmanTable::new(counts, symbols.to_vec())?);
+        match (class_and_id >> 4, (class_and_id & 0xF) as usize) {
+            (0, id @ 0..=3) => dc_tables[id] = table,
+            (1, id @ 0..=3) => ac_tables[id] = table,
+            _ => return Err(malformed("invalid Huffman table class or id")),
+        }
+
+        remaining = &rest[16 + symbol_count..];
+    }
+
+    Ok(())
+}
+
+/// Finds the end of a scan's entropy coded data, splitting it at restart markers and removing
+/// the stuffed zero bytes that follow every 0xFF.
+///
+/// Returns the restart intervals and the total number of bytes of entropy coded data.
+fn split_entropy_coded_data(bytes: &[u8]) -> (Vec<Vec<u8>>, usize) {
+    let mut intervals = vec![vec![]];
+    let mut position = 0;
+
+    while position < bytes.len() {
+        let byte = bytes[position];
+        if byte != 0xFF {
+            intervals.last_mut().unwrap().push(byte);
+            position += 1;
+            continue;
+        }
+
+        match bytes.get(position + 1) {
+            Some(0x00) => {
+                intervals.last_mut().unwrap().push(0xFF);
+                position += 2;
+            }
+            Some(0xD0..=0xD7) => {
+                intervals.push(vec![]);
+                position += 2;
+            }
+            // Fill bytes before a marker
+            Some(0xFF) => position += 1,
+            _ => break,
+        }
+    }
+
+    (intervals, position)
+}
+
+/// A canonical Huffman table, as defined by a DHT segment.
+#[derive(Debug, Clone)]
+struct HuffmanTable {
+    symbols: Vec<u8>,
+    /// For each code length, the largest code of that length, or -1 if there are none.
+    max_codes: [i32; 17],
+    /// For each code length, the first code of that length minus the index of its symbol.
+    offsets: [i32; 17],
+    /// The code and code length for each symbol.
+    codes: [Option<(u16, u8)>; 256],
+}
+
+impl HuffmanTable {
+    fn new(counts: [u8; 16], symbols: Vec<u8>) -> Result<HuffmanTable, ErrorType> {
+        let mut table = HuffmanTable {
+            symbols,
+            max_codes: [-1; 17],
+            offsets: [0; 17],
+            codes: [None; 256],
+        };
+
+        let mut code = 0u32;
+        let mut index = 0;
+        for (length, count) in (1..=16).zip(counts) {
+            table.offsets[length] = code as i32 - index as i32;
+            for _ in 0..count {
+                if code >= 1 << length {
+                    return Err(malformed("invalid Huffman table"));
+                }
+
+                table.codes[table.symbols[index] as usize] = Some((code as u16, length as u8));
+                code += 1;
+                index += 1;
+            }
+            table.max_codes[length] = if count > 0 { code as i32 - 1 } else { -1 };
+            code <<= 1;
+        }
+
+        Ok(table)
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u8, ErrorType> {
+        let mut code = 0;
+        for length in 1..=16 {
+            code = (code << 1) | reader.read_bit()? as i32;
+            if code <= self.max_codes[length] {
+                return Ok(self.symbols[(code - self.offsets[length]) as usize]);
+            }
+        }
+
+        Err(malformed("invalid Huffman code"))
+    }
+
+    fn encode(&self, writer: &mut BitWriter, symbol: u8) -> Result<(), ErrorType> {
+        let (code, length) = self.codes[symbol as usize]
+            .ok_or_else(|| malformed("the coefficients can't be coded with the original tables"))?;
+        writer.write_bits(code as u32, length);
+
+        Ok(())
+    }
+}
+
+/// Reads bits from the unstuffed entropy coded data of a restart interval.
+struct BitReader {
+    bytes: Vec<u8>,
+    position: usize,
+    bit: u8,
+}
+
+impl BitReader {
+    fn new(bytes: Vec<u8>) -> BitReader {
+        BitReader {
+            bytes,
+            position: 0,
+            bit: 0,
+        }
+    }
+
+    fn read_bit(&mut self) -> Result<u32, ErrorType> {
+        let byte = self
+            .bytes
+            .get(self.position)
+            .ok_or_else(|| malformed("entropy coded data ended early"))?;
+        let bit = (byte >> (7 - self.bit)) & 1;
+
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.position += 1;
+        }
+
+        Ok(bit as u32)
+    }
+
+    fn read_bits(&mut self, count: u8) -> Result<u32, ErrorType> {
+        let mut value = 0;
+        for _ in 0..count {
+            value = (value << 1) | self.read_bit()?;
+        }
+
+        Ok(value)
+    }
+}
+
+/// Writes bits as entropy coded data, stuffing a zero byte after every 0xFF.
+struct BitWriter<'a> {
+    output: &'a mut Vec<u8>,
+    buffer: u32,
+    buffered_bits: u8,
+}
+
+impl<'a> BitWriter<'a> {
+    fn new(output: &'a mut Vec<u8>) -> BitWriter<'a> {
+        BitWriter {
+            output,
+            buffer: 0,
+            buffered_bits: 0,
+        }
+    }
+
+    fn write_bits(&mut self, bits: u32, count: u8) {
+        for shift in (0..count).rev() {
+            self.buffer = (self.buffer << 1) | ((bits >> shift) & 1);
+            self.buffered_bits += 1;
+
+            if self.buffered_bits == 8 {
+                let byte = self.buffer as u8;
+                self.output.push(byte);
+                if byte == 0xFF {
+                    self.output.push(0x00);
+                }
+
+                self.buffer = 0;
+                self.buffered_bits = 0;
+            }
+        }
+    }
+
+    /// Pads the final byte with ones, as is done before restart markers and the end of a scan.
+    fn flush(&mut self) {
+        if self.buffered_bits > 0 {
+            self.write_bits(u32::MAX, 8 - self.buffered_bits);
+        }
+    }
+}
+
+/// Converts the `size` bits following a Huffman code into a signed coefficient value.
+fn extend(bits: u32, size: u8) -> i32 {
+    if size == 0 {
+        0
+    } else if bits < 1 << (size - 1) {
+        bits as i32 - (1 << size) + 1
+    } else {
+        bits as i32
+    }
+}
+
+/// The inverse of `extend`, returning the number of bits needed for a value and those bits.
+fn magnitude(value: i32) -> (u8, u32) {
+    let size = (u32::BITS - value.unsigned_abs().leading_zeros()) as u8;
+    let bits = if value < 0 {
+        value - 1 + (1 << size)
+    } else {
+        value
+    };
+
+    (size, bits as u32)
+}
+
+fn decode_block(
+    reader: &mut BitReader,
+    dc_table: &HuffmanTable,
+    ac_table: &HuffmanTable,
+    prediction: &mut i32,
+    block: &mut [i32; BLOCK_SIZE],
+) -> Result<(), ErrorType> {
+    let size = dc_table.decode(reader)?;
+    if size > 16 {
+        return Err(malformed("invalid DC coefficient size"));
+    }
+    *prediction += extend(reader.read_bits(size)?, size);
+    block[0] = *prediction;
+
+    let mut index = 1;
+    while index < BLOCK_SIZE {
+        let run_and_size = ac_table.decode(reader)?;
+        let (run, size) = ((run_and_size >> 4) as usize, run_and_size & 0xF);
+
+        if size == 0 {
+            if run == 15 {
+                // Sixteen zeros
+                index += 16;
+                continue;
+            }
+            // End of block, the rest are zeros
+            break;
+        }
+
+        index += run;
+        if index >= BLOCK_SIZE {
+            return Err(malformed("too many coefficients in a block"));
+        }
+        block[index] = extend(reader.read_bits(size)?, size);
+        index += 1;
+    }
+
+    Ok(())
+}
+
+fn encode_block(
+    writer: &mut BitWriter,
+    dc_table: &HuffmanTable,
+    ac_table: &HuffmanTable,
+    prediction: &mut i32,
+    block: &[i32; BLOCK_SIZE],
+) -> Result<(), ErrorType> {
+    let (size, bits) = magnitude(block[0] - *prediction);
+    *prediction = block[0];
+    dc_table.encode(writer, size)?;
+    writer.write_bits(bits, size);
+
+    let mut run = 0;
+    for &coefficient in &block[1..] {
+        if coefficient == 0 {
+            run += 1;
+            continue;
+        }
+
+        while run >= 16 {
+            ac_table.encode(writer, 0xF0)?;
+            run -= 16;
+        }
+
+        let (size, bits) = magnitude(coefficient);
+        ac_table.encode(writer, (run << 4) | size)?;
+        writer.write_bits(bits, size);
+        run = 0;
+    }
+
+    if run > 0 {
+        ac_table.encode(writer, 0x00)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,344 @@
+use std::io::Write;
+
+use image::ImageFormat;
+
+use crate::{
+    base_context, encoded_context,
+    error::ErrorType,
+    file_types::{
+        bits::{join_bits, split_bits},
+        carrier::Carrier,
+        supported_file::SupportedFileType,
+    },
+    header::{Algorithm, Header, HEADER_BYTES},
+    output_context,
+    payload::SealedPayload,
+    progress::Progress,
+    slot_order::SlotOrder,
+    CorruptionType, DecodeOptions, EncodeOptions, Result,
+};
+
+mod codec;
+
+use codec::Jpeg;
+
+/// Whether a coefficient can hold a bit of the payload.
+///
+/// Zeros are skipped as making them non-zero would change how the block is coded, and ones are
+/// skipped as clearing their lowest bit would turn them into zeros. Every other coefficient keeps
+/// its Huffman category when its lowest bit changes, so the file stays nearly the same size.
+fn is_usable(coefficient: i32) -> bool {
+    coefficient.abs() >= 2
+}
+
+/// Hides data in the quantized DCT coefficients of JPEGs.
+pub struct JpegCarrier;
+
+impl Carrier for JpegCarrier {
+    fn file_type(&self) -> SupportedFileType {
+        SupportedFileType::Jpeg
+    }
+
+    fn extensions(&self) -> &[&str] {
+        ImageFormat::Jpeg.extensions_str()
+    }
+
+    fn detect(&self, contents: &[u8]) -> bool {
+        image::guess_format(contents).is_ok_and(|format| format == ImageFormat::Jpeg)
+    }
+
+    fn capacity(&self, contents: &[u8], _options: &EncodeOptions) -> Result<u64> {
+        // JPEGs always store a single bit per coefficient
+        base_context!(available_size_of(contents))
+    }
+
+    fn embed(
+        &self,
+        contents: &[u8],
+        payload: &SealedPayload,
+        options: &EncodeOptions,
+        progress: &Progress,
+        output: &mut dyn Write,
+    ) -> Result<()> {
+        encode(
+            contents,
+            &payload.with_header(Algorithm::DctCoefficients, 1),
+            &SlotOrder::new(options.key.as_deref()),
+            progress,
+            output,
+        )
+    }
+
+    fn extract(
+        &self,
+        contents: &[u8],
+        options: &DecodeOptions,
+        progress: &Progress,
+    ) -> Result<(Header, Vec<u8>)> {
+        decode(contents, &SlotOrder::new(options.key.as_deref()), progress)
+    }
+}
+
+/// Parses the whole JPEG.
+fn read_jpeg(contents: &[u8]) -> std::result::Result<Jpeg, ErrorType> {
+    log::trace!("Parsing {} bytes of JPEG", contents.len());
+
+    Jpeg::parse(contents)
+}
+
+/// Finds the amount of space in bytes, that can be used to store a secret file.
+///
+/// Every AC coefficient with a magnitude of at least two provides a single bit.
+pub fn available_size_of(contents: &[u8]) -> std::result::Result<u64, ErrorType> {
+    let jpeg = read_jpeg(contents)?;
+    let usable_coefficients = jpeg
+        .ac_coefficients()
+        .filter(|coefficient| is_usable(**coefficient))
+        .count() as u64;
+    log::trace!("Found {usable_coefficients} usable coefficients in a JPEG");
+
+    Ok((usable_coefficients / 8).saturating_sub(HEADER_BYTES))
+}
+
+/// Encodes the payload into the base JPEG, and writes the results to the output file.
+///
+/// The payload is expected to start with its header, and is stored one bit at a time in the
+/// lowest bit of each usable coefficient's magnitude, in the order given by the slot order.
+pub fn encode(
+    base_image: &[u8],
+    payload: &[u8],
+    order: &SlotOrder,
+    progress: &Progress,
+    output_image: &mut dyn Write,
+) -> Result<()> {
+    log::info!("Beginning the encoding process into a JPEG");
+
+    let mut jpeg = base_context!(read_jpeg(base_image))?;
+    log::trace!("Parsed the base JPEG");
+
+    let mut coefficients: Vec<&mut i32> = jpeg
+        .ac_coefficients_mut()
+        .filter(|coefficient| is_usable(**coefficient))
+        .collect();
+
+    for (slot, bit) in order.iter(coefficients.len() as u64).zip(progress.track(
+        split_bits(payload, 1),
+        1,
+        payload.len() as u64,
+    )) {
+        let coefficient = &mut coefficients[slot as usize];
+        let magnitude = (coefficient.unsigned_abs() & !1) | bit as u32;
+        **coefficient = coefficient.signum() * magnitude as i32;
+    }
+    output_context!(progress.check())?;
+
+    log::trace!("Updated the coefficients with the secret file's data");
+
+    let bytes = output_context!(jpeg.to_bytes())?;
+    output_context!(output_image.write_all(&bytes))
+}
+
+/// Decodes the encoded JPEG, returning the header and the payload that follows it.
+///
+/// The slot order must match the one the payload was encoded with.
+pub fn decode(
+    encoded_image: &[u8],
+    order: &SlotOrder,
+    progress: &Progress,
+) -> Result<(Header, Vec<u8>)> {
+    log::info!("Beginning the decoding process from a JPEG");
+
+    let jpeg = encoded_context!(read_jpeg(encoded_image))?;
+    log::trace!("Parsed the encoded JPEG");
+
+    let coefficients: Vec<i32> = jpeg
+        .ac_coefficients()
+        .copied()
+        .filter(|coefficient| is_usable(*coefficient))
+        .collect();
+    let bits = order
+        .iter(coefficients.len() as u64)
+        .map(|slot| (coefficients[slot as usize].unsigned_abs() & 1) as u8);
+    let mut secret_data = join_bits(bits, 1);
+
+    let header = encoded_context!(Header::from_bytes(&mut secret_data))?;
+    if header.algorithm != Algorithm::DctCoefficients {
+        return encoded_context!(Err(CorruptionType::IncorrectHeader));
+    }
+    log::trace!(
+        "Decoded the header. Payload size of {}",
+        header.payload_size
+    );
+
+    let payload = header.read_payload(progress.track(secret_data, 8, header.payload_size));
+    encoded_context!(progress.check())?;
+    let payload = encoded_context!(payload)?;
+    log::trace!("Read the payload from the JPEG");
+
+    Ok((header, payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::Cursor, path::PathBuf};
+
+    use image::{codecs::jpeg::JpegEncoder, GenericImageView, RgbImage};
+
+    use crate::{
+        base_context, encoded_context,
+        file_types::{
+            base_file::BaseFile,
+            jpeg::{available_size_of, decode, encode},
+            supported_file::SupportedFileType,
+        },
+        header::Algorithm,
+        output_context,
+        payload::Payload,
+        progress::Progress,
+        slot_order::SlotOrder,
+        CorruptionType, DecodeOptions, EncodeOptions, ErrorType, Result,
+    };
+
+    fn test_data_dir() -> PathBuf {
+        "./test_data".into()
+    }
+
+    /// Saves a textured image as a JPEG, as flat colours would leave few usable coefficients.
+    fn base_jpeg(name: &str, quality: u8) -> PathBuf {
+        let path = test_data_dir().join(format!("{name}.result.jpg"));
+        let image = RgbImage::from_fn(203, 155, |x, y| {
+            let noise = (x * 7919 + y * 104_729) ^ (x * y);
+            image::Rgb([
+                (x * 2 + noise % 37) as u8,
+                (y * 3 + noise % 23) as u8,
+                ((x + y) + noise % 53) as u8,
+            ])
+        });
+
+        let mut file = File::create(&path).unwrap();
+        JpegEncoder::new_with_quality(&mut file, quality)
+            .encode_image(&image)
+            .unwrap();
+
+        path
+    }
+
+    /// Encodes a payload into the base JPEG, returning the output's path.
+    fn encode_into(base_path: &PathBuf, payload: &Payload, order: &SlotOrder) -> Result<PathBuf> {
+        let output_path = base_path.with_extension("encoded.jpg");
+        let base_image = base_context!(std::fs::read(base_path))?;
+        let mut output_image = output_context!(File::create(&output_path))?;
+        encode(
+            &base_image,
+            &payload
+                .seal(&EncodeOptions::default())
+                .with_header(Algorithm::DctCoefficients, 1),
+            order,
+            &Progress::default(),
+            &mut output_image,
+        )?;
+
+        Ok(output_path)
+    }
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let base_path = base_jpeg("jpeg_round_trip", 90);
+        let payload = Payload::new("secret.txt", b"Hidden in the coefficients".to_vec());
+        let output_path = encode_into(&base_path, &payload, &SlotOrder::sequential())?;
+
+        let encoded_file = encoded_context!(std::fs::read(&output_path))?;
+        let (header, bytes) = decode(
+            &encoded_file,
+            &SlotOrder::sequential(),
+            &Progress::default(),
+        )?;
+        let decoded = encoded_context!(Payload::unseal(&header, bytes, &DecodeOptions::default()))?;
+        assert_eq!(decoded, payload);
+
+        // The output is still a JPEG of the same image, and only the coefficients changed
+        let base = image::open(&base_path).unwrap();
+        let encoded = image::open(&output_path).unwrap();
+        assert_eq!(base.dimensions(), encoded.dimensions());
+
+        let base_size = std::fs::metadata(&base_path).unwrap().len() as f64;
+        let encoded_size = std::fs::metadata(&output_path).unwrap().len() as f64;
+        assert!((base_size - encoded_size).abs() / base_size < 0.01);
+
+        Ok(())
+    }
+
+    #[test]
+    fn keyed_round_trip() -> Result<()> {
+        let base_path = base_jpeg("jpeg_keyed_round_trip", 75);
+        let contents = (0..=255).cycle().take(600).collect();
+        let payload = Payload::new("secret.bin", contents);
+        let output_path = encode_into(&base_path, &payload, &SlotOrder::keyed("key"))?;
+
+        let encoded_file = encoded_context!(std::fs::read(&output_path))?;
+        let (header, bytes) = decode(
+            &encoded_file,
+            &SlotOrder::keyed("key"),
+            &Progress::default(),
+        )?;
+        let decoded = encoded_context!(Payload::unseal(&header, bytes, &DecodeOptions::default()))?;
+        assert_eq!(decoded, payload);
+
+        let error = decode(
+            &encoded_file,
+            &SlotOrder::sequential(),
+            &Progress::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error.error_type,
+            ErrorType::CorruptedFile(CorruptionType::NoPayloadFound)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn capacity_counts_usable_coefficients() -> std::result::Result<(), ErrorType> {
+        let low_quality = std::fs::read(base_jpeg("jpeg_low_quality", 30))?;
+        let high_quality = std::fs::read(base_jpeg("jpeg_high_quality", 95))?;
+
+        let low_quality = available_size_of(&low_quality)?;
+        assert!(low_quality > 0);
+        assert!(available_size_of(&high_quality)? > low_quality);
+
+        Ok(())
+    }
+
+    /// A JPEG made of only a start of frame segment with the given marker, for a single
+    /// component image of the given size.
+    fn frame_only(marker: u8, width: u16, height: u16) -> Vec<u8> {
+        let mut bytes = vec![0xFF, 0xD8, 0xFF, marker, 0, 11, 8];
+        bytes.extend(height.to_be_bytes());
+        bytes.extend(width.to_be_bytes());
+        bytes.extend([1, 1, 0x11, 0, 0xFF, 0xD9]);
+        bytes
+    }
+
+    #[test]
+    fn rejects_frames_larger_than_the_file() {
+        let error = available_size_of(&frame_only(0xC0, u16::MAX, u16::MAX)).unwrap_err();
+
+        assert!(matches!(error, ErrorType::ImageError(_)));
+    }
+
+    #[test]
+    fn progressive_jpegs_are_unsupported() {
+        let base = BaseFile::from_reader(
+            Cursor::new(frame_only(0xC2, 16, 16)),
+            SupportedFileType::Jpeg,
+        )
+        .unwrap();
+        let error = base.get_properties(&EncodeOptions::default()).unwrap_err();
+
+        assert!(matches!(
+            error.error_type,
+            ErrorType::ImageError(message) if message.contains("Progressive")
+        ));
+    }
+}
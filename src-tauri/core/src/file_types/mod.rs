@@ -1,4 +1,9 @@
+mod animation;
 pub mod base_file;
+pub mod bits;
+pub mod carrier;
 pub mod encoded_file;
 mod image;
+mod jpeg;
 pub mod supported_file;
+mod wav;
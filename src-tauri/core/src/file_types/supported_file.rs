@@ -0,0 +1,234 @@
+use std::{
+    fs,
+    io::{Read, Seek},
+    path::Path,
+};
+
+use crate::{
+    error::ErrorType,
+    file_types::carrier::{self, Carrier},
+};
+
+/// Represents a type of file that we support encoding a secret file into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum SupportedFileType {
+    // Lossless image formats that the image crate can both read and write. These all work with
+    // our image module, which rewrites the output in the same format as the base file.
+    Png,
+    Bmp,
+    Tiff,
+    Tga,
+    Qoi,
+    Pnm,
+    /// WebP files are always written back out losslessly.
+    WebP,
+    /// JPEGs are handled by our jpeg module, which hides data in their DCT coefficients rather
+    /// than their pixels so that it isn't lost to recompression.
+    Jpeg,
+    // Animated images, handled by our animation module so that every frame is kept.
+    Gif,
+    /// Animated PNGs share their extension with PNGs, and are told apart when opened.
+    Apng,
+    /// PCM WAV files, handled by our wav module.
+    Wav,
+    /// A type of file handled by a carrier registered from outside of this crate, by name.
+    Other(&'static str),
+    // TODO: Add support for Open Office format files
+    // TODO: Add support for PDFs
+    // TODO: Add support for other audio files
+    // TODO: Add support for video
+}
+
+impl SupportedFileType {
+    /// Finds the file type from a file's name.
+    ///
+    /// Returns None if the file type is not supported.
+    pub fn from_file_path<P: AsRef<Path>>(file_path: P) -> Option<SupportedFileType> {
+        carrier_from_file_path(file_path.as_ref()).map(|carrier| carrier.file_type())
+    }
+
+    /// Finds the file type from the magic bytes at the start of a file's contents.
+    ///
+    /// Returns None if the contents aren't recognised. Some formats, like TGA, have no magic bytes
+    /// and can only be found from their extension.
+    pub fn from_contents(contents: &[u8]) -> Option<SupportedFileType> {
+        carrier::from_contents(contents).map(|carrier| carrier.file_type())
+    }
+}
+
+/// Finds the carrier for a file from its extension.
+fn carrier_from_file_path(file_path: &Path) -> Option<&'static dyn Carrier> {
+    let extension = file_path.extension()?.to_string_lossy();
+    log::trace!("Parsed file extension");
+
+    let carrier = carrier::from_extension(&extension);
+    match carrier {
+        Some(carrier) => log::info!("Parsed {file_path:?} as {:?}", carrier.file_type()),
+        None => log::debug!("Didn't match against file extension: {extension:?}"),
+    }
+
+    carrier
+}
+
+/// The contents of a file along with the carrier for its type.
+///
+/// Every carrier has to parse the whole file, so it is read into memory up front. This lets files
+/// come from disk or from any other reader in the same way.
+pub struct SupportedFile {
+    contents: Vec<u8>,
+    carrier: &'static dyn Carrier,
+}
+
+impl SupportedFile {
+    /// Attempts to open an existing file.
+    ///
+    /// The file type is found from the file's contents, falling back to its extension when the
+    /// contents aren't recognised. Returns an error if the file isn't a supported type, if its
+    /// contents don't match its extension, or if the file can't be read.
+    pub fn open<P: AsRef<Path>>(file_path: P) -> Result<SupportedFile, ErrorType> {
+        let file_path = file_path.as_ref();
+        let contents = fs::read(file_path)?;
+
+        let from_extension = carrier_from_file_path(file_path);
+        let carrier = match carrier::from_contents(&contents) {
+            Some(from_contents) => {
+                log::trace!("Sniffed {file_path:?} as {:?}", from_contents.file_type());
+                match from_extension {
+                    Some(from_extension)
+                        if !carrier::share_an_extension(from_extension, from_contents) =>
+                    {
+                        return Err(ErrorType::FileTypeMismatch {
+                            from_extension: from_extension.file_type(),
+                            from_contents: from_contents.file_type(),
+                        });
+                    }
+                    _ => from_contents,
+                }
+            }
+            None => from_extension.ok_or(ErrorType::UnsupportedFileType(file_path.into()))?,
+        };
+
+        Ok(SupportedFile { contents, carrier })
+    }
+
+    /// Reads a file of the given type in full, from the start of the reader.
+    ///
+    /// Without a path, the given type takes the place of the extension. Returns an error if no
+    /// carrier handles the type, if the contents are recognised as a different type, or if the
+    /// reader fails.
+    pub fn from_reader(
+        mut reader: impl Read + Seek,
+        file_type: SupportedFileType,
+    ) -> Result<SupportedFile, ErrorType> {
+        let expected =
+            carrier::from_file_type(file_type).ok_or(ErrorType::UnregisteredFileType(file_type))?;
+
+        reader.rewind()?;
+        let mut contents = vec![];
+        reader.read_to_end(&mut contents)?;
+        log::trace!("Read {} bytes from a reader", contents.len());
+
+        let carrier = match carrier::from_contents(&contents) {
+            // Animated PNGs can be given as PNGs and the other way around, as the contents decide
+            Some(from_contents)
+                if from_contents.file_type() == file_type
+                    || carrier::share_an_extension(expected, from_contents) =>
+            {
+                from_contents
+            }
+            Some(from_contents) => {
+                return Err(ErrorType::FileTypeMismatch {
+                    from_extension: file_type,
+                    from_contents: from_contents.file_type(),
+                });
+            }
+            None => expected,
+        };
+
+        Ok(SupportedFile { contents, carrier })
+    }
+
+    /// Returns the file type as an enum.
+    pub fn file_type(&self) -> SupportedFileType {
+        self.carrier.file_type()
+    }
+
+    /// Returns the carrier that handles this type of file.
+    pub fn carrier(&self) -> &'static dyn Carrier {
+        self.carrier
+    }
+
+    /// Returns the whole contents of the file.
+    pub fn contents(&self) -> &[u8] {
+        &self.contents
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor, path::PathBuf};
+
+    use crate::{
+        file_types::supported_file::{SupportedFile, SupportedFileType},
+        ErrorType,
+    };
+
+    fn test_data_dir() -> PathBuf {
+        "./test_data".into()
+    }
+
+    #[test]
+    fn detects_type_from_contents() -> Result<(), ErrorType> {
+        let path = test_data_dir().join("stick.result.PNG.bak");
+        fs::copy(test_data_dir().join("stick.png"), &path)?;
+
+        assert_eq!(
+            SupportedFile::open(&path)?.file_type(),
+            SupportedFileType::Png
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_mismatched_extension() -> Result<(), ErrorType> {
+        let path = test_data_dir().join("stick_renamed.result.jpg");
+        fs::copy(test_data_dir().join("stick.png"), &path)?;
+
+        assert!(matches!(
+            SupportedFile::open(&path),
+            Err(ErrorType::FileTypeMismatch {
+                from_extension: SupportedFileType::Jpeg,
+                from_contents: SupportedFileType::Png,
+            })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn falls_back_to_extension() {
+        // TGA files don't start with any magic bytes
+        assert_eq!(SupportedFileType::from_contents(&[0, 0, 2, 0]), None);
+        assert_eq!(
+            SupportedFileType::from_file_path("image.tga"),
+            Some(SupportedFileType::Tga)
+        );
+    }
+
+    #[test]
+    fn rejects_unregistered_file_type() {
+        let error = SupportedFile::from_reader(
+            Cursor::new(vec![0; 16]),
+            SupportedFileType::Other("Unregistered"),
+        )
+        .err()
+        .unwrap();
+
+        assert!(matches!(
+            error,
+            ErrorType::UnregisteredFileType(SupportedFileType::Other("Unregistered"))
+        ));
+    }
+}
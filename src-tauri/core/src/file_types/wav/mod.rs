@@ -0,0 +1,303 @@
+use std::{io::Write, ops::RangeInclusive};
+
+use crate::{
+    base_context, encoded_context,
+    error::ErrorType,
+    file_types::{
+        bits::{join_bits, split_bits},
+        carrier::Carrier,
+        supported_file::SupportedFileType,
+    },
+    header::{Algorithm, Header, HEADER_BYTES},
+    output_context,
+    payload::SealedPayload,
+    progress::Progress,
+    slot_order::SlotOrder,
+    DecodeOptions, EncodeOptions, Result,
+};
+
+mod riff;
+
+use riff::Wave;
+
+/// The numbers of least significant bits per sample that can be used to store data.
+///
+/// Past four bits, the added noise becomes plainly audible in 8 bit audio.
+const BITS_PER_SAMPLE: RangeInclusive<u8> = 1..=4;
+
+/// Hides data in the least significant bits of PCM WAV samples.
+pub struct WavCarrier;
+
+impl Carrier for WavCarrier {
+    fn file_type(&self) -> SupportedFileType {
+        SupportedFileType::Wav
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["wav", "wave"]
+    }
+
+    fn detect(&self, contents: &[u8]) -> bool {
+        contents.len() >= 12 && &contents[0..4] == b"RIFF" && &contents[8..12] == b"WAVE"
+    }
+
+    fn capacity(&self, contents: &[u8], options: &EncodeOptions) -> Result<u64> {
+        base_context!(available_size_of(contents, options.bits_per_channel))
+    }
+
+    fn embed(
+        &self,
+        contents: &[u8],
+        payload: &SealedPayload,
+        options: &EncodeOptions,
+        progress: &Progress,
+        output: &mut dyn Write,
+    ) -> Result<()> {
+        encode(
+            contents,
+            &payload.with_header(Algorithm::LeastSignificantBits, options.bits_per_channel),
+            &SlotOrder::new(options.key.as_deref()),
+            options.bits_per_channel,
+            progress,
+            output,
+        )
+    }
+
+    fn extract(
+        &self,
+        contents: &[u8],
+        options: &DecodeOptions,
+        progress: &Progress,
+    ) -> Result<(Header, Vec<u8>)> {
+        decode(contents, &SlotOrder::new(options.key.as_deref()), progress)
+    }
+}
+
+/// Parses the whole WAV file.
+fn read_wave(contents: &[u8]) -> std::result::Result<Wave, ErrorType> {
+    log::trace!("Parsing {} bytes of WAV", contents.len());
+
+    Wave::parse(contents.to_vec())
+}
+
+/// Returns an error if the audio can't be encoded with the number of bits per sample.
+fn check_bits_per_sample(bits_per_sample: u8) -> std::result::Result<(), ErrorType> {
+    if BITS_PER_SAMPLE.contains(&bits_per_sample) {
+        Ok(())
+    } else {
+        Err(ErrorType::UnsupportedBitsPerChannel(bits_per_sample))
+    }
+}
+
+/// Finds the amount of space in bytes, that can be used to store a secret file.
+///
+/// Every sample of every channel provides `bits_per_sample` bits.
+pub fn available_size_of(
+    contents: &[u8],
+    bits_per_sample: u8,
+) -> std::result::Result<u64, ErrorType> {
+    check_bits_per_sample(bits_per_sample)?;
+
+    let wave = read_wave(contents)?;
+    log::trace!("Found {} samples in a WAV file", wave.sample_count());
+
+    Ok((wave.sample_count() * bits_per_sample as u64 / 8).saturating_sub(HEADER_BYTES))
+}
+
+/// Encodes the payload into the base audio, and writes the results to the output file.
+///
+/// The payload is expected to start with its header, and is stored `bits_per_sample` bits at a
+/// time in the order given by the slot order.
+pub fn encode(
+    base_audio: &[u8],
+    payload: &[u8],
+    order: &SlotOrder,
+    bits_per_sample: u8,
+    progress: &Progress,
+    output_audio: &mut dyn Write,
+) -> Result<()> {
+    log::info!("Beginning the encoding process into a WAV file");
+
+    let mut wave = base_context!(read_wave(base_audio))?;
+    log::trace!("Parsed the base WAV file");
+
+    let mask = (1 << bits_per_sample) - 1;
+    let secret_data = progress.track(
+        split_bits(payload, bits_per_sample),
+        bits_per_sample,
+        payload.len() as u64,
+    );
+    for (sample, secret_bits) in order.iter(wave.sample_count()).zip(secret_data) {
+        let value = wave.low_byte_mut(sample);
+        *value = (*value & !mask) | secret_bits;
+    }
+    output_context!(progress.check())?;
+
+    log::trace!("Updated the samples with the secret file's data");
+
+    output_context!(output_audio.write_all(wave.as_bytes()))
+}
+
+/// Decodes the encoded audio, returning the header and the payload that follows it.
+///
+/// The slot order must match the one the payload was encoded with. The number of bits per
+/// sample is found by looking for a header that was written with it.
+pub fn decode(
+    encoded_audio: &[u8],
+    order: &SlotOrder,
+    progress: &Progress,
+) -> Result<(Header, Vec<u8>)> {
+    log::info!("Beginning the decoding process from a WAV file");
+
+    let wave = encoded_context!(read_wave(encoded_audio))?;
+    log::trace!("Parsed the encoded WAV file");
+
+    let wave = &wave;
+    let (header, secret_data) = encoded_context!(Header::find(
+        BITS_PER_SAMPLE,
+        Algorithm::LeastSignificantBits,
+        |bits_per_sample| {
+            let mask = (1 << bits_per_sample) - 1;
+            let chunks = order
+                .iter(wave.sample_count())
+                .map(move |sample| wave.low_byte(sample) & mask);
+            join_bits(chunks, bits_per_sample)
+        }
+    ))?;
+    log::trace!(
+        "Decoded the header. Payload size of {} stored with {} bits per sample",
+        header.payload_size,
+        header.depth
+    );
+
+    let payload = header.read_payload(progress.track(secret_data, 8, header.payload_size));
+    encoded_context!(progress.check())?;
+    let payload = encoded_context!(payload)?;
+    log::trace!("Read the payload from the WAV file");
+
+    Ok((header, payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, path::PathBuf};
+
+    use crate::{
+        base_context, encoded_context,
+        file_types::wav::{available_size_of, decode, encode},
+        header::{Algorithm, HEADER_BYTES},
+        output_context,
+        payload::Payload,
+        progress::Progress,
+        slot_order::SlotOrder,
+        DecodeOptions, EncodeOptions, ErrorType, Result,
+    };
+
+    fn test_data_dir() -> PathBuf {
+        "./test_data".into()
+    }
+
+    /// A chunk that isn't part of the audio, which should be copied to the output untouched.
+    const LIST_CHUNK: &[u8] = b"LIST\x0b\x00\x00\x00INFOcomment\x00";
+
+    /// Writes a WAV file of a tone with the given sample size and channel count.
+    fn base_wav(name: &str, bits_per_sample: u16, channels: u16, frames: u32) -> PathBuf {
+        let path = test_data_dir().join(format!("{name}.result.wav"));
+        let bytes_per_sample = bits_per_sample / 8;
+        let block_align = channels * bytes_per_sample;
+
+        let mut data: Vec<u8> = vec![];
+        for frame in 0..frames {
+            for channel in 0..channels as u32 {
+                let sample = frame
+                    .wrapping_mul(2_654_435_761)
+                    .wrapping_add(channel * 40_503)
+                    .to_le_bytes();
+                data.extend(&sample[..bytes_per_sample as usize]);
+            }
+        }
+
+        let mut bytes = b"RIFF\0\0\0\0WAVE".to_vec();
+        bytes.extend(b"fmt \x10\x00\x00\x00\x01\x00");
+        bytes.extend(channels.to_le_bytes());
+        bytes.extend(44_100u32.to_le_bytes());
+        bytes.extend((44_100 * block_align as u32).to_le_bytes());
+        bytes.extend(block_align.to_le_bytes());
+        bytes.extend(bits_per_sample.to_le_bytes());
+        bytes.extend(LIST_CHUNK);
+        bytes.extend(b"data");
+        bytes.extend((data.len() as u32).to_le_bytes());
+        bytes.extend(data);
+        let riff_size = (bytes.len() - 8) as u32;
+        bytes[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn assert_round_trip(bits_per_sample: u16, channels: u16, depth: u8) -> Result<()> {
+        let name = format!("wav_{bits_per_sample}_bit_{channels}_channel");
+        let base_path = base_wav(&name, bits_per_sample, channels, 3000);
+        let output_path = base_path.with_extension("encoded.wav");
+
+        let payload = Payload::new("secret.txt", b"Hidden in the samples".to_vec());
+        let base_audio = base_context!(std::fs::read(&base_path))?;
+        let mut output_audio = output_context!(File::create(&output_path))?;
+        encode(
+            &base_audio,
+            &payload
+                .seal(&EncodeOptions::default())
+                .with_header(Algorithm::LeastSignificantBits, depth),
+            &SlotOrder::keyed("key"),
+            depth,
+            &Progress::default(),
+            &mut output_audio,
+        )?;
+
+        let encoded_audio = encoded_context!(std::fs::read(&output_path))?;
+        let (header, bytes) = decode(
+            &encoded_audio,
+            &SlotOrder::keyed("key"),
+            &Progress::default(),
+        )?;
+        let decoded = encoded_context!(Payload::unseal(&header, bytes, &DecodeOptions::default()))?;
+        assert_eq!(decoded, payload);
+
+        // Only the samples change, so everything before them is identical
+        let base = std::fs::read(&base_path).unwrap();
+        let encoded = std::fs::read(&output_path).unwrap();
+        let data_start = base.windows(4).position(|id| id == b"data").unwrap();
+        assert_eq!(base.len(), encoded.len());
+        assert_eq!(base[..data_start], encoded[..data_start]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip_8_bit_mono() -> Result<()> {
+        assert_round_trip(8, 1, 2)
+    }
+
+    #[test]
+    fn round_trip_16_bit_stereo() -> Result<()> {
+        assert_round_trip(16, 2, 1)
+    }
+
+    #[test]
+    fn round_trip_24_bit_multichannel() -> Result<()> {
+        assert_round_trip(24, 6, 4)
+    }
+
+    #[test]
+    fn size_of() -> std::result::Result<(), ErrorType> {
+        let file = std::fs::read(base_wav("wav_size_of", 16, 2, 1000))?;
+
+        assert_eq!(available_size_of(&file, 2)?, 500 - HEADER_BYTES);
+        assert!(matches!(
+            available_size_of(&file, 5),
+            Err(ErrorType::UnsupportedBitsPerChannel(5))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use std::ops::Range;
+
+use crate::error::ErrorType;
+
+/// The format tag of integer PCM samples.
+const FORMAT_PCM: u16 = 0x0001;
+
+/// The format tag of WAVE_FORMAT_EXTENSIBLE, whose actual format is given by a sub-format GUID.
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// A PCM WAV file, kept as its original bytes along with where its samples are.
+///
+/// Only the samples are ever edited, so every other chunk is written back out exactly as it was.
+pub struct Wave {
+    bytes: Vec<u8>,
+    /// Where the sample data is within the bytes.
+    data: Range<usize>,
+    /// The number of bytes each sample of a single channel takes up.
+    bytes_per_sample: usize,
+}
+
+/// The error used for any WAV file that can't be interpreted.
+fn malformed(reason: &str) -> ErrorType {
+    ErrorType::AudioError(format!("Malformed or unsupported WAV file: {reason}"))
+}
+
+impl Wave {
+    /// Parses a RIFF WAVE file, finding its format and sample data.
+    ///
+    /// Only 8, 16 and 24 bit integer PCM is supported.
+    pub fn parse(bytes: Vec<u8>) -> Result<Wave, ErrorType> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            return Err(malformed("missing RIFF WAVE header"));
+        }
+
+        let mut format = None;
+        let mut data = None;
+
+        let mut position = 12;
+        while position + 8 <= bytes.len() {
+            let id = &bytes[position..position + 4];
+            let size = u32::from_le_bytes(bytes[position + 4..position + 8].try_into().unwrap());
+            let start = position + 8;
+            let end = start
+                .checked_add(size as usize)
+                .filter(|end| *end <= bytes.len())
+                .ok_or_else(|| malformed("truncated chunk"))?;
+
+            match id {
+                b"fmt " => format = Some(start..end),
+                b"data" => data = Some(start..end),
+                _ => {}
+            }
+
+            // Chunks are padded to an even number of bytes
+            position = end + (size as usize & 1);
+        }
+
+        let format = format.ok_or_else(|| malformed("missing format chunk"))?;
+        let data = data.ok_or_else(|| malformed("missing data chunk"))?;
+        let bytes_per_sample = parse_format(&bytes[format])?;
+
+        Ok(Wave {
+            bytes,
+            data,
+            bytes_per_sample,
+        })
+    }
+
+    /// The number of samples across every channel.
+    pub fn sample_count(&self) -> u64 {
+        (self.data.len() / self.bytes_per_sample) as u64
+    }
+
+    /// The byte holding the least significant bits of a sample.
+    ///
+    /// Samples are little endian, so this is their first byte whether they are signed or not.
+    pub fn low_byte(&self, sample: u64) -> u8 {
+        self.bytes[self.data.start + sample as usize * self.bytes_per_sample]
+    }
+
+    /// A mutable reference to the byte holding the least significant bits of a sample.
+    pub fn low_byte_mut(&mut self, sample: u64) -> &mut u8 {
+        &mut self.bytes[self.data.start + sample as usize * self.bytes_per_sample]
+    }
+
+    /// The bytes of the whole file, including any changes to its samples.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// Reads a format chunk, returning the number of bytes in each sample.
+fn parse_format(format: &[u8]) -> Result<usize, ErrorType> {
+    if format.len() < 16 {
+        return Err(malformed("truncated format chunk"));
+    }
+
+    let field = |offset: usize| u16::from_le_bytes([format[offset], format[offset + 1]]);
+    let format_tag = match field(0) {
+        // The first two bytes of the sub-format GUID are the format tag it stands for
+        FORMAT_EXTENSIBLE if format.len() >= 26 => field(24),
+        format_tag => format_tag,
+    };
+    let channels = field(2) as usize;
+    let block_align = field(12) as usize;
+    let bits_per_sample = field(14);
+
+    if format_tag != FORMAT_PCM {
+        return Err(malformed("only integer PCM samples can be used"));
+    }
+    if !matches!(bits_per_sample, 8 | 16 | 24) {
+        return Err(malformed("only 8, 16 and 24 bit samples can be used"));
+    }
+
+    let bytes_per_sample = bits_per_sample as usize / 8;
+    if channels == 0 || block_align != channels * bytes_per_sample {
+        return Err(malformed("inconsistent channel count and block alignment"));
+    }
+
+    Ok(bytes_per_sample)
+}
@@ -0,0 +1,408 @@
+use std::ops::RangeInclusive;
+
+use crate::{error_correction, CorruptionType, Redundancy};
+
+/// The bytes every header starts with. Used to tell apart files that contain a payload from
+/// files that don't.
+const MAGIC: [u8; 4] = *b"STGO";
+
+/// The version of the header format this build writes, and the newest version it can read.
+pub const CURRENT_VERSION: u8 = 1;
+
+/// The number of bytes we'll use as a header for our data.
+///
+/// Made up of the magic number (4), version (1), algorithm (1), flags (2), depth (1),
+/// payload size (8) and a CRC-32 of the preceding bytes (4).
+pub const HEADER_BYTES: u64 = 21;
+
+/// The number of times the header is written before a payload with error correction.
+///
+/// A header damaged in any one copy is recovered by a majority vote over each of its bits, as the
+/// payload's error correction can't be used until the header has been read.
+pub const REDUNDANT_HEADER_COPIES: u64 = 3;
+
+/// The steganographic algorithm that was used to hide a payload in a carrier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// The payload is stored in the least significant bits of the carrier's samples.
+    LeastSignificantBits = 0,
+    /// The payload is stored in the quantized DCT coefficients of a JPEG.
+    DctCoefficients = 1,
+    /// The payload is stored in the lowest bit of the palette indices of an indexed image.
+    PaletteIndices = 2,
+}
+
+impl TryFrom<u8> for Algorithm {
+    type Error = CorruptionType;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Algorithm::LeastSignificantBits),
+            1 => Ok(Algorithm::DctCoefficients),
+            2 => Ok(Algorithm::PaletteIndices),
+            _ => Err(CorruptionType::IncorrectHeader),
+        }
+    }
+}
+
+/// Describes the payload that follows it inside of an encoded file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    /// Which version of the format the payload was written with.
+    pub version: u8,
+    /// How the payload was hidden inside of the carrier.
+    pub algorithm: Algorithm,
+    /// Optional features used by the payload, as a combination of the flag constants on Header.
+    pub flags: u16,
+    /// The number of payload bits stored in each slot of the carrier, for example in each colour
+    /// channel of an image.
+    pub depth: u8,
+    /// The number of bytes of payload following the header.
+    pub payload_size: u64,
+}
+
+impl Header {
+    /// The payload starts with the secret file's name and content type.
+    pub const FILE_METADATA: u16 = 1 << 0;
+
+    /// The payload following the header is encrypted with a password.
+    pub const ENCRYPTED: u16 = 1 << 1;
+
+    /// The payload is stored in the alpha channel of an image as well as its colour channels.
+    pub const ALPHA_CHANNEL: u16 = 1 << 2;
+
+    /// The payload skips the fully transparent pixels of an image.
+    pub const SKIPS_TRANSPARENT: u16 = 1 << 3;
+
+    /// The payload is only stored in the fully transparent pixels of an image.
+    pub const ONLY_TRANSPARENT: u16 = 1 << 4;
+
+    /// The flags that describe which parts of an image with an alpha channel hold the payload.
+    pub const ALPHA_FLAGS: u16 =
+        Header::ALPHA_CHANNEL | Header::SKIPS_TRANSPARENT | Header::ONLY_TRANSPARENT;
+
+    /// The payload was compressed with deflate before being encrypted.
+    pub const DEFLATE_COMPRESSED: u16 = 1 << 5;
+
+    /// The payload was compressed with zstd before being encrypted.
+    pub const ZSTD_COMPRESSED: u16 = 1 << 6;
+
+    /// The payload ends with a SHA-256 digest of the secret file and its metadata, taken before
+    /// they were compressed and encrypted.
+    pub const CHECKSUM: u16 = 1 << 7;
+
+    /// The payload carries Reed-Solomon parity with 16 bytes in every codeword.
+    pub const LOW_REDUNDANCY: u16 = 1 << 8;
+
+    /// The payload carries Reed-Solomon parity with 32 bytes in every codeword.
+    pub const MEDIUM_REDUNDANCY: u16 = 1 << 9;
+
+    /// The payload carries Reed-Solomon parity with 64 bytes in every codeword.
+    pub const HIGH_REDUNDANCY: u16 = 1 << 10;
+
+    /// The flags that describe how much error correction the payload carries.
+    pub const REDUNDANCY_FLAGS: u16 =
+        Header::LOW_REDUNDANCY | Header::MEDIUM_REDUNDANCY | Header::HIGH_REDUNDANCY;
+
+    /// The payload is an archive of several files and directories, which are unpacked into a
+    /// directory instead of being written to a single file.
+    pub const ARCHIVE: u16 = 1 << 11;
+
+    /// The flags describing how the payload itself was transformed, which are authenticated
+    /// along with an encrypted payload. The rest describe how the carrier stored it.
+    pub const SEALED_FLAGS: u16 = Header::FILE_METADATA
+        | Header::ENCRYPTED
+        | Header::DEFLATE_COMPRESSED
+        | Header::ZSTD_COMPRESSED
+        | Header::CHECKSUM
+        | Header::ARCHIVE;
+
+    /// Every flag this version knows how to interpret.
+    const KNOWN_FLAGS: u16 = Header::FILE_METADATA
+        | Header::ENCRYPTED
+        | Header::ALPHA_FLAGS
+        | Header::DEFLATE_COMPRESSED
+        | Header::ZSTD_COMPRESSED
+        | Header::CHECKSUM
+        | Header::REDUNDANCY_FLAGS
+        | Header::ARCHIVE;
+
+    /// Constructs a header in the current version for a payload of the given size.
+    pub fn new(algorithm: Algorithm, depth: u8, payload_size: u64) -> Header {
+        Header {
+            version: CURRENT_VERSION,
+            algorithm,
+            flags: 0,
+            depth,
+            payload_size,
+        }
+    }
+
+    /// The number of times the header is written before its payload.
+    pub fn copies(&self) -> u64 {
+        if self.flags & Header::REDUNDANCY_FLAGS != 0 {
+            REDUNDANT_HEADER_COPIES
+        } else {
+            1
+        }
+    }
+
+    /// Serializes the header as many times as it is written before its payload.
+    pub fn to_stored_bytes(&self) -> Vec<u8> {
+        self.to_bytes().repeat(self.copies() as usize)
+    }
+
+    /// Serializes a single copy of the header.
+    pub fn to_bytes(&self) -> [u8; HEADER_BYTES as usize] {
+        let mut bytes = [0; HEADER_BYTES as usize];
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4] = self.version;
+        bytes[5] = self.algorithm as u8;
+        bytes[6..8].copy_from_slice(&self.flags.to_be_bytes());
+        bytes[8] = self.depth;
+        bytes[9..17].copy_from_slice(&self.payload_size.to_be_bytes());
+
+        let checksum = crc32fast::hash(&bytes[..17]);
+        bytes[17..21].copy_from_slice(&checksum.to_be_bytes());
+
+        bytes
+    }
+
+    /// Reads a header from the start of a stream of bytes, consuming the header along with any
+    /// copies of it.
+    ///
+    /// If the first copy is damaged, the header is voted on from the first three copies, which
+    /// only payloads with error correction have. Returns an error describing why the stream
+    /// doesn't start with a valid header.
+    pub fn from_bytes(bytes: &mut impl Iterator<Item = u8>) -> Result<Header, CorruptionType> {
+        let first_copy = read_copy(bytes).ok_or(CorruptionType::FileTooSmallForHeader)?;
+        let header_error = match Header::parse(&first_copy) {
+            Ok(header) => {
+                // The other copies are only needed if this one was damaged
+                bytes
+                    .take(((header.copies() - 1) * HEADER_BYTES) as usize)
+                    .for_each(drop);
+                return Ok(header);
+            }
+            Err(err) => err,
+        };
+
+        let (Some(second_copy), Some(third_copy)) = (read_copy(bytes), read_copy(bytes)) else {
+            return Err(header_error);
+        };
+        let mut voted = [0; HEADER_BYTES as usize];
+        for (index, byte) in voted.iter_mut().enumerate() {
+            let (first, second, third) = (first_copy[index], second_copy[index], third_copy[index]);
+            *byte = (first & second) | (first & third) | (second & third);
+        }
+
+        match Header::parse(&voted) {
+            Ok(header) if header.copies() == REDUNDANT_HEADER_COPIES => {
+                log::warn!("The header was damaged, so it was recovered from its copies");
+                Ok(header)
+            }
+            _ => Err(header_error),
+        }
+    }
+
+    /// Parses a single copy of the header.
+    fn parse(header_bytes: &[u8; HEADER_BYTES as usize]) -> Result<Header, CorruptionType> {
+        if header_bytes[0..4] != MAGIC {
+            log::debug!("Header didn't start with the magic number");
+            return Err(CorruptionType::NoPayloadFound);
+        }
+
+        let checksum = u32::from_be_bytes(header_bytes[17..21].try_into().unwrap());
+        if crc32fast::hash(&header_bytes[..17]) != checksum {
+            log::debug!("Header checksum didn't match its contents");
+            return Err(CorruptionType::IncorrectHeader);
+        }
+
+        let version = header_bytes[4];
+        if version > CURRENT_VERSION {
+            return Err(CorruptionType::UnsupportedVersion(version));
+        }
+
+        let flags = u16::from_be_bytes(header_bytes[6..8].try_into().unwrap());
+        if flags & !Header::KNOWN_FLAGS != 0 {
+            log::debug!("Header contained unknown flags {flags:#06x}");
+            return Err(CorruptionType::IncorrectHeader);
+        }
+
+        let depth = header_bytes[8];
+        if !(1..=8).contains(&depth) {
+            log::debug!("Header contained an impossible depth of {depth}");
+            return Err(CorruptionType::IncorrectHeader);
+        }
+
+        Ok(Header {
+            version,
+            algorithm: Algorithm::try_from(header_bytes[5])?,
+            flags,
+            depth,
+            payload_size: u64::from_be_bytes(header_bytes[9..17].try_into().unwrap()),
+        })
+    }
+
+    /// Searches for a header written at any of the depths, where `bytes_at_depth` reads a carrier
+    /// using a given depth.
+    ///
+    /// The header records its depth, but is itself stored at that depth, so each possibility is
+    /// tried in turn. Returns the header along with the remaining bytes read at its depth.
+    pub fn find<I: Iterator<Item = u8>>(
+        depths: RangeInclusive<u8>,
+        algorithm: Algorithm,
+        mut bytes_at_depth: impl FnMut(u8) -> I,
+    ) -> Result<(Header, I), CorruptionType> {
+        let mut header_error = CorruptionType::NoPayloadFound;
+        for depth in depths {
+            let mut bytes = bytes_at_depth(depth);
+
+            match Header::from_bytes(&mut bytes) {
+                Ok(header) if header.algorithm != algorithm => {
+                    header_error = CorruptionType::IncorrectHeader;
+                }
+                Ok(header) if header.depth == depth => return Ok((header, bytes)),
+                Ok(_) => {}
+                // A missing magic number is expected when trying the wrong depth, but any
+                // other error means we found something resembling a header
+                Err(CorruptionType::NoPayloadFound) => {}
+                Err(err) => header_error = err,
+            }
+        }
+
+        Err(header_error)
+    }
+
+    /// Reads the payload described by this header from the bytes that follow it.
+    ///
+    /// Returns an error if the bytes end before the whole payload has been read, unless the
+    /// payload carries enough error correction to recover the missing bytes.
+    pub fn read_payload(&self, bytes: impl Iterator<Item = u8>) -> Result<Vec<u8>, CorruptionType> {
+        let payload_size = self.payload_size as usize;
+
+        // Capping the allocation up front, as the size is untrusted
+        let mut payload = Vec::with_capacity(payload_size.min(1 << 20));
+        payload.extend(bytes.take(payload_size));
+
+        // Only pad out as much as error correction could recover, as the size may not be
+        // trustworthy and anything more would fail to correct anyway
+        let missing = payload_size - payload.len();
+        if missing > 0
+            && Redundancy::from_flags(self.flags).is_ok_and(|redundancy| {
+                error_correction::can_recover_truncated(redundancy, payload_size, payload.len())
+            })
+        {
+            log::warn!(
+                "Payload ended after {} of {payload_size} bytes, so correcting the rest",
+                payload.len()
+            );
+            payload.resize(payload_size, 0);
+        }
+
+        // Verifying that we were able to read the entire payload according to the header
+        if payload.len() < payload_size {
+            log::debug!(
+                "Payload ended after {} of {payload_size} bytes",
+                payload.len()
+            );
+            return Err(CorruptionType::IncorrectHeader);
+        }
+
+        Ok(payload)
+    }
+}
+
+/// Reads the bytes of a single copy of the header, or None if the stream ends first.
+fn read_copy(bytes: &mut impl Iterator<Item = u8>) -> Option<[u8; HEADER_BYTES as usize]> {
+    let mut copy = [0; HEADER_BYTES as usize];
+    for byte in copy.iter_mut() {
+        *byte = bytes.next()?;
+    }
+
+    Some(copy)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        header::{Algorithm, Header, HEADER_BYTES},
+        CorruptionType,
+    };
+
+    #[test]
+    fn round_trip() {
+        let header = Header::new(Algorithm::LeastSignificantBits, 2, 1234);
+        let parsed = Header::from_bytes(&mut header.to_bytes().into_iter());
+
+        assert_eq!(parsed, Ok(header));
+    }
+
+    #[test]
+    fn rejects_missing_magic() {
+        let mut bytes = [0u8; HEADER_BYTES as usize].into_iter();
+
+        assert_eq!(
+            Header::from_bytes(&mut bytes),
+            Err(CorruptionType::NoPayloadFound)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_checksum() {
+        let mut bytes = Header::new(Algorithm::LeastSignificantBits, 2, 1234).to_bytes();
+        bytes[12] ^= 1;
+
+        assert_eq!(
+            Header::from_bytes(&mut bytes.into_iter()),
+            Err(CorruptionType::IncorrectHeader)
+        );
+    }
+
+    #[test]
+    fn rejects_short_stream() {
+        let bytes = Header::new(Algorithm::LeastSignificantBits, 2, 1234).to_bytes();
+
+        assert_eq!(
+            Header::from_bytes(&mut bytes[..10].iter().copied()),
+            Err(CorruptionType::FileTooSmallForHeader)
+        );
+    }
+
+    #[test]
+    fn recovers_damaged_header_from_copies() {
+        let mut header = Header::new(Algorithm::LeastSignificantBits, 2, 1234);
+        header.flags = Header::HIGH_REDUNDANCY;
+        let stored = header.to_stored_bytes();
+        assert_eq!(stored.len() as u64, 3 * HEADER_BYTES);
+
+        // Damaging the magic number of the first copy, and a different bit of each other copy
+        let mut damaged = stored.clone();
+        damaged[0] ^= 0xFF;
+        damaged[HEADER_BYTES as usize + 12] ^= 1;
+        damaged[2 * HEADER_BYTES as usize + 17] ^= 0x80;
+        damaged.push(42);
+
+        let mut bytes = damaged.into_iter();
+        assert_eq!(Header::from_bytes(&mut bytes), Ok(header.clone()));
+        // The copies are consumed along with the header
+        assert_eq!(bytes.next(), Some(42));
+
+        let mut bytes = stored.into_iter().chain([42]);
+        assert_eq!(Header::from_bytes(&mut bytes), Ok(header));
+        assert_eq!(bytes.next(), Some(42));
+    }
+
+    #[test]
+    fn single_copy_is_not_voted_on() {
+        let mut bytes = Header::new(Algorithm::LeastSignificantBits, 2, 1234)
+            .to_bytes()
+            .repeat(3);
+        bytes[0] ^= 0xFF;
+
+        assert_eq!(
+            Header::from_bytes(&mut bytes.into_iter()),
+            Err(CorruptionType::NoPayloadFound)
+        );
+    }
+}
@@ -0,0 +1,41 @@
+//! Hides secret files inside of images and audio, and recovers them again.
+//!
+//! This crate has no GUI dependencies. Enable the `serde` feature to serialize results and
+//! errors, as the app does to send them to its front-end.
+
+// Instead of exporting all of our modules, we can selectively export the relevant parts
+mod archive;
+mod atomic_file;
+mod compression;
+mod decoder;
+mod encoder;
+mod encryption;
+mod error;
+mod error_correction;
+mod file_types;
+mod header;
+mod options;
+mod payload;
+mod progress;
+mod slot_order;
+
+pub use archive::{ArchiveEntry, EntryKind};
+pub use decoder::{decode_bytes, decode_to_writer, Decoder};
+pub use encoder::{encode_bytes, encode_to_writer, secret_size, Encoder};
+pub use error::{CorruptionType, Error, ErrorContext, ErrorType, Result, WhichDuplicates};
+pub use file_types::base_file::{get_properties, FileProperties};
+pub use file_types::bits::{join_bits, split_bits};
+pub use file_types::carrier::{register_carrier, Carrier};
+pub use file_types::encoded_file::{inspect, list_entries};
+pub use file_types::supported_file::SupportedFileType;
+pub use header::{Algorithm, Header, HEADER_BYTES};
+pub use options::{
+    AlphaMode, Compression, DecodeOptions, EncodeOptions, Redundancy, DEFAULT_BITS_PER_CHANNEL,
+};
+pub use payload::{SealedPayload, SecretProperties};
+pub use progress::{CancellationToken, Progress, Tracked};
+pub use slot_order::SlotOrder;
+
+// The error macros log through this, so crates using them needn't depend on log themselves
+#[doc(hidden)]
+pub use log;
@@ -0,0 +1,114 @@
+/// The number of bits of each colour channel used to store the secret file, unless otherwise chosen.
+pub const DEFAULT_BITS_PER_CHANNEL: u8 = 2;
+
+/// Settings that control how a secret file is encoded into a base file.
+#[derive(Debug, Clone)]
+pub struct EncodeOptions {
+    /// A password used to encrypt the secret file before it's hidden. No encryption if None.
+    pub password: Option<String>,
+    /// A key used to scatter the secret file across the base file. Stored sequentially if None.
+    pub key: Option<String>,
+    /// How many of the least significant bits of each channel are used to store the secret file.
+    ///
+    /// More bits give more space, but make the changes to the base file more noticeable.
+    pub bits_per_channel: u8,
+    /// Leaves out metadata that could identify the base file, such as text, EXIF and timestamps.
+    ///
+    /// Otherwise the base file's metadata is copied into the output. Only still PNGs are stripped
+    /// for now, as JPEGs and WAVs are always written back with their metadata as it was.
+    pub strip_metadata: bool,
+    /// Which channels and pixels of images with an alpha channel are used.
+    pub alpha: AlphaMode,
+    /// How the secret file is compressed before it's hidden. It's stored as it is if compressing
+    /// doesn't make it smaller.
+    pub compression: Compression,
+    /// How much error correction is stored alongside the secret file, so that it can be
+    /// recovered from a base file that's been slightly damaged. Takes up space in the base file.
+    pub redundancy: Redundancy,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        EncodeOptions {
+            password: None,
+            key: None,
+            bits_per_channel: DEFAULT_BITS_PER_CHANNEL,
+            strip_metadata: false,
+            alpha: AlphaMode::default(),
+            compression: Compression::default(),
+            redundancy: Redundancy::default(),
+        }
+    }
+}
+
+/// How an image with an alpha channel is used to store the secret file. Images without one
+/// always use the colour channels of every pixel. The pixels of an indexed PNG take their
+/// transparency from their colour, so it can skip or only use transparent pixels, but has no
+/// alpha channel to include.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+pub enum AlphaMode {
+    /// Only the colour channels are used, in every pixel.
+    #[default]
+    ColourOnly,
+    /// The alpha channel is used along with the colour channels, as long as every pixel is fully
+    /// opaque. Otherwise only the colour channels are used.
+    IncludeAlpha,
+    /// Fully transparent pixels are left as they are, as changes to their colour are easy to
+    /// find by removing the transparency.
+    SkipTransparent,
+    /// Only fully transparent pixels are used, as their colour can't be seen.
+    OnlyTransparent,
+}
+
+/// How the secret file is compressed before it's hidden.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+pub enum Compression {
+    /// The secret file is stored as it is.
+    #[default]
+    None,
+    /// Deflate, which is quick and widely supported.
+    Deflate,
+    /// Zstandard, which usually compresses further than deflate.
+    Zstd,
+}
+
+/// How much Reed-Solomon error correction is stored alongside the secret file. Each level can
+/// correct about twice as much damage as the one before it, but takes up twice as much space.
+///
+/// Any level also writes the header three times, so that damage to one copy of any of its bits
+/// can be outvoted. Damage that leaves every slot where it was can be corrected, such as flipped
+/// bits, small edits and cropping rows off of the bottom of an image without a key. Edits that
+/// move the slots can't be, such as cropping rows off of the top or columns off of either side,
+/// resizing, or any crop of an image encoded with a key, whose slot order depends on its size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+pub enum Redundancy {
+    /// No error correction, so any damage is only detected by the checksum.
+    #[default]
+    None,
+    /// Corrects up to 8 damaged bytes in every 255, for about 7% more space.
+    Low,
+    /// Corrects up to 16 damaged bytes in every 255, for about 14% more space.
+    Medium,
+    /// Corrects up to 32 damaged bytes in every 255, for about 34% more space.
+    High,
+}
+
+/// Settings that control how a secret file is read back out of an encoded file.
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+    /// The password the secret file was encrypted with, if it was encrypted.
+    pub password: Option<String>,
+    /// The key the secret file was scattered with, if it was scattered.
+    pub key: Option<String>,
+    /// Keeps whatever can be recovered of a secret file that doesn't match its checksum.
+    ///
+    /// Decoding still fails with a `ChecksumMismatch` error, but the damaged secret file is
+    /// written to the output first. A compressed secret file is kept up to where the damage
+    /// stopped it from decompressing. Damage to an encrypted secret file can't be told apart
+    /// from a wrong password, so it fails with `WrongPassword` instead, unless error correction
+    /// repaired it.
+    pub best_effort: bool,
+}
@@ -0,0 +1,561 @@
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    compression, encryption, error_correction,
+    header::{Algorithm, Header},
+    Compression, CorruptionType, DecodeOptions, EncodeOptions, ErrorType, Redundancy,
+};
+
+/// The content type used when the secret file's contents can't be identified.
+const UNKNOWN_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// The content type of an archive of several secret files.
+const ARCHIVE_CONTENT_TYPE: &str = "application/x-stegosaurusography-archive";
+
+/// The number of bytes in the SHA-256 digest that ends a payload with a checksum.
+const CHECKSUM_BYTES: usize = 32;
+
+/// A collection of properties about a secret file hidden inside of an encoded file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SecretProperties {
+    /// The name of the secret file before it was encoded, if it was known.
+    pub file_name: Option<String>,
+    /// The extension of the secret file, either from its name or from its contents.
+    pub extension: Option<String>,
+    /// The MIME type sniffed from the secret file's contents.
+    pub content_type: String,
+    /// Whether the secret is an archive of several files and directories, which are unpacked
+    /// into a directory.
+    pub archive: bool,
+}
+
+impl SecretProperties {
+    /// Describes a secret file from its path and contents.
+    pub fn describe(file_path: impl AsRef<Path>, contents: &[u8]) -> SecretProperties {
+        let file_path = file_path.as_ref();
+        let sniffed_type = infer::get(contents);
+
+        let content_type = match sniffed_type {
+            Some(sniffed_type) => sniffed_type.mime_type(),
+            None if std::str::from_utf8(contents).is_ok() => "text/plain",
+            None => UNKNOWN_CONTENT_TYPE,
+        };
+        let extension = file_path
+            .extension()
+            .map(|extension| extension.to_string_lossy().into_owned())
+            .or_else(|| sniffed_type.map(|sniffed_type| sniffed_type.extension().to_owned()));
+
+        SecretProperties {
+            file_name: file_path
+                .file_name()
+                .map(|file_name| file_name.to_string_lossy().into_owned()),
+            extension,
+            content_type: content_type.to_owned(),
+            archive: false,
+        }
+    }
+
+    /// Properties for a payload that didn't record any metadata.
+    fn unknown() -> SecretProperties {
+        SecretProperties {
+            file_name: None,
+            extension: None,
+            content_type: UNKNOWN_CONTENT_TYPE.to_owned(),
+            archive: false,
+        }
+    }
+
+    /// A file name that can be used to save the secret file to.
+    ///
+    /// Only the final component of the stored name is used, so a crafted name can't escape the
+    /// directory it is saved into.
+    pub fn suggested_file_name(&self) -> String {
+        self.file_name
+            .as_deref()
+            .and_then(|file_name| Path::new(file_name).file_name())
+            .map(|file_name| file_name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| match &self.extension {
+                Some(extension) => format!("secret.{extension}"),
+                None => "secret".to_owned(),
+            })
+    }
+
+    /// Serializes the properties as a sequence of length-prefixed strings.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        for field in [
+            self.file_name.as_deref().unwrap_or_default(),
+            self.extension.as_deref().unwrap_or_default(),
+            &self.content_type,
+        ] {
+            // Our fields are file names and MIME types, which are much shorter than u16::MAX
+            let field = &field.as_bytes()[..field.len().min(u16::MAX as usize)];
+            bytes.extend((field.len() as u16).to_be_bytes());
+            bytes.extend(field);
+        }
+
+        bytes
+    }
+
+    /// Reads properties from the start of the bytes, returning the properties and the remaining bytes.
+    fn from_bytes(bytes: &[u8]) -> Result<(SecretProperties, &[u8]), CorruptionType> {
+        let mut remaining = bytes;
+        let mut next_field = || -> Result<Option<String>, CorruptionType> {
+            let (length, rest) = remaining
+                .split_first_chunk::<2>()
+                .ok_or(CorruptionType::IncorrectMetadata)?;
+            let length = u16::from_be_bytes(*length) as usize;
+            if rest.len() < length {
+                return Err(CorruptionType::IncorrectMetadata);
+            }
+
+            let (field, rest) = rest.split_at(length);
+            remaining = rest;
+
+            let field =
+                String::from_utf8(field.to_vec()).map_err(|_| CorruptionType::IncorrectMetadata)?;
+            Ok(Some(field).filter(|field| !field.is_empty()))
+        };
+
+        let file_name = next_field()?;
+        let extension = next_field()?;
+        let content_type = next_field()?.unwrap_or_else(|| UNKNOWN_CONTENT_TYPE.to_owned());
+
+        Ok((
+            SecretProperties {
+                file_name,
+                extension,
+                content_type,
+                archive: false,
+            },
+            remaining,
+        ))
+    }
+}
+
+/// The secret file along with everything we record about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payload {
+    pub properties: SecretProperties,
+    pub contents: Vec<u8>,
+}
+
+impl Payload {
+    /// Constructs a payload from the secret file's path and contents.
+    pub fn new(file_path: impl AsRef<Path>, contents: Vec<u8>) -> Payload {
+        Payload {
+            properties: SecretProperties::describe(file_path, &contents),
+            contents,
+        }
+    }
+
+    /// Constructs a payload from an archive of the secret files and directories at the paths,
+    /// named after the only one if there's only one.
+    pub fn archive(paths: &[impl AsRef<Path>], archive: Vec<u8>) -> Payload {
+        let file_name = match paths {
+            [path] => path
+                .as_ref()
+                .file_name()
+                .map(|file_name| file_name.to_string_lossy().into_owned()),
+            _ => None,
+        };
+
+        Payload {
+            properties: SecretProperties {
+                file_name,
+                extension: None,
+                content_type: ARCHIVE_CONTENT_TYPE.to_owned(),
+                archive: true,
+            },
+            contents: archive,
+        }
+    }
+
+    /// Serializes the payload into the bytes that will follow the header.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.properties.to_bytes();
+        bytes.extend(&self.contents);
+
+        bytes
+    }
+
+    /// Serializes the payload followed by its checksum, and compresses it if that makes it
+    /// smaller, returning the flags recording the compression along with the bytes.
+    fn compressed(&self, compression: Compression) -> (u16, Vec<u8>) {
+        let mut flags = Header::FILE_METADATA | Header::CHECKSUM;
+        if self.properties.archive {
+            flags |= Header::ARCHIVE;
+        }
+        let mut bytes = self.to_bytes();
+        bytes.extend(Sha256::digest(&bytes));
+
+        match compression::compress(compression, &bytes) {
+            Some(compressed) => (flags | compression.flag(), compressed),
+            None => (flags, bytes),
+        }
+    }
+
+    /// Applies every transformation requested by the options, producing the bytes that will be
+    /// hidden along with the flags needed to reverse them.
+    ///
+    /// The payload is compressed before it's encrypted, as encrypted bytes don't compress.
+    pub fn seal(&self, options: &EncodeOptions) -> SealedPayload {
+        let (mut flags, mut bytes) = self.compressed(options.compression);
+
+        if let Some(password) = &options.password {
+            flags |= Header::ENCRYPTED;
+            bytes = encryption::encrypt(password, &bytes, flags);
+        }
+
+        SealedPayload {
+            flags,
+            bytes,
+            redundancy: options.redundancy,
+        }
+    }
+
+    /// The number of bytes that will follow the header once the payload is sealed with the
+    /// options, found without spending the time to derive an encryption key.
+    pub fn sealed_size(&self, options: &EncodeOptions) -> u64 {
+        let (_, bytes) = self.compressed(options.compression);
+        let encryption_overhead = match options.password {
+            Some(_) => encryption::OVERHEAD,
+            None => 0,
+        };
+
+        (bytes.len() + encryption_overhead) as u64
+    }
+
+    /// Reverses the transformations described by the header, parsing the bytes following it back
+    /// into a payload.
+    ///
+    /// Returns a `ChecksumMismatch` error if the payload doesn't match the checksum it was hidden
+    /// with, as it was damaged after being hidden.
+    pub fn unseal(
+        header: &Header,
+        bytes: Vec<u8>,
+        options: &DecodeOptions,
+    ) -> Result<Payload, ErrorType> {
+        match Payload::unseal_damaged(header, bytes, options)? {
+            (payload, true) => Ok(payload),
+            (_, false) => Err(CorruptionType::ChecksumMismatch.into()),
+        }
+    }
+
+    /// Reverses the transformations described by the header like `unseal`, but returns a payload
+    /// that doesn't match its checksum instead of an error, so that whatever survived can be
+    /// recovered. Also returns whether the payload matched its checksum, which payloads without
+    /// one always do.
+    ///
+    /// A compressed payload is kept up to where the damage stopped it from decompressing, which
+    /// for zstd is the start of the damaged block of up to 128 KiB. An encrypted payload is
+    /// authenticated as a whole, and damage to it can't be told apart from a wrong password, so
+    /// it still fails with `WrongPassword` unless error correction repaired the damage first.
+    pub fn unseal_damaged(
+        header: &Header,
+        bytes: Vec<u8>,
+        options: &DecodeOptions,
+    ) -> Result<(Payload, bool), ErrorType> {
+        let redundancy = Redundancy::from_flags(header.flags)?;
+        let mut bytes = error_correction::decode(redundancy, &bytes)?;
+
+        if header.flags & Header::ENCRYPTED != 0 {
+            let password = options
+                .password
+                .as_deref()
+                .ok_or(ErrorType::PasswordRequired)?;
+            bytes = encryption::decrypt(password, &bytes, header.flags & Header::SEALED_FLAGS)?;
+        }
+
+        let compression = Compression::from_flags(header.flags)?;
+        let (decompressed, complete) = compression::decompress(compression, &bytes)?;
+        bytes = decompressed;
+
+        let mut intact = complete;
+        if !complete {
+            // Without a checksum, the damage wouldn't be noticed otherwise
+            if header.flags & Header::CHECKSUM == 0 {
+                return Err(CorruptionType::IncorrectCompression.into());
+            }
+            log::warn!("The payload could only be partly decompressed");
+        } else if header.flags & Header::CHECKSUM != 0 {
+            let checksum_start = bytes
+                .len()
+                .checked_sub(CHECKSUM_BYTES)
+                .ok_or(CorruptionType::ChecksumMismatch)?;
+            let checksum = bytes.split_off(checksum_start);
+            intact = Sha256::digest(&bytes).as_slice() == checksum;
+            if !intact {
+                log::warn!("The payload doesn't match its checksum");
+            }
+        }
+
+        if header.flags & Header::FILE_METADATA == 0 {
+            let payload = Payload {
+                properties: SecretProperties::unknown(),
+                contents: bytes,
+            };
+            return Ok((payload, intact));
+        }
+
+        let (mut properties, contents) = SecretProperties::from_bytes(&bytes)?;
+        properties.archive = header.flags & Header::ARCHIVE != 0;
+        let payload = Payload {
+            properties,
+            contents: contents.to_vec(),
+        };
+        Ok((payload, intact))
+    }
+}
+
+/// The bytes of a payload after every transformation has been applied.
+pub struct SealedPayload {
+    /// The header flags describing which transformations were applied.
+    flags: u16,
+    bytes: Vec<u8>,
+    /// The error correction added to the bytes when they are hidden.
+    redundancy: Redundancy,
+}
+
+impl SealedPayload {
+    /// The number of bytes that will follow the header, before any error correction is added.
+    pub fn size(&self) -> u64 {
+        self.bytes.len() as u64
+    }
+
+    /// Serializes the payload preceded by its header, ready to be hidden by the given algorithm
+    /// at the given depth.
+    pub fn with_header(&self, algorithm: Algorithm, depth: u8) -> Vec<u8> {
+        self.with_carrier_flags(algorithm, depth, 0)
+    }
+
+    /// Serializes the payload preceded by its header, like `with_header`, with extra header flags
+    /// describing where the carrier hid it.
+    pub fn with_carrier_flags(
+        &self,
+        algorithm: Algorithm,
+        depth: u8,
+        carrier_flags: u16,
+    ) -> Vec<u8> {
+        let encoded = error_correction::encode(self.redundancy, &self.bytes);
+        let mut header = Header::new(algorithm, depth, encoded.len() as u64);
+        header.flags = self.flags | self.redundancy.flag() | carrier_flags;
+
+        let mut bytes = header.to_stored_bytes();
+        bytes.extend(encoded);
+
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        header::{Algorithm, Header},
+        payload::{Payload, SecretProperties, CHECKSUM_BYTES},
+        Compression, CorruptionType, DecodeOptions, EncodeOptions, ErrorType,
+    };
+
+    /// Seals the payload and reads it back the way a carrier would.
+    fn round_trip_with(
+        payload: &Payload,
+        encode_options: &EncodeOptions,
+        decode_options: &DecodeOptions,
+    ) -> Result<Payload, ErrorType> {
+        let bytes = payload
+            .seal(encode_options)
+            .with_header(Algorithm::LeastSignificantBits, 2);
+        let mut bytes = bytes.into_iter();
+        let header = Header::from_bytes(&mut bytes)?;
+
+        Payload::unseal(&header, bytes.collect(), decode_options)
+    }
+
+    #[test]
+    fn round_trip() {
+        let payload = Payload::new("documents/report.pdf", b"%PDF-1.7 rest".to_vec());
+        let decoded = round_trip_with(
+            &payload,
+            &EncodeOptions::default(),
+            &DecodeOptions::default(),
+        );
+
+        assert_eq!(decoded.unwrap(), payload);
+    }
+
+    #[test]
+    fn encrypted_round_trip() {
+        let payload = Payload::new("documents/report.pdf", b"%PDF-1.7 rest".to_vec());
+        let encode_options = EncodeOptions {
+            password: Some("hunter2".to_owned()),
+            ..Default::default()
+        };
+        let decode_options = |password: Option<&str>| DecodeOptions {
+            password: password.map(str::to_owned),
+            ..Default::default()
+        };
+
+        let decoded = round_trip_with(&payload, &encode_options, &decode_options(Some("hunter2")));
+        assert_eq!(decoded.unwrap(), payload);
+
+        let decoded = round_trip_with(&payload, &encode_options, &decode_options(Some("hunter3")));
+        assert!(matches!(decoded, Err(ErrorType::WrongPassword)));
+
+        let decoded = round_trip_with(&payload, &encode_options, &decode_options(None));
+        assert!(matches!(decoded, Err(ErrorType::PasswordRequired)));
+    }
+
+    #[test]
+    fn encryption_authenticates_flags() {
+        let payload = Payload::new("story.txt", b"Once upon a time".to_vec());
+        let options = EncodeOptions {
+            password: Some("hunter2".to_owned()),
+            ..Default::default()
+        };
+        let bytes = payload
+            .seal(&options)
+            .with_header(Algorithm::LeastSignificantBits, 2);
+        let mut bytes = bytes.into_iter();
+        let mut header = Header::from_bytes(&mut bytes).unwrap();
+        header.flags ^= Header::ARCHIVE;
+
+        let decode_options = DecodeOptions {
+            password: Some("hunter2".to_owned()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Payload::unseal(&header, bytes.collect(), &decode_options),
+            Err(ErrorType::WrongPassword)
+        ));
+    }
+
+    #[test]
+    fn compressed_round_trip() {
+        let payload = Payload::new("story.txt", "Once upon a time. ".repeat(200).into_bytes());
+        let decode_options = DecodeOptions {
+            password: Some("hunter2".to_owned()),
+            ..Default::default()
+        };
+
+        for compression in [Compression::Deflate, Compression::Zstd] {
+            let encode_options = EncodeOptions {
+                password: Some("hunter2".to_owned()),
+                compression,
+                ..Default::default()
+            };
+            let sealed_size = payload.seal(&encode_options).size();
+            assert!(sealed_size < payload.contents.len() as u64 / 10);
+            assert_eq!(payload.sealed_size(&encode_options), sealed_size);
+
+            let decoded = round_trip_with(&payload, &encode_options, &decode_options);
+            assert_eq!(decoded.unwrap(), payload);
+        }
+    }
+
+    #[test]
+    fn incompressible_payload_is_stored_as_it_is() {
+        let payload = Payload::new("noise.bin", (0..=255).collect());
+        let encode_options = EncodeOptions {
+            compression: Compression::Zstd,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            payload.seal(&encode_options).size(),
+            payload.seal(&EncodeOptions::default()).size()
+        );
+        let decoded = round_trip_with(&payload, &encode_options, &DecodeOptions::default());
+        assert_eq!(decoded.unwrap(), payload);
+    }
+
+    #[test]
+    fn detects_damaged_payload() {
+        let payload = Payload::new("story.txt", b"It was a dark and stormy night".to_vec());
+        let mut bytes = payload
+            .seal(&EncodeOptions::default())
+            .with_header(Algorithm::LeastSignificantBits, 2);
+        // Changing the case of the last letter of the contents, which come before the checksum
+        let last_letter = bytes.len() - CHECKSUM_BYTES - 1;
+        bytes[last_letter] ^= 0x20;
+
+        let mut bytes = bytes.into_iter();
+        let header = Header::from_bytes(&mut bytes).unwrap();
+        let bytes: Vec<u8> = bytes.collect();
+        let decoded = Payload::unseal(&header, bytes.clone(), &DecodeOptions::default());
+        assert!(matches!(
+            decoded,
+            Err(ErrorType::CorruptedFile(CorruptionType::ChecksumMismatch))
+        ));
+
+        let (damaged, intact) =
+            Payload::unseal_damaged(&header, bytes, &DecodeOptions::default()).unwrap();
+        assert!(!intact);
+        assert_eq!(damaged.contents, b"It was a dark and stormy nighT");
+    }
+
+    #[test]
+    fn keeps_what_decompresses_of_damaged_payload() {
+        // Long enough that zstd splits it into several blocks, which are decompressed one at a
+        // time
+        let story: String = (0..20_000).map(|line| format!("Line {line}.\n")).collect();
+        let payload = Payload::new("story.txt", story.clone().into_bytes());
+
+        for compression in [Compression::Deflate, Compression::Zstd] {
+            let options = EncodeOptions {
+                compression,
+                ..Default::default()
+            };
+            let mut bytes = payload
+                .seal(&options)
+                .with_header(Algorithm::LeastSignificantBits, 2);
+            // Damaging the end of the compressed bytes, so the start still decompresses
+            let near_end = bytes.len() - 4;
+            bytes[near_end] ^= 0xFF;
+
+            let mut bytes = bytes.into_iter();
+            let header = Header::from_bytes(&mut bytes).unwrap();
+            let bytes: Vec<u8> = bytes.collect();
+            let decoded = Payload::unseal(&header, bytes.clone(), &DecodeOptions::default());
+            assert!(matches!(
+                decoded,
+                Err(ErrorType::CorruptedFile(CorruptionType::ChecksumMismatch))
+            ));
+
+            let (damaged, intact) =
+                Payload::unseal_damaged(&header, bytes, &DecodeOptions::default()).unwrap();
+            assert!(!intact);
+            assert_eq!(damaged.properties.file_name.as_deref(), Some("story.txt"));
+            assert!(story.as_bytes().starts_with(&damaged.contents[..100]));
+        }
+    }
+
+    #[test]
+    fn describes_secret_file() {
+        let properties = SecretProperties::describe("archive.bin", b"PK\x03\x04 zipped");
+
+        assert_eq!(properties.file_name.as_deref(), Some("archive.bin"));
+        assert_eq!(properties.extension.as_deref(), Some("bin"));
+        assert_eq!(properties.content_type, "application/zip");
+
+        let properties = SecretProperties::describe("notes", b"plain text");
+
+        assert_eq!(properties.extension, None);
+        assert_eq!(properties.content_type, "text/plain");
+        assert_eq!(properties.suggested_file_name(), "notes");
+    }
+
+    #[test]
+    fn suggested_file_name_stays_in_directory() {
+        let properties = SecretProperties {
+            file_name: Some("../../etc/passwd".to_owned()),
+            extension: None,
+            content_type: "text/plain".to_owned(),
+            archive: false,
+        };
+
+        assert_eq!(properties.suggested_file_name(), "passwd");
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crate::ErrorType;
+
+/// How many times progress is reported over the course of a whole payload.
+const REPORTS_PER_PAYLOAD: u64 = 100;
+
+/// Lets an encode or decode running on one thread be stopped from another.
+///
+/// Clones share the same state, so cancelling any of them cancels them all.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Constructs a token that hasn't been cancelled.
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Asks every job using this token to stop as soon as it can.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true once the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Called with the number of bytes of the payload processed so far, and the total number of
+/// bytes in the payload.
+type Observer = dyn Fn(u64, u64) + Send + Sync;
+
+/// Reports how far through an encode or decode is, and stops it if it's cancelled.
+///
+/// The default reports to nobody and is never cancelled.
+#[derive(Clone, Default)]
+pub struct Progress {
+    observer: Option<Arc<Observer>>,
+    cancellation: CancellationToken,
+}
+
+impl Progress {
+    /// Constructs a progress that reports to nobody and is never cancelled.
+    pub fn new() -> Progress {
+        Progress::default()
+    }
+
+    /// Reports progress to the observer, which is called with the bytes processed and the total.
+    ///
+    /// The observer is called from the thread doing the work, around a hundred times per payload.
+    pub fn with_observer(
+        mut self,
+        observer: impl Fn(u64, u64) + Send + Sync + 'static,
+    ) -> Progress {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// Stops the work once the token is cancelled.
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Progress {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// Returns a Cancelled error if the work has been cancelled.
+    pub fn check(&self) -> Result<(), ErrorType> {
+        if self.cancellation.is_cancelled() {
+            log::info!("Stopping as the job was cancelled");
+            Err(ErrorType::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Wraps the chunks of a payload that is `total` bytes long, each holding `bits` bits of it.
+    ///
+    /// Progress is reported as the chunks are taken. The chunks end early if the work is
+    /// cancelled, so `check` should be called once they have been used up.
+    pub fn track<I: Iterator>(&self, chunks: I, bits: u8, total: u64) -> Tracked<'_, I> {
+        Tracked {
+            chunks,
+            progress: self,
+            bits: bits as u64,
+            taken: 0,
+            total,
+            next_report: 0,
+        }
+    }
+
+    fn report(&self, processed: u64, total: u64) {
+        if let Some(observer) = &self.observer {
+            observer(processed, total);
+        }
+    }
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Progress")
+            .field("observed", &self.observer.is_some())
+            .field("cancellation", &self.cancellation)
+            .finish()
+    }
+}
+
+/// The chunks of a payload, reporting progress as they're taken. Made with `Progress::track`.
+pub struct Tracked<'a, I> {
+    chunks: I,
+    progress: &'a Progress,
+    bits: u64,
+    taken: u64,
+    total: u64,
+    /// The number of processed bytes at which progress is next reported.
+    next_report: u64,
+}
+
+impl<I: Iterator> Iterator for Tracked<'_, I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        if self.progress.cancellation.is_cancelled() {
+            return None;
+        }
+
+        // The last chunk may be padded, so the bytes processed are capped at the total
+        let processed = (self.taken * self.bits / 8).min(self.total);
+        if processed >= self.next_report {
+            self.progress.report(processed, self.total);
+
+            let step = (self.total / REPORTS_PER_PAYLOAD).max(1);
+            self.next_report = if processed == self.total {
+                u64::MAX
+            } else {
+                (processed / step + 1).saturating_mul(step).min(self.total)
+            };
+        }
+
+        let chunk = self.chunks.next();
+        if chunk.is_some() {
+            self.taken += 1;
+        } else if self.next_report != u64::MAX {
+            // Any bytes processed since the last report are reported as the chunks run out
+            self.progress.report(processed, self.total);
+            self.next_report = u64::MAX;
+        }
+
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{
+        progress::{CancellationToken, Progress},
+        split_bits, ErrorType,
+    };
+
+    #[test]
+    fn reports_up_to_the_total() {
+        let reports = Arc::new(Mutex::new(vec![]));
+        let observed = reports.clone();
+        let progress = Progress::new().with_observer(move |processed, total| {
+            observed.lock().unwrap().push((processed, total));
+        });
+
+        let payload = vec![0xA5; 1000];
+        let chunks = progress.track(split_bits(&payload, 3), 3, 1000).count();
+        assert_eq!(chunks, split_bits(&payload, 3).count());
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.first(), Some(&(0, 1000)));
+        assert_eq!(reports.last(), Some(&(1000, 1000)));
+        assert!(reports.len() <= 102);
+        assert!(reports.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn stops_once_cancelled() {
+        let cancellation = CancellationToken::new();
+        let progress = Progress::new().with_cancellation(cancellation.clone());
+
+        let payload = vec![0; 100];
+        let mut chunks = progress.track(split_bits(&payload, 1), 1, 100);
+        assert!(chunks.next().is_some());
+        assert!(progress.check().is_ok());
+
+        cancellation.cancel();
+        assert!(chunks.next().is_none());
+        assert!(matches!(progress.check(), Err(ErrorType::Cancelled)));
+    }
+}
@@ -0,0 +1,226 @@
+use std::ops::Range;
+
+use argon2::Argon2;
+use itertools::Either;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// The number of rounds used by the Feistel network that shuffles slots.
+const ROUNDS: usize = 8;
+
+/// How many slots `iter_blocks` finds at a time.
+const BLOCK_SLOTS: u64 = 1 << 16;
+
+/// Salt used when deriving round keys. The key is secret, so a fixed salt is enough to make the
+/// derived keys specific to this use.
+const SALT: &[u8] = b"stegosaurusography slot order";
+
+/// The order in which the slots of a carrier (for example each channel of each pixel) are used
+/// to store a payload.
+///
+/// Without a key, slots are used sequentially. With a key, the slots are shuffled by a keyed
+/// permutation so that the payload is scattered across the entire carrier, and the same key is
+/// needed to find it again.
+#[derive(Debug, Clone)]
+pub struct SlotOrder {
+    round_keys: Option<[u64; ROUNDS]>,
+}
+
+impl SlotOrder {
+    /// Slots are used in order, starting from the first one.
+    pub fn sequential() -> SlotOrder {
+        SlotOrder { round_keys: None }
+    }
+
+    /// Constructs the slot order for an optional key.
+    pub fn new(key: Option<&str>) -> SlotOrder {
+        match key {
+            Some(key) => SlotOrder::keyed(key),
+            None => SlotOrder::sequential(),
+        }
+    }
+
+    /// Slots are shuffled by a permutation derived from the key.
+    pub fn keyed(key: &str) -> SlotOrder {
+        let mut derived = [0; ROUNDS * 8];
+        Argon2::default()
+            .hash_password_into(key.as_bytes(), SALT, &mut derived)
+            .expect("The default Argon2 parameters accept any key and our salt");
+        log::trace!("Derived the slot order from the key");
+
+        let mut round_keys = [0; ROUNDS];
+        for (round_key, bytes) in round_keys.iter_mut().zip(derived.chunks_exact(8)) {
+            *round_key = u64::from_be_bytes(bytes.try_into().unwrap());
+        }
+
+        SlotOrder {
+            round_keys: Some(round_keys),
+        }
+    }
+
+    /// Iterates over every slot index below `slot_count` exactly once, in this order.
+    pub fn iter(&self, slot_count: u64) -> impl Iterator<Item = u64> + '_ {
+        match &self.round_keys {
+            None => Either::Left(0..slot_count),
+            Some(round_keys) => {
+                let permutation = Permutation::new(round_keys, slot_count);
+                Either::Right((0..slot_count).map(move |index| permutation.apply(index)))
+            }
+        }
+    }
+
+    /// Finds the slots at the given positions in this order, out of `slot_count` slots.
+    ///
+    /// With the parallel feature, keyed slots are found using every core.
+    pub fn slots(&self, slot_count: u64, positions: Range<u64>) -> Vec<u64> {
+        let Some(round_keys) = &self.round_keys else {
+            return positions.collect();
+        };
+
+        let permutation = Permutation::new(round_keys, slot_count);
+        #[cfg(feature = "parallel")]
+        let positions = positions.into_par_iter();
+
+        positions.map(|index| permutation.apply(index)).collect()
+    }
+
+    /// Iterates over the same slots as `iter`, finding them a block at a time with `slots`.
+    ///
+    /// Shuffling a slot takes far longer than using it, so this is much faster for large
+    /// carriers with a key. Less than a block is wasted if the iterator isn't used up.
+    pub fn iter_blocks(&self, slot_count: u64) -> impl Iterator<Item = u64> + '_ {
+        (0..slot_count)
+            .step_by(BLOCK_SLOTS as usize)
+            .flat_map(move |start| {
+                self.slots(slot_count, start..slot_count.min(start + BLOCK_SLOTS))
+            })
+    }
+}
+
+/// A keyed bijection over the integers `0..size`.
+///
+/// Built from an unbalanced Feistel network over the smallest number of bits that can hold
+/// `size`, using cycle walking to stay inside of the domain. As the domain is less than twice
+/// `size`, each index takes fewer than two rounds of the network on average.
+struct Permutation<'a> {
+    size: u64,
+    /// The number of low bits that each round mixes into the high bits.
+    low_bits: u32,
+    /// The number of high bits, which are moved to the bottom after each round.
+    high_bits: u32,
+    round_keys: &'a [u64; ROUNDS],
+}
+
+impl<'a> Permutation<'a> {
+    fn new(round_keys: &'a [u64; ROUNDS], size: u64) -> Permutation<'a> {
+        let bits = u64::BITS - size.saturating_sub(1).leading_zeros();
+        Permutation {
+            size,
+            low_bits: bits / 2,
+            high_bits: bits - bits / 2,
+            round_keys,
+        }
+    }
+
+    /// Finds where the index is moved to by the permutation.
+    fn apply(&self, index: u64) -> u64 {
+        // Every value inside of the domain is reached by walking along its cycle, as the
+        // Feistel network is itself a permutation over the larger power of two
+        let mut value = self.feistel(index);
+        while value >= self.size {
+            value = self.feistel(value);
+        }
+
+        value
+    }
+
+    /// Each round mixes the low bits into the high bits, then swaps them around, which can be
+    /// undone whether or not the two parts are the same size.
+    fn feistel(&self, value: u64) -> u64 {
+        let low_mask = (1 << self.low_bits) - 1;
+        let high_mask = (1 << self.high_bits) - 1;
+        let mut value = value;
+
+        for round_key in self.round_keys {
+            let high = value >> self.low_bits;
+            let low = value & low_mask;
+            value = (low << self.high_bits) | (high ^ (mix(low ^ round_key) & high_mask));
+        }
+
+        value
+    }
+}
+
+/// The SplitMix64 finalizer, used as the Feistel round function.
+fn mix(value: u64) -> u64 {
+    let mut value = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    value ^ (value >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::slot_order::{Permutation, SlotOrder};
+
+    #[test]
+    fn keyed_order_is_a_permutation() {
+        let order = SlotOrder::keyed("key");
+        for slot_count in [1, 2, 7, 1000, 4096, 4097] {
+            let mut slots: Vec<u64> = order.iter(slot_count).collect();
+            slots.sort_unstable();
+
+            assert_eq!(slots, (0..slot_count).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn cycle_walking_stays_inside_twice_the_size() {
+        let order = SlotOrder::keyed("key");
+        let round_keys = order.round_keys.as_ref().unwrap();
+        // Just past a power of two, where the domain is furthest from the size
+        for size in [3, 1025, 4097, 8193] {
+            let permutation = Permutation::new(round_keys, size);
+            let walked: u64 = (0..size)
+                .map(|index| {
+                    let mut value = permutation.feistel(index);
+                    let mut steps = 1;
+                    while value >= size {
+                        value = permutation.feistel(value);
+                        steps += 1;
+                    }
+                    steps
+                })
+                .sum();
+
+            assert!(walked < 2 * size, "Walked {walked} steps for {size} slots");
+        }
+    }
+
+    #[test]
+    fn keys_change_the_order() {
+        let first: Vec<u64> = SlotOrder::keyed("key").iter(1000).collect();
+        let second: Vec<u64> = SlotOrder::keyed("other key").iter(1000).collect();
+        let sequential: Vec<u64> = SlotOrder::sequential().iter(1000).collect();
+
+        assert_ne!(first, second);
+        assert_ne!(first, sequential);
+        assert_eq!(sequential, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn blocks_match_the_order() {
+        let slot_count = 200_000;
+        for order in [SlotOrder::sequential(), SlotOrder::keyed("key")] {
+            assert!(order.iter(slot_count).eq(order.iter_blocks(slot_count)));
+            assert_eq!(
+                order.slots(slot_count, 70_000..70_010),
+                order
+                    .iter(slot_count)
+                    .skip(70_000)
+                    .take(10)
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+}
@@ -2,42 +2,199 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod requests {
-    use std::fs::File;
-    use stegosaurusography::{
-        get_properties, secret_context, Decoder, Encoder, FileProperties, Result,
+    use std::{fs::File, path::PathBuf, sync::Mutex};
+    use stegosaurusography_core::{
+        get_properties, inspect, list_entries, secret_context, secret_size, AlphaMode,
+        ArchiveEntry, CancellationToken, Compression, DecodeOptions, Decoder, EncodeOptions,
+        Encoder, FileProperties, Progress, Redundancy, Result, SecretProperties,
+        DEFAULT_BITS_PER_CHANNEL,
     };
+    use tauri::{AppHandle, Emitter, State};
 
-    /// Used to do the encoding of the secret file into the base file. The results will be
+    /// The payload of the `encode-progress` and `decode-progress` events.
+    #[derive(Clone, serde::Serialize)]
+    struct JobProgress {
+        /// How many bytes of the payload have been hidden or read so far.
+        processed: u64,
+        /// How many bytes are in the whole payload.
+        total: u64,
+    }
+
+    /// Keeps track of the encode or decode that the cancel command will stop.
+    #[derive(Default)]
+    pub struct RunningJob(Mutex<Option<CancellationToken>>);
+
+    impl RunningJob {
+        /// Starts tracking a new job, returning a progress that emits the named event as it goes.
+        fn start(&self, app: AppHandle, event: &'static str) -> Progress {
+            let cancellation = CancellationToken::new();
+            *self
+                .0
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(cancellation.clone());
+
+            Progress::new()
+                .with_cancellation(cancellation)
+                .with_observer(move |processed, total| {
+                    if let Err(err) = app.emit(event, JobProgress { processed, total }) {
+                        log::warn!("Couldn't emit {event}: {err}");
+                    }
+                })
+        }
+    }
+
+    /// Used to do the encoding of the secret files into the base file. The results will be
     /// stored in the output file.
+    ///
+    /// A single secret file is hidden as it is. Several secret files, or any directories, are
+    /// hidden together as an archive, which is unpacked into a directory when decoded.
+    ///
+    /// If a password is given, the secret file is encrypted with it before being encoded. If a key
+    /// is given, the secret file is scattered across the base file using it. The number of bits
+    /// per channel defaults to DEFAULT_BITS_PER_CHANNEL. The base file's metadata is copied into
+    /// the output unless strip_metadata is true. Images with an alpha channel only have their
+    /// colour channels used unless another alpha mode is given. The secret file is compressed
+    /// first if a compression is given and it makes the secret file smaller, and error correction
+    /// is stored with it if a redundancy is given.
+    ///
+    /// Emits `encode-progress` events as the secret file is hidden. If the job is cancelled, the
+    /// output file is left as it was.
     #[tauri::command]
-    pub async fn encode(base_file: &str, secret_file: &str, output_file: &str) -> Result<()> {
+    pub async fn encode(
+        app: AppHandle,
+        job: State<'_, RunningJob>,
+        base_file: &str,
+        secret_files: Vec<String>,
+        output_file: &str,
+        password: Option<String>,
+        key: Option<String>,
+        bits_per_channel: Option<u8>,
+        strip_metadata: Option<bool>,
+        alpha: Option<AlphaMode>,
+        compression: Option<Compression>,
+        redundancy: Option<Redundancy>,
+    ) -> Result<()> {
         log::info!("Encoding request received!");
-        log::trace!("Encode Request > base_file={base_file}, secret_file={secret_file}, output_file={output_file}");
+        log::trace!("Encode Request > base_file={base_file}, secret_files={secret_files:?}, output_file={output_file}, encrypted={}, keyed={}, bits_per_channel={bits_per_channel:?}, strip_metadata={strip_metadata:?}, alpha={alpha:?}, compression={compression:?}, redundancy={redundancy:?}", password.is_some(), key.is_some());
 
-        Encoder::new(base_file, secret_file, output_file)
-            .and_then(|mut encoder| encoder.encode())
-            .map(|_| log::info!("Completed the encoding request!"))
+        let options = EncodeOptions {
+            password,
+            key,
+            bits_per_channel: bits_per_channel.unwrap_or(DEFAULT_BITS_PER_CHANNEL),
+            strip_metadata: strip_metadata.unwrap_or_default(),
+            alpha: alpha.unwrap_or_default(),
+            compression: compression.unwrap_or_default(),
+            redundancy: redundancy.unwrap_or_default(),
+        };
+        let mut encoder = Encoder::with_secrets(base_file, secret_files, output_file, options)?;
+        encoder.set_progress(job.start(app, "encode-progress"));
+        encoder.encode()?;
+
+        log::info!("Completed the encoding request!");
+        Ok(())
     }
 
     /// Used to do the decoding of the encoded file. The results will be stored in the output file.
+    ///
+    /// The output file may be a directory, in which case the secret file's original name is used.
+    /// Several secret files hidden together are unpacked into the output file as a directory.
+    /// Returns the path that the secret file was written to.
+    ///
+    /// Emits `decode-progress` events as the secret file is read. If the job is cancelled, the
+    /// output file is left as it was. If the secret file was damaged, a ChecksumMismatch error is
+    /// returned, and the damaged secret file is only written if best_effort is true.
     #[tauri::command]
-    pub async fn decode(encoded_file: &str, output_file: &str) -> Result<()> {
+    pub async fn decode(
+        app: AppHandle,
+        job: State<'_, RunningJob>,
+        encoded_file: &str,
+        output_file: &str,
+        password: Option<String>,
+        key: Option<String>,
+        best_effort: Option<bool>,
+    ) -> Result<PathBuf> {
         log::info!("Decoding request received!");
-        log::trace!("Decode Request > encoded_file={encoded_file}, output_file={output_file}");
+        log::trace!("Decode Request > encoded_file={encoded_file}, output_file={output_file}, password_given={}, key_given={}, best_effort={best_effort:?}", password.is_some(), key.is_some());
 
-        Decoder::new(encoded_file, output_file)
-            .and_then(|mut decoder| decoder.decode())
-            .map(|_| log::info!("Completed the decoding request!"))
+        let options = DecodeOptions {
+            password,
+            key,
+            best_effort: best_effort.unwrap_or_default(),
+        };
+        let mut decoder = Decoder::with_options(encoded_file, output_file, options)?;
+        decoder.set_progress(job.start(app, "decode-progress"));
+        decoder.decode()?;
+
+        log::info!("Completed the decoding request!");
+        Ok(decoder.output_file_path().to_path_buf())
+    }
+
+    /// Used to get the properties of the secret file hidden in an encoded file. For example, its
+    /// original name and what type of file it is.
+    #[tauri::command]
+    pub async fn encoded_file_properties(
+        encoded_file: &str,
+        password: Option<String>,
+        key: Option<String>,
+    ) -> Result<SecretProperties> {
+        log::info!("Encoded file property request received!");
+        log::trace!("Encoded File Property Request > encoded_file={encoded_file}");
+
+        let options = DecodeOptions {
+            password,
+            key,
+            ..Default::default()
+        };
+        inspect(encoded_file, &options).map(|props| {
+            log::info!("Secret file properties are {props:?}");
+            props
+        })
+    }
+
+    /// Used to list the files and directories hidden in an encoded file, without writing them
+    /// anywhere. A single secret file is listed under the name it would be decoded to.
+    #[tauri::command]
+    pub async fn encoded_file_entries(
+        encoded_file: &str,
+        password: Option<String>,
+        key: Option<String>,
+    ) -> Result<Vec<ArchiveEntry>> {
+        log::info!("Encoded file entries request received!");
+        log::trace!("Encoded File Entries Request > encoded_file={encoded_file}");
+
+        let options = DecodeOptions {
+            password,
+            key,
+            ..Default::default()
+        };
+        list_entries(encoded_file, &options).map(|entries| {
+            log::info!("Encoded file holds {} entries", entries.len());
+            entries
+        })
     }
 
     /// Used to get the properties of the base file. For example, how much data can be stored
     /// secretly, as well as double checking that the file type is supported.
+    ///
+    /// The available space depends on the number of bits per channel, the alpha mode and the
+    /// redundancy that will be used to encode.
     #[tauri::command]
-    pub async fn base_file_properties(base_file: &str) -> Result<FileProperties> {
+    pub async fn base_file_properties(
+        base_file: &str,
+        bits_per_channel: Option<u8>,
+        alpha: Option<AlphaMode>,
+        redundancy: Option<Redundancy>,
+    ) -> Result<FileProperties> {
         log::info!("File property request received!");
-        log::trace!("File Property Request > base_file={base_file}");
+        log::trace!("File Property Request > base_file={base_file}, bits_per_channel={bits_per_channel:?}, alpha={alpha:?}, redundancy={redundancy:?}");
 
-        get_properties(base_file).map(|props| {
+        let options = EncodeOptions {
+            bits_per_channel: bits_per_channel.unwrap_or(DEFAULT_BITS_PER_CHANNEL),
+            alpha: alpha.unwrap_or_default(),
+            redundancy: redundancy.unwrap_or_default(),
+            ..Default::default()
+        };
+        get_properties(base_file, &options).map(|props| {
             log::info!("File properties are {props:?}");
             props
         })
@@ -59,6 +216,46 @@ pub async fn file_size(file: &str) -> Result<u64> {
         })
     }
 
+    /// Gets the amount of a base file's space the secret files will take up once encoded. For
+    /// example, less than their size if they're compressed, or more if they're encrypted.
+    /// Several files or a directory are measured as the archive they would be hidden as.
+    ///
+    /// Useful when paired with base_file_properties, to see whether the secret files will fit.
+    #[tauri::command]
+    pub async fn secret_file_size(
+        secret_files: Vec<String>,
+        password: Option<String>,
+        compression: Option<Compression>,
+    ) -> Result<u64> {
+        log::info!("Secret file size request received!");
+        log::trace!("Secret File Size Request > secret_files={secret_files:?}, encrypted={}, compression={compression:?}", password.is_some());
+
+        let options = EncodeOptions {
+            password,
+            compression: compression.unwrap_or_default(),
+            ..Default::default()
+        };
+        secret_size(&secret_files, &options).map(|secret_size| {
+            log::info!("Secret file size is {secret_size}");
+            secret_size
+        })
+    }
+
+    /// Used to cancel the encode or decode that was most recently started. The job stops with a
+    /// Cancelled error, and nothing is written to its output file.
+    #[tauri::command]
+    pub fn cancel(job: State<'_, RunningJob>) {
+        log::info!("Cancel request received!");
+
+        let running = job
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(cancellation) = running.as_ref() {
+            cancellation.cancel();
+        }
+    }
+
     // TODO: Add a command to allow the front-end to send messages to be logged for user interactions
 }
 
@@ -71,11 +268,16 @@ fn main() {
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_dialog::init())
+        .manage(requests::RunningJob::default())
         .invoke_handler(tauri::generate_handler![
             requests::encode,
             requests::decode,
             requests::base_file_properties,
-            requests::file_size
+            requests::encoded_file_properties,
+            requests::encoded_file_entries,
+            requests::file_size,
+            requests::secret_file_size,
+            requests::cancel
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");